@@ -1,20 +1,50 @@
 package sushi.hardcore.aira
 
 import sushi.hardcore.aira.background_service.Contact
+import sushi.hardcore.aira.background_service.ContactSettings
+import sushi.hardcore.aira.background_service.DiscoveredPeer
+import sushi.hardcore.aira.background_service.Group
+import sushi.hardcore.aira.background_service.PreviousKey
 
 object AIRADatabase {
+    const val PADDING_POLICY_NONE = 0
+
     external fun initLogging(): Boolean
     external fun isIdentityProtected(databaseFolder: String): Boolean
-    external fun getIdentityName(databaseFolder: String): String?
+    external fun identityExists(databaseFolder: String): Boolean
+    external fun getLoginLabel(databaseFolder: String): String?
+    external fun setLoginLabel(databaseFolder: String, loginLabel: String?): Boolean
+    external fun getIdentityName(): String
     external fun loadIdentity(databaseFolder: String, password: ByteArray?): Boolean
     external fun addContact(name: String, avatarUuid: String?, publicKey: ByteArray): Contact?
     external fun removeContact(uuid: String): Boolean
     external fun loadContacts(): ArrayList<Contact>?
     external fun setVerified(uuid: String): Boolean
+    external fun setUnverified(uuid: String): Boolean
+    external fun changeContactKey(contactUuid: String, newPublicKey: ByteArray, timestamp: Long): Boolean
+    external fun loadKeyHistory(contactUuid: String): ArrayList<PreviousKey>?
     external fun setContactSeen(contactUuid: String, seen: Boolean): Boolean
+    external fun blockPeer(publicKey: ByteArray): Boolean
+    external fun unblockPeer(publicKey: ByteArray): Boolean
+    external fun isPeerBlocked(publicKey: ByteArray): Boolean
+    external fun loadBlockedPeers(): ArrayList<ByteArray>?
+    external fun runDiscoveryResponder(discoveryInterface: String, port: Int): Boolean
+    external fun stopDiscoveryResponder()
+    external fun discoverPeers(discoveryInterface: String, timeout: Int): ArrayList<DiscoveredPeer>?
+    external fun getContactSettings(contactUuid: String): ContactSettings?
+    external fun setContactSettings(contactUuid: String, padding: Int, notifications: Int, autoAcceptSize: Long, retention: Long): Boolean
+    external fun deleteExpiredMsgs(contactUuid: String, now: Long): Boolean
     external fun changeContactName(contactUuid: String, newName: String): Boolean
     external fun setContactAvatar(contactUuid: String, avatarUuid: String?): Boolean
     external fun storeMsg(contactUuid: String, outgoing: Boolean, timestamp: Long, data: ByteArray): Boolean
+    external fun storeMsgs(contactUuid: String, msgs: List<ChatItem>): LongArray?
+    external fun addGroup(groupUuid: String?, name: String, members: Array<String>): Group?
+    external fun loadGroups(): ArrayList<Group>?
+    external fun setGroupMembers(groupUuid: String, members: Array<String>): Boolean
+    external fun changeGroupName(groupUuid: String, newName: String): Boolean
+    external fun removeGroup(groupUuid: String): Boolean
+    external fun storeGroupMsg(groupUuid: String, senderUuid: String?, timestamp: Long, data: ByteArray): Boolean
+    external fun loadGroupMsgs(groupUuid: String, offset: Int, count: Int): ArrayList<GroupChatItem>?
     external fun storeFile(contactUuid: String?, data: ByteArray): ByteArray?
     external fun loadMsgs(uuid: String, offset: Int, count: Int): ArrayList<ChatItem>?
     external fun loadFile(rawUuid: ByteArray): ByteArray?
@@ -22,11 +52,24 @@ object AIRADatabase {
     external fun clearCache()
     external fun getIdentityPublicKey(): ByteArray
     external fun getIdentityFingerprint(): String
-    external fun getUsePadding(): Boolean
-    external fun setUsePadding(usePadding: Boolean): Boolean
+    external fun getSafetyNumber(peerPublicKey: ByteArray): String
+    external fun getIdentityQrPayload(): String
+    external fun verifyContactQrPayload(contactUuid: String, payload: String): Boolean
+    external fun getPaddingPolicy(): Int
+    external fun setPaddingPolicy(paddingPolicy: Int): Boolean
+    external fun getCoverTrafficRate(): Int
+    external fun setCoverTrafficRate(rate: Int): Boolean
+    external fun getEncryptDatabase(): Boolean
+    external fun setEncryptDatabase(encryptDatabase: Boolean): Boolean
     external fun storeAvatar(avatar: ByteArray): String?
     external fun getAvatar(avatarUuid: String): ByteArray?
     external fun changeName(newName: String): Boolean
+    external fun rotateKeypair(): Boolean
+    external fun getKeyRotations(): ByteArray?
+    external fun findRotatedContact(keyRotations: ByteArray, peerPublicKey: ByteArray): String?
+    external fun destroyIdentity(databaseFolder: String): Boolean
+    external fun setDuressPassword(duressPassword: ByteArray, decoyName: String?): Boolean
+    external fun removeDuressPassword(): Boolean
     external fun changePassword(databaseFolder: String, oldPassword: ByteArray?, newPassword: ByteArray?): Boolean
     external fun setIdentityAvatar(databaseFolder: String, avatar: ByteArray): Boolean
     external fun removeIdentityAvatar(databaseFolder: String): Boolean
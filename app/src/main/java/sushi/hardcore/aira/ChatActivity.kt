@@ -2,6 +2,7 @@ package sushi.hardcore.aira
 
 import android.content.ComponentName
 import android.content.Context
+import android.content.Intent
 import android.content.ServiceConnection
 import android.os.Bundle
 import android.os.IBinder
@@ -18,6 +19,7 @@ import sushi.hardcore.aira.adapters.ChatAdapter
 import sushi.hardcore.aira.adapters.FuckRecyclerView
 import sushi.hardcore.aira.background_service.*
 import sushi.hardcore.aira.databinding.ActivityChatBinding
+import sushi.hardcore.aira.databinding.DialogContactSettingsBinding
 import sushi.hardcore.aira.databinding.DialogFingerprintsBinding
 import sushi.hardcore.aira.databinding.DialogInfoBinding
 import sushi.hardcore.aira.utils.FileUtils
@@ -137,6 +139,16 @@ class ChatActivity : ServiceBoundActivity() {
                 false
             }
         }
+        override fun onNewGroupMessage(groupUuid: String, sessionId: Int, timestamp: Long, data: ByteArray) {}
+        override fun onContactKeyChanged(sessionId: Int) {
+            if (this@ChatActivity.sessionId == sessionId) {
+                runOnUiThread {
+                    Toast.makeText(this@ChatActivity, getString(R.string.contact_key_changed, sessionName), Toast.LENGTH_LONG).show()
+                    invalidateOptionsMenu()
+                    displayIconTrustLevel(true, false)
+                }
+            }
+        }
     }
     
     override fun onCreate(savedInstanceState: Bundle?) {
@@ -326,9 +338,14 @@ class ChatActivity : ServiceBoundActivity() {
         menu.findItem(R.id.remove_contact).isVisible = contact != null
         if (contact == null) {
             menu.findItem(R.id.verify).isVisible = false
+            menu.findItem(R.id.unverify).isVisible = false
         } else {
             menu.findItem(R.id.verify).isVisible = !contact.verified
+            menu.findItem(R.id.unverify).isVisible = contact.verified
         }
+        menu.findItem(R.id.merge_into_contact).isVisible = contact == null && isOnline
+        menu.findItem(R.id.contact_settings).isVisible = contact != null
+        menu.findItem(R.id.block).isVisible = isOnline || contact != null
         menu.findItem(R.id.refresh_profile).isEnabled = isOnline
         menu.findItem(R.id.session_info).isVisible = isOnline || contact != null
         return true
@@ -380,6 +397,7 @@ class ChatActivity : ServiceBoundActivity() {
                     val dialogBinding = DialogFingerprintsBinding.inflate(layoutInflater)
                     dialogBinding.textLocalFingerprint.text = localFingerprint
                     dialogBinding.textPeerFingerprint.text = peerFingerprint
+                    dialogBinding.textSafetyNumber.text = AIRADatabase.getSafetyNumber(contact.publicKey)
                     AlertDialog.Builder(this, R.style.CustomAlertDialog)
                         .setTitle(R.string.verifying_contact)
                         .setView(dialogBinding.root)
@@ -394,6 +412,70 @@ class ChatActivity : ServiceBoundActivity() {
                 }
                 true
             }
+            R.id.contact_settings -> {
+                airaService.getContactSettings(sessionId)?.let { settings ->
+                    val dialogBinding = DialogContactSettingsBinding.inflate(layoutInflater)
+                    dialogBinding.spinnerPadding.setSelection(settings.padding)
+                    dialogBinding.spinnerNotifications.setSelection(settings.notifications)
+                    dialogBinding.editAutoAcceptSize.setText((settings.autoAcceptFileSize/1_000_000).toString())
+                    dialogBinding.editRetention.setText((settings.retention/86400).toString())
+                    AlertDialog.Builder(this, R.style.CustomAlertDialog)
+                        .setTitle(R.string.contact_settings)
+                        .setView(dialogBinding.root)
+                        .setPositiveButton(R.string.ok) { _, _ ->
+                            airaService.setContactSettings(sessionId, ContactSettings(
+                                dialogBinding.spinnerPadding.selectedItemPosition,
+                                dialogBinding.spinnerNotifications.selectedItemPosition,
+                                (dialogBinding.editAutoAcceptSize.text.toString().toLongOrNull() ?: 0)*1_000_000,
+                                (dialogBinding.editRetention.text.toString().toLongOrNull() ?: 0)*86400,
+                            ))
+                        }
+                        .setNegativeButton(R.string.cancel, null)
+                        .show()
+                }
+                true
+            }
+            R.id.block -> {
+                AlertDialog.Builder(this, R.style.CustomAlertDialog)
+                    .setTitle(R.string.warning)
+                    .setMessage(R.string.ask_block)
+                    .setPositiveButton(R.string.block) { _, _ ->
+                        if (airaService.blockSession(sessionId)) {
+                            finish()
+                        }
+                    }
+                    .setNegativeButton(R.string.cancel, null)
+                    .show()
+                true
+            }
+            R.id.unverify -> {
+                if (airaService.setUnverified(sessionId)) {
+                    invalidateOptionsMenu()
+                    displayIconTrustLevel(true, false)
+                }
+                true
+            }
+            R.id.merge_into_contact -> {
+                val offlineContacts = airaService.contacts.filterKeys { !airaService.isOnline(it) }.toList()
+                if (offlineContacts.isEmpty()) {
+                    Toast.makeText(this, R.string.no_offline_contact, Toast.LENGTH_SHORT).show()
+                } else {
+                    AlertDialog.Builder(this, R.style.CustomAlertDialog)
+                        .setTitle(R.string.merge_into_contact)
+                        .setItems(offlineContacts.map { it.second.name }.toTypedArray()) { _, i ->
+                            val contactSessionId = offlineContacts[i].first
+                            if (airaService.mergeIntoContact(sessionId, contactSessionId)) {
+                                startActivity(Intent(this, ChatActivity::class.java).apply {
+                                    putExtra("sessionId", contactSessionId)
+                                })
+                                finish()
+                            }
+                        }
+                        .setNegativeButton(R.string.cancel, null)
+                        .show()
+                }
+                true
+            }
             R.id.delete_conversation -> {
                 AlertDialog.Builder(this, R.style.CustomAlertDialog)
                         .setTitle(R.string.warning)
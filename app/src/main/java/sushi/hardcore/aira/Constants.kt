@@ -5,8 +5,6 @@ import java.io.File
 
 object Constants {
     const val port = 7530
-    const val mDNSServiceName = "AIRA Node"
-    const val mDNSServiceType = "_aira._tcp"
     const val fileSizeLimit = 16380000
     const val MSG_LOADING_COUNT = 20
     const val FILE_CHUNK_SIZE = 1023996
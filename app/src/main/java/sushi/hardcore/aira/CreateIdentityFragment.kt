@@ -14,7 +14,7 @@ import sushi.hardcore.aira.databinding.FragmentCreateIdentityBinding
 import sushi.hardcore.aira.utils.AvatarPicker
 
 class CreateIdentityFragment(private val activity: AppCompatActivity) : Fragment() {
-    private external fun createNewIdentity(databaseFolder: String, name: String, password: ByteArray?): Boolean
+    private external fun createNewIdentity(databaseFolder: String, name: String, loginLabel: String?, encryptDatabase: Boolean, password: ByteArray?): Boolean
 
     companion object {
         fun newInstance(activity: AppCompatActivity, binder: Binder): CreateIdentityFragment {
@@ -78,7 +78,8 @@ class CreateIdentityFragment(private val activity: AppCompatActivity) : Fragment
         arguments?.let { bundle ->
             bundle.getBinder(LoginActivity.BINDER_ARG)?.let { binder ->
                 val databaseFolder = Constants.getDatabaseFolder(requireContext())
-                if (createNewIdentity(databaseFolder, identityName, password)) {
+                val loginLabel = binding.editLoginLabel.text.toString().ifEmpty { null }
+                if (createNewIdentity(databaseFolder, identityName, loginLabel, binding.checkboxEncryptDatabase.isChecked, password)) {
                     (binder as LoginActivity.ActivityLauncher).launch()
                     success = true
                 }
@@ -0,0 +1,5 @@
+package sushi.hardcore.aira
+
+class GroupChatItem(val sender: String?, val timestamp: Long, val data: ByteArray) {
+    val outgoing = sender == null
+}
\ No newline at end of file
@@ -10,7 +10,7 @@ import java.io.File
 
 class LoginActivity : AppCompatActivity() {
     companion object {
-        const val NAME_ARG = "identityName"
+        const val LOGIN_LABEL_ARG = "loginLabel"
         const val BINDER_ARG = "binder"
     }
 
@@ -35,10 +35,10 @@ class LoginActivity : AppCompatActivity() {
             }
         }
         val isProtected = AIRADatabase.isIdentityProtected(databaseFolder)
-        val name = AIRADatabase.getIdentityName(databaseFolder)
+        val identityExists = AIRADatabase.identityExists(databaseFolder)
         if (AIRAService.isServiceRunning) {
             startMainActivity()
-        } else if (name != null && !isProtected) {
+        } else if (identityExists && !isProtected) {
             if (AIRADatabase.loadIdentity(databaseFolder, null)) {
                 AIRADatabase.clearCache()
                 startMainActivity()
@@ -48,11 +48,11 @@ class LoginActivity : AppCompatActivity() {
         } else {
             supportFragmentManager.beginTransaction()
                 .add(
-                    R.id.fragment_container, if (name == null) {
+                    R.id.fragment_container, if (identityExists) {
+                        LoginFragment.newInstance(AIRADatabase.getLoginLabel(databaseFolder), ActivityLauncher())
+                    } else {
                         AIRADatabase.removeIdentityAvatar(databaseFolder)
                         CreateIdentityFragment.newInstance(this, ActivityLauncher())
-                    } else {
-                        LoginFragment.newInstance(name, ActivityLauncher())
                     }
                 )
                 .commit()
@@ -10,11 +10,11 @@ import sushi.hardcore.aira.databinding.FragmentLoginBinding
 
 class LoginFragment : Fragment() {
     companion object {
-        fun newInstance(name: String, binder: LoginActivity.ActivityLauncher): LoginFragment {
+        fun newInstance(loginLabel: String?, binder: LoginActivity.ActivityLauncher): LoginFragment {
             return LoginFragment().apply {
                 arguments = Bundle().apply {
                     putBinder(LoginActivity.BINDER_ARG, binder)
-                    putString(LoginActivity.NAME_ARG, name)
+                    putString(LoginActivity.LOGIN_LABEL_ARG, loginLabel)
                 }
             }
         }
@@ -29,23 +29,26 @@ class LoginFragment : Fragment() {
 
     override fun onViewCreated(view: View, savedInstanceState: Bundle?) {
         arguments?.let { bundle ->
-            bundle.getString(LoginActivity.NAME_ARG)?.let { name ->
-                bundle.getBinder(LoginActivity.BINDER_ARG)?.let { binder ->
-                    val databaseFolder = Constants.getDatabaseFolder(requireContext())
-                    val avatar = AIRADatabase.getIdentityAvatar(databaseFolder)
-                    if (avatar == null) {
-                        binding.avatar.setTextAvatar(name)
+            val loginLabel = bundle.getString(LoginActivity.LOGIN_LABEL_ARG)
+            bundle.getBinder(LoginActivity.BINDER_ARG)?.let { binder ->
+                val databaseFolder = Constants.getDatabaseFolder(requireContext())
+                val avatar = AIRADatabase.getIdentityAvatar(databaseFolder)
+                if (avatar == null) {
+                    binding.avatar.setTextAvatar(loginLabel)
+                } else {
+                    binding.avatar.setImageAvatar(avatar)
+                }
+                if (loginLabel == null) {
+                    binding.textIdentityName.visibility = View.GONE
+                } else {
+                    binding.textIdentityName.text = loginLabel
+                }
+                binding.buttonLogin.setOnClickListener {
+                    if (AIRADatabase.loadIdentity(databaseFolder, binding.editPassword.text.toString().toByteArray())) {
+                        AIRADatabase.clearCache()
+                        (binder as LoginActivity.ActivityLauncher).launch()
                     } else {
-                        binding.avatar.setImageAvatar(avatar)
-                    }
-                    binding.textIdentityName.text = name
-                    binding.buttonLogin.setOnClickListener {
-                        if (AIRADatabase.loadIdentity(databaseFolder, binding.editPassword.text.toString().toByteArray())) {
-                            AIRADatabase.clearCache()
-                            (binder as LoginActivity.ActivityLauncher).launch()
-                        } else {
-                            Toast.makeText(activity, R.string.identity_load_failed, Toast.LENGTH_SHORT).show()
-                        }
+                        Toast.makeText(activity, R.string.identity_load_failed, Toast.LENGTH_SHORT).show()
                     }
                 }
             }
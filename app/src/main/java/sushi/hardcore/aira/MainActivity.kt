@@ -86,6 +86,12 @@ class MainActivity : ServiceBoundActivity() {
             }
             return true
         }
+        override fun onNewGroupMessage(groupUuid: String, sessionId: Int, timestamp: Long, data: ByteArray) {}
+        override fun onContactKeyChanged(sessionId: Int) {
+            runOnUiThread {
+                Toast.makeText(this@MainActivity, getString(R.string.contact_key_changed, airaService.getNameOf(sessionId)), Toast.LENGTH_LONG).show()
+            }
+        }
     }
 
     override fun onCreate(savedInstanceState: Bundle?) {
@@ -10,6 +10,7 @@ import android.widget.EditText
 import android.widget.Toast
 import androidx.appcompat.app.AlertDialog
 import androidx.appcompat.app.AppCompatActivity
+import androidx.preference.ListPreference
 import androidx.preference.Preference
 import androidx.preference.PreferenceFragmentCompat
 import androidx.preference.SwitchPreferenceCompat
@@ -27,6 +28,7 @@ import sushi.hardcore.aira.utils.StringUtils
 
 class SettingsActivity: AppCompatActivity() {
     class MySettingsFragment(private val activity: AppCompatActivity): PreferenceFragmentCompat() {
+        private external fun generateFingerprint(publicKey: ByteArray): String
         private lateinit var databaseFolder: String
         private lateinit var airaService: AIRAService
         private val avatarPicker = AvatarPicker(activity) { avatar ->
@@ -49,8 +51,19 @@ class SettingsActivity: AppCompatActivity() {
             findPreference<Preference>("identityAvatar")?.let { identityAvatarPreference = it }
             startAtBootSwitch = findPreference("startAtBoot")!!
             updateStartAtBootSwitch(AIRADatabase.isIdentityProtected(databaseFolder))
-            val paddingPreference = findPreference<SwitchPreferenceCompat>("psecPadding")
+            val paddingPreference = findPreference<ListPreference>("paddingPolicy")
             paddingPreference?.isPersistent = false
+            paddingPreference?.value = AIRADatabase.getPaddingPolicy().toString()
+            val coverTrafficPreference = findPreference<ListPreference>("coverTraffic")
+            coverTrafficPreference?.isPersistent = false
+            coverTrafficPreference?.value = AIRADatabase.getCoverTrafficRate().toString()
+            findPreference<SwitchPreferenceCompat>("encryptDatabase")?.apply {
+                isPersistent = false
+                isChecked = AIRADatabase.getEncryptDatabase()
+                setOnPreferenceChangeListener { _, checked ->
+                    AIRADatabase.setEncryptDatabase(checked as Boolean)
+                }
+            }
             AIRADatabase.getIdentityAvatar(databaseFolder)?.let { avatar ->
                 displayAvatar(avatar)
             }
@@ -59,7 +72,6 @@ class SettingsActivity: AppCompatActivity() {
                     override fun onServiceConnected(name: ComponentName?, service: IBinder) {
                         val binder = service as AIRAService.AIRABinder
                         airaService = binder.getService()
-                        paddingPreference?.isChecked = airaService.usePadding
                     }
                     override fun onServiceDisconnected(name: ComponentName?) {}
                 }, Context.BIND_AUTO_CREATE)
@@ -97,12 +109,42 @@ class SettingsActivity: AppCompatActivity() {
                     .show()
                 false
             }
+            findPreference<Preference>("rotateKeypair")?.setOnPreferenceClickListener {
+                AlertDialog.Builder(activity, R.style.CustomAlertDialog)
+                    .setMessage(R.string.confirm_rotate_keypair)
+                    .setTitle(R.string.warning)
+                    .setPositiveButton(R.string.ok) { _, _ ->
+                        if (AIRADatabase.rotateKeypair()) {
+                            findPreference<Preference>("fingerprint")?.summary = StringUtils.beautifyFingerprint(AIRADatabase.getIdentityFingerprint())
+                        } else {
+                            Toast.makeText(activity, R.string.rotate_keypair_failed, Toast.LENGTH_SHORT).show()
+                        }
+                    }
+                    .setNegativeButton(R.string.cancel, null)
+                    .show()
+                false
+            }
+            findPreference<Preference>("blockedPeers")?.setOnPreferenceClickListener {
+                val blockedPeers = AIRADatabase.loadBlockedPeers()
+                if (blockedPeers.isNullOrEmpty()) {
+                    Toast.makeText(activity, R.string.no_blocked_peers, Toast.LENGTH_SHORT).show()
+                } else {
+                    AlertDialog.Builder(activity, R.style.CustomAlertDialog)
+                        .setTitle(R.string.unblock)
+                        .setItems(blockedPeers.map { StringUtils.beautifyFingerprint(generateFingerprint(it)) }.toTypedArray()) { _, i ->
+                            AIRADatabase.unblockPeer(blockedPeers[i])
+                        }
+                        .setNegativeButton(R.string.cancel, null)
+                        .show()
+                }
+                false
+            }
             findPreference<Preference>("deleteIdentity")?.setOnPreferenceClickListener {
                 AlertDialog.Builder(activity, R.style.CustomAlertDialog)
                     .setMessage(R.string.confirm_delete)
                     .setTitle(R.string.warning)
                     .setPositiveButton(R.string.ok) { _, _ ->
-                        if (Constants.getDatabasePath(activity).delete()) {
+                        if (AIRADatabase.destroyIdentity(databaseFolder)) {
                             airaService.logOut()
                             startActivity(Intent(activity, LoginActivity::class.java))
                             activity.finish()
@@ -112,6 +154,35 @@ class SettingsActivity: AppCompatActivity() {
                     .show()
                 false
             }
+            findPreference<Preference>("duressPassword")?.setOnPreferenceClickListener {
+                val dialogView = layoutInflater.inflate(R.layout.dialog_duress_password, null)
+                val duressPasswordEditText = dialogView.findViewById<EditText>(R.id.duress_password)
+                val duressPasswordConfirmEditText = dialogView.findViewById<EditText>(R.id.duress_password_confirm)
+                val decoyNameEditText = dialogView.findViewById<EditText>(R.id.decoy_name)
+                AlertDialog.Builder(activity, R.style.CustomAlertDialog)
+                    .setView(dialogView)
+                    .setTitle(R.string.duress_password)
+                    .setPositiveButton(R.string.ok) { _, _ ->
+                        val duressPassword = duressPasswordEditText.text.toString().toByteArray()
+                        val duressPasswordConfirm = duressPasswordConfirmEditText.text.toString().toByteArray()
+                        if (duressPassword.isNotEmpty() && duressPassword.contentEquals(duressPasswordConfirm)) {
+                            val decoyName = decoyNameEditText.text.toString().ifEmpty { null }
+                            if (!AIRADatabase.setDuressPassword(duressPassword, decoyName)) {
+                                Toast.makeText(activity, R.string.duress_password_failed, Toast.LENGTH_SHORT).show()
+                            }
+                        } else {
+                            Toast.makeText(activity, R.string.password_mismatch, Toast.LENGTH_SHORT).show()
+                        }
+                        duressPassword.fill(0)
+                        duressPasswordConfirm.fill(0)
+                    }
+                    .setNeutralButton(R.string.remove) { _, _ ->
+                        AIRADatabase.removeDuressPassword()
+                    }
+                    .setNegativeButton(R.string.cancel, null)
+                    .show()
+                false
+            }
             findPreference<Preference>("identityPassword")?.setOnPreferenceClickListener {
                 val dialogView = layoutInflater.inflate(R.layout.dialog_password, null)
                 val oldPasswordEditText = dialogView.findViewById<EditText>(R.id.old_password)
@@ -150,21 +221,27 @@ class SettingsActivity: AppCompatActivity() {
                 false
             }
             findPreference<Preference>("fingerprint")?.let { fingerprintPreference ->
-                val fingerprint = StringUtils.beautifyFingerprint(AIRADatabase.getIdentityFingerprint())
-                fingerprintPreference.summary = fingerprint
+                fingerprintPreference.summary = StringUtils.beautifyFingerprint(AIRADatabase.getIdentityFingerprint())
                 fingerprintPreference.setOnPreferenceClickListener {
                     activity.getSystemService(CLIPBOARD_SERVICE)?.let { service ->
                         val clipboardManager = service as ClipboardManager
-                        clipboardManager.setPrimaryClip(ClipData.newPlainText("", fingerprint))
+                        clipboardManager.setPrimaryClip(ClipData.newPlainText("", fingerprintPreference.summary))
                     }
                     Toast.makeText(activity, R.string.copied, Toast.LENGTH_SHORT).show()
                     false
                 }
             }
-            paddingPreference?.setOnPreferenceChangeListener { _, checked ->
-                airaService.usePadding = checked as Boolean
-                AIRADatabase.setUsePadding(checked)
-                true
+            paddingPreference?.setOnPreferenceChangeListener { _, value ->
+                val paddingPolicy = (value as String).toInt()
+                if (AIRADatabase.setPaddingPolicy(paddingPolicy)) {
+                    airaService.usePadding = paddingPolicy != AIRADatabase.PADDING_POLICY_NONE
+                    true
+                } else {
+                    false
+                }
+            }
+            coverTrafficPreference?.setOnPreferenceChangeListener { _, value ->
+                airaService.setCoverTrafficRate((value as String).toInt())
             }
         }
 
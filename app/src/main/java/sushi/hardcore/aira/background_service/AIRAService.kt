@@ -5,8 +5,7 @@ import android.app.*
 import android.content.Context
 import android.content.Intent
 import android.net.Uri
-import android.net.nsd.NsdManager
-import android.net.nsd.NsdServiceInfo
+import android.net.wifi.WifiManager
 import android.os.*
 import android.os.Process.THREAD_PRIORITY_BACKGROUND
 import android.util.Log
@@ -42,6 +41,8 @@ class AIRAService : Service() {
         const val MESSAGE_SEND_AVATAR = 4
         const val MESSAGE_CANCEL_FILE_TRANSFER = 5
         const val FLAG_PENDING_INTENT = PendingIntent.FLAG_UPDATE_CURRENT
+        const val DISCOVERY_TIMEOUT = 3000
+        const val DISCOVERY_INTERVAL = 60000L
 
         var isServiceRunning = false
     }
@@ -58,43 +59,13 @@ class AIRAService : Service() {
     private val sendFileTransfers = mutableMapOf<Int, FilesSender>()
     val receiveFileTransfers = mutableMapOf<Int, FilesReceiver>()
     lateinit var contacts: HashMap<Int, Contact>
+    lateinit var groups: HashMap<String, Group>
+    private val contactSettings = mutableMapOf<Int, ContactSettings>()
     var usePadding = true
     private lateinit var serviceHandler: Handler
     private lateinit var notificationManager: NotificationManagerCompat
-    private lateinit var nsdManager: NsdManager
-    private val nsdRegistrationListener = object : NsdManager.RegistrationListener {
-        override fun onRegistrationFailed(serviceInfo: NsdServiceInfo?, errorCode: Int) {
-            Log.w("mDNS","DNS-SD registration failed: $errorCode")
-        }
-        override fun onUnregistrationFailed(serviceInfo: NsdServiceInfo?, errorCode: Int) {}
-        override fun onServiceRegistered(serviceInfo: NsdServiceInfo?) {}
-        override fun onServiceUnregistered(serviceInfo: NsdServiceInfo?) {}
-    }
-    private var shouldRestartDiscovery = false
-    private val nsdDiscoveryListener = object : NsdManager.DiscoveryListener {
-        override fun onStartDiscoveryFailed(serviceType: String?, errorCode: Int) {
-            Log.w("mDNS", "Failed to start discovery: $errorCode")
-        }
-        override fun onStopDiscoveryFailed(serviceType: String?, errorCode: Int) {}
-        override fun onDiscoveryStarted(serviceType: String?) {}
-        override fun onDiscoveryStopped(serviceType: String?) {
-            if (shouldRestartDiscovery) {
-                startDiscovery()
-                shouldRestartDiscovery = false
-            }
-        }
-        override fun onServiceFound(serviceInfo: NsdServiceInfo) {
-            nsdManager.resolveService(serviceInfo, object : NsdManager.ResolveListener {
-                override fun onResolveFailed(serviceInfo: NsdServiceInfo?, errorCode: Int) {}
-                override fun onServiceResolved(serviceInfo: NsdServiceInfo) {
-                    if (isServiceRunning) {
-                        connectTo(serviceInfo.host.hostAddress)
-                    }
-                }
-            })
-        }
-        override fun onServiceLost(serviceInfo: NsdServiceInfo?) {}
-    }
+    private var multicastLock: WifiManager.MulticastLock? = null
+    private val discoveryLock = Object()
     lateinit var identityName: String
     val savedMsgs = mutableMapOf<Int, MutableList<ChatItem>>()
     val pendingMsgs = mutableMapOf<Int, MutableList<ByteArray>>()
@@ -122,12 +93,17 @@ class AIRAService : Service() {
         fun onPendingMessagesSent(sessionId: Int)
         fun onNewMessage(sessionId: Int, timestamp: Long, data: ByteArray): Boolean
         fun onAskLargeFiles(sessionId: Int, filesReceiver: FilesReceiver): Boolean
+        fun onContactKeyChanged(sessionId: Int)
+        fun onNewGroupMessage(groupUuid: String, sessionId: Int, timestamp: Long, data: ByteArray)
     }
 
-    fun connectTo(ip: String) {
+    fun connectTo(ip: String, port: Int = Constants.port) {
         serviceHandler.obtainMessage().apply {
             what = MESSAGE_CONNECT_TO
-            data = Bundle().apply { putString("ip", ip) }
+            data = Bundle().apply {
+                putString("ip", ip)
+                putInt("port", port)
+            }
             serviceHandler.sendMessage(this)
         }
     }
@@ -219,10 +195,9 @@ class AIRAService : Service() {
         sessions[sessionId]?.peerPublicKey?.let {
             AIRADatabase.addContact(name, savedAvatars[sessionId], it)?.let { contact ->
                 contacts[sessionId] = contact
+                contactSettings[sessionId] = ContactSettings(ContactSettings.PADDING_DEFAULT, ContactSettings.NOTIFICATIONS_DEFAULT, 0, 0)
                 savedMsgs.remove(sessionId)?.let { msgs ->
-                    for (msg in msgs) {
-                        AIRADatabase.storeMsg(contact.uuid, msg.outgoing, msg.timestamp, msg.data)
-                    }
+                    AIRADatabase.storeMsgs(contact.uuid, msgs)
                 }
                 savedNames.remove(sessionId)
                 pendingMsgs[sessionId] = mutableListOf()
@@ -233,6 +208,32 @@ class AIRAService : Service() {
         return false
     }
 
+    fun getContactSettings(sessionId: Int): ContactSettings? {
+        return contactSettings[sessionId]
+    }
+
+    fun setContactSettings(sessionId: Int, settings: ContactSettings): Boolean {
+        contacts[sessionId]?.let {
+            if (AIRADatabase.setContactSettings(it.uuid, settings.padding, settings.notifications, settings.autoAcceptFileSize, settings.retention)) {
+                contactSettings[sessionId] = settings
+                applyContactSettings(sessionId)
+                if (settings.retention > 0) {
+                    AIRADatabase.deleteExpiredMsgs(it.uuid, TimeUtils.getTimestamp())
+                }
+                return true
+            }
+        }
+        return false
+    }
+
+    private fun applyContactSettings(sessionId: Int) {
+        sessions[sessionId]?.paddingOverride = when (contactSettings[sessionId]?.padding) {
+            ContactSettings.PADDING_ALWAYS -> true
+            ContactSettings.PADDING_NEVER -> false
+            else -> null
+        }
+    }
+
     fun setVerified(sessionId: Int): Boolean {
         contacts[sessionId]?.let {
             if (AIRADatabase.setVerified(it.uuid)) {
@@ -243,6 +244,156 @@ class AIRAService : Service() {
         return false
     }
 
+    fun setUnverified(sessionId: Int): Boolean {
+        contacts[sessionId]?.let {
+            if (AIRADatabase.setUnverified(it.uuid)) {
+                it.verified = false
+                return true
+            }
+        }
+        return false
+    }
+
+    //the old key is kept in the contact's key history and the verification is revoked
+    fun changeContactKey(sessionId: Int, newPublicKey: ByteArray): Boolean {
+        contacts[sessionId]?.let {
+            if (it.publicKey.contentEquals(newPublicKey)) {
+                return true
+            }
+            if (AIRADatabase.changeContactKey(it.uuid, newPublicKey, TimeUtils.getTimestamp())) {
+                it.publicKey = newPublicKey
+                it.verified = false
+                uiCallbacks?.onContactKeyChanged(sessionId)
+                return true
+            }
+        }
+        return false
+    }
+
+    //attach an unknown session to an offline contact whose identity key changed
+    fun mergeIntoContact(sessionId: Int, contactSessionId: Int): Boolean {
+        if (isContact(sessionId) || isOnline(contactSessionId)) {
+            return false
+        }
+        synchronized(this) {
+            val session = sessions[sessionId] ?: return false
+            if (!changeContactKey(contactSessionId, session.peerPublicKey)) {
+                return false
+            }
+            sessions.remove(sessionId)
+            sessions[contactSessionId] = session
+            applyContactSettings(contactSessionId)
+            for ((key, id) in sessionIdByKey) {
+                if (id == sessionId) {
+                    sessionIdByKey[key] = contactSessionId
+                }
+            }
+            val contactUuid = contacts[contactSessionId]!!.uuid
+            savedMsgs.remove(sessionId)?.let { msgs ->
+                AIRADatabase.storeMsgs(contactUuid, msgs)
+            }
+            savedNames.remove(sessionId)
+            savedAvatars.remove(sessionId)
+            notSeen.remove(sessionId)
+            uiCallbacks?.onSessionDisconnect(sessionId)
+            uiCallbacks?.onNewSession(contactSessionId, session.ip)
+            session.startCoverTraffic()
+            for (i in 0 until pendingMsgs[contactSessionId]!!.size) {
+                sendAndSave(contactSessionId, pendingMsgs[contactSessionId]!!.removeAt(0))
+            }
+            uiCallbacks?.onPendingMessagesSent(contactSessionId)
+        }
+        return true
+    }
+
+    private fun getSessionIdOf(contactUuid: String): Int? {
+        return contacts.entries.find { it.value.uuid == contactUuid }?.key
+    }
+
+    private fun sendGroupInfo(group: Group) {
+        val members = group.members.mapNotNull { uuid -> contacts.values.find { it.uuid == uuid }?.publicKey }
+        val buffer = Protocol.groupInfo(group.uuid, group.name, members)
+        for (member in group.members) {
+            getSessionIdOf(member)?.let { sessionId ->
+                sendOrAddToPending(sessionId, buffer)
+            }
+        }
+    }
+
+    fun createGroup(name: String, sessionIds: List<Int>): Group? {
+        val members = sessionIds.mapNotNull { contacts[it]?.uuid }
+        return AIRADatabase.addGroup(null, name, members.toTypedArray())?.also { group ->
+            groups[group.uuid] = group
+            sendGroupInfo(group)
+        }
+    }
+
+    fun setGroupMembers(groupUuid: String, sessionIds: List<Int>): Boolean {
+        groups[groupUuid]?.let { group ->
+            val members = sessionIds.mapNotNull { contacts[it]?.uuid }
+            if (AIRADatabase.setGroupMembers(groupUuid, members.toTypedArray())) {
+                group.members.clear()
+                group.members.addAll(members)
+                sendGroupInfo(group)
+                return true
+            }
+        }
+        return false
+    }
+
+    fun removeGroup(groupUuid: String): Boolean {
+        return if (AIRADatabase.removeGroup(groupUuid)) {
+            groups.remove(groupUuid)
+            true
+        } else {
+            false
+        }
+    }
+
+    //group messages are fanned out over the pairwise sessions and stored once in the group conversation
+    fun sendGroupMsg(groupUuid: String, msg: ByteArray): Boolean {
+        groups[groupUuid]?.let { group ->
+            val buffer = Protocol.groupMessage(groupUuid, msg)
+            for (member in group.members) {
+                getSessionIdOf(member)?.let { sessionId ->
+                    sendOrAddToPending(sessionId, buffer)
+                }
+            }
+            return AIRADatabase.storeGroupMsg(groupUuid, null, TimeUtils.getTimestamp(), msg)
+        }
+        return false
+    }
+
+    private fun handleGroupInfo(contact: Contact, groupInfo: Protocol.Companion.GroupInfo) {
+        val members = groupInfo.members.mapNotNull { publicKey ->
+            contacts.values.find { it.publicKey.contentEquals(publicKey) }?.uuid
+        }.plus(contact.uuid).distinct()
+        val group = groups[groupInfo.groupUuid]
+        if (group == null) {
+            AIRADatabase.addGroup(groupInfo.groupUuid, groupInfo.name, members.toTypedArray())?.let {
+                groups[it.uuid] = it
+            }
+        } else if (group.members.contains(contact.uuid)) { //only members can update the group
+            if (group.name != groupInfo.name && AIRADatabase.changeGroupName(group.uuid, groupInfo.name)) {
+                group.name = groupInfo.name
+            }
+            if (AIRADatabase.setGroupMembers(group.uuid, members.toTypedArray())) {
+                group.members.clear()
+                group.members.addAll(members)
+            }
+        }
+    }
+
+    private fun handleGroupMessage(sessionId: Int, contact: Contact, groupMessage: Protocol.Companion.GroupMessage) {
+        groups[groupMessage.groupUuid]?.let { group ->
+            if (group.members.contains(contact.uuid) && groupMessage.msg[0] == Protocol.MESSAGE) {
+                val timestamp = TimeUtils.getTimestamp()
+                AIRADatabase.storeGroupMsg(group.uuid, contact.uuid, timestamp, groupMessage.msg)
+                uiCallbacks?.onNewGroupMessage(group.uuid, sessionId, timestamp, groupMessage.msg)
+            }
+        }
+    }
+
     fun setSeen(sessionId: Int, seen: Boolean) {
         if (seen) {
             notSeen.remove(sessionId)
@@ -262,6 +413,9 @@ class AIRAService : Service() {
     fun removeContact(sessionId: Int): Boolean {
         contacts.remove(sessionId)?.let {
             return if (AIRADatabase.removeContact(it.uuid)) {
+                for (group in groups.values) {
+                    group.members.remove(it.uuid)
+                }
                 savedMsgs[sessionId] = mutableListOf()
                 pendingMsgs.remove(sessionId)
                 savedNames[sessionId] = it.name
@@ -328,7 +482,7 @@ class AIRAService : Service() {
                                 isActuallyNewSession = false
                             }
                         }
-                        if (isActuallyNewSession && !session.peerPublicKey.contentEquals(AIRADatabase.getIdentityPublicKey())) {
+                        if (isActuallyNewSession && !session.peerPublicKey.contentEquals(AIRADatabase.getIdentityPublicKey()) && !AIRADatabase.isPeerBlocked(session.peerPublicKey)) {
                             var sessionId: Int? = null
                             for ((i, contact) in contacts) {
                                 if (contact.publicKey.contentEquals(session.peerPublicKey)){
@@ -342,11 +496,18 @@ class AIRAService : Service() {
                                 sessionId = sessionCounter
                                 sessionCounter++
                             }
+                            applyContactSettings(sessionId)
                             session.configureBlocking(false)
                             val key = session.register(selector, SelectionKey.OP_READ)
                             sessionIdByKey[key] = sessionId
                             uiCallbacks?.onNewSession(sessionId, session.ip)
+                            session.startCoverTraffic()
                             if (isContact(sessionId)) {
+                                AIRADatabase.getKeyRotations()?.let { keyRotations ->
+                                    if (keyRotations.isNotEmpty()) {
+                                        session.encryptAndSend(Protocol.keyRotations(keyRotations), usePadding)
+                                    }
+                                }
                                 for (i in 0 until pendingMsgs[sessionId]!!.size) {
                                     sendAndSave(sessionId, pendingMsgs[sessionId]!!.removeAt(0))
                                 }
@@ -367,6 +528,47 @@ class AIRAService : Service() {
         }.start()
     }
 
+    fun setCoverTrafficRate(rate: Int): Boolean {
+        if (!AIRADatabase.setCoverTrafficRate(rate)) {
+            return false
+        }
+        //the native schedulers stopped when the rate changed
+        synchronized(this) {
+            for (session in sessions.values) {
+                session.startCoverTraffic()
+            }
+        }
+        return true
+    }
+
+    private fun closeSession(sessionId: Int, session: Session, key: SelectionKey) {
+        session.close()
+        key.cancel()
+        uiCallbacks?.onSessionDisconnect(sessionId)
+        sessions.remove(sessionId)
+        savedMsgs.remove(sessionId)
+        savedNames.remove(sessionId)
+        sendFileTransfers.remove(sessionId)?.fileTransferNotification?.cancel()
+        receiveFileTransfers.remove(sessionId)?.fileTransferNotification?.cancel()
+    }
+
+    //blocked peers are refused right after the handshake, before anything they send is stored
+    fun blockSession(sessionId: Int): Boolean {
+        val publicKey = sessions[sessionId]?.peerPublicKey ?: contacts[sessionId]?.publicKey ?: return false
+        if (!AIRADatabase.blockPeer(publicKey)) {
+            return false
+        }
+        synchronized(this) {
+            sessions[sessionId]?.let { session ->
+                sessionIdByKey.entries.find { it.value == sessionId }?.let { entry ->
+                    sessionIdByKey.remove(entry.key)
+                    closeSession(sessionId, session, entry.key)
+                }
+            }
+        }
+        return true
+    }
+
     private fun avatarToIcon(avatar: ByteArray): IconCompat {
         return IconCompat.createWithBitmap(
             Glide.with(this)
@@ -378,6 +580,10 @@ class AIRAService : Service() {
     }
 
     private fun sendNotification(sessionId: Int, msgContent: ByteArray, timestamp: Long) {
+        val notificationMode = contactSettings[sessionId]?.notifications
+        if (notificationMode == ContactSettings.NOTIFICATIONS_MUTED) {
+            return
+        }
         val name = getNameOf(sessionId)
         val text = if (msgContent[0] == Protocol.MESSAGE) {
             msgContent.decodeToString(1)
@@ -418,6 +624,7 @@ class AIRAService : Service() {
                 )
                 .setAutoCancel(true)
                 .setDefaults(Notification.DEFAULT_ALL)
+                .setSilent(notificationMode == ContactSettings.NOTIFICATIONS_SILENT)
                 .apply {
                     priority = NotificationCompat.PRIORITY_HIGH
                 }
@@ -511,8 +718,9 @@ class AIRAService : Service() {
                             }
                             MESSAGE_CONNECT_TO -> {
                                 msg.data.getString("ip")?.let { ip ->
+                                    val port = msg.data.getInt("port", Constants.port)
                                     Thread {
-                                        val addr = InetSocketAddress(ip, Constants.port)
+                                        val addr = InetSocketAddress(ip, port)
                                         if (addr.isUnresolved) {
                                             uiCallbacks?.onConnectFailed(ip, getString(R.string.invalid_ip))
                                         } else {
@@ -562,7 +770,6 @@ class AIRAService : Service() {
                                 }
                             }
                             MESSAGE_LOGOUT -> {
-                                nsdManager.unregisterService(nsdRegistrationListener)
                                 stopDiscovery()
                                 quit()
                                 stopSelf()
@@ -575,7 +782,7 @@ class AIRAService : Service() {
                 }
             }
         }
-        identityName = AIRADatabase.getIdentityName(databaseFolder)!!
+        identityName = AIRADatabase.getIdentityName()
         val contactList = AIRADatabase.loadContacts()
         if (contactList == null) {
             contacts = HashMap(0)
@@ -584,13 +791,25 @@ class AIRAService : Service() {
             for (contact in contactList) {
                 contacts[sessionCounter] = contact
                 pendingMsgs[sessionCounter] = mutableListOf()
+                AIRADatabase.getContactSettings(contact.uuid)?.let {
+                    contactSettings[sessionCounter] = it
+                    if (it.retention > 0) {
+                        AIRADatabase.deleteExpiredMsgs(contact.uuid, TimeUtils.getTimestamp())
+                    }
+                }
                 if (!contact.seen) {
                     notSeen.add(sessionCounter)
                 }
                 sessionCounter++
             }
         }
-        usePadding = AIRADatabase.getUsePadding()
+        val groupList = AIRADatabase.loadGroups()
+        groups = if (groupList == null) {
+            HashMap(0)
+        } else {
+            HashMap(groupList.associateBy { it.uuid })
+        }
+        usePadding = AIRADatabase.getPaddingPolicy() != AIRADatabase.PADDING_POLICY_NONE
     }
 
     private fun setAvatarUuid(sessionId: Int, avatarUuid: String?) {
@@ -677,7 +896,7 @@ class AIRAService : Service() {
                                         val buffer = session.receiveAndDecrypt()
                                         if (buffer == null) {
                                             shouldCloseSession = true
-                                        } else {
+                                        } else if (buffer.isNotEmpty()) {
                                             when (buffer[0]) {
                                                 Protocol.LARGE_FILE_CHUNK -> {
                                                     receiveFileTransfers[sessionId]?.let { filesReceiver ->
@@ -781,8 +1000,12 @@ class AIRAService : Service() {
                                                                     notificationManager,
                                                             )
                                                             receiveFileTransfers[sessionId] = filesReceiver
+                                                            val autoAcceptSize = contactSettings[sessionId]?.autoAcceptFileSize ?: 0
                                                             var shouldSendNotification = true
-                                                            if (!isAppInBackground) {
+                                                            if (autoAcceptSize > 0 && files.sumOf { it.fileSize } <= autoAcceptSize) {
+                                                                filesReceiver.accept()
+                                                                shouldSendNotification = false
+                                                            } else if (!isAppInBackground) {
                                                                 if (uiCallbacks?.onAskLargeFiles(sessionId, filesReceiver) == true) {
                                                                     shouldSendNotification = false
                                                                 }
@@ -814,6 +1037,29 @@ class AIRAService : Service() {
                                                         session.encryptAndSend(Protocol.avatar(avatar), usePadding)
                                                     }
                                                 }
+                                                Protocol.KEY_ROTATIONS -> {
+                                                    if (!isContact(sessionId)) {
+                                                        AIRADatabase.findRotatedContact(buffer.sliceArray(1 until buffer.size), session.peerPublicKey)?.let { contactUuid ->
+                                                            contacts.entries.find { it.value.uuid == contactUuid }?.let {
+                                                                mergeIntoContact(sessionId, it.key)
+                                                            }
+                                                        }
+                                                    }
+                                                }
+                                                Protocol.GROUP_INFO -> {
+                                                    contacts[sessionId]?.let { contact ->
+                                                        Protocol.parseGroupInfo(buffer)?.let { groupInfo ->
+                                                            handleGroupInfo(contact, groupInfo)
+                                                        }
+                                                    }
+                                                }
+                                                Protocol.GROUP_MESSAGE -> {
+                                                    contacts[sessionId]?.let { contact ->
+                                                        Protocol.parseGroupMessage(buffer)?.let { groupMessage ->
+                                                            handleGroupMessage(sessionId, contact, groupMessage)
+                                                        }
+                                                    }
+                                                }
                                                 Protocol.NAME -> {
                                                     val name = StringUtils.sanitizeName(buffer.sliceArray(1 until buffer.size).decodeToString())
                                                     uiCallbacks?.onNameTold(sessionId, name)
@@ -884,14 +1130,7 @@ class AIRAService : Service() {
                                         shouldCloseSession = true
                                     }
                                     if (shouldCloseSession) {
-                                        session.close()
-                                        key.cancel()
-                                        uiCallbacks?.onSessionDisconnect(sessionId)
-                                        sessions.remove(sessionId)
-                                        savedMsgs.remove(sessionId)
-                                        savedNames.remove(sessionId)
-                                        sendFileTransfers.remove(sessionId)?.fileTransferNotification?.cancel()
-                                        receiveFileTransfers.remove(sessionId)?.fileTransferNotification?.cancel()
+                                        closeSession(sessionId, session, key)
                                     }
                                 }
                             }
@@ -912,16 +1151,69 @@ class AIRAService : Service() {
     }
 
     fun restartDiscovery() {
-        shouldRestartDiscovery = true
-        stopDiscovery()
+        synchronized(discoveryLock) {
+            discoveryLock.notify()
+        }
     }
 
     private fun stopDiscovery() {
-        nsdManager.stopServiceDiscovery(nsdDiscoveryListener)
+        AIRADatabase.stopDiscoveryResponder()
+        multicastLock?.release()
+        multicastLock = null
+        restartDiscovery() //wake up the browsing thread so that it exits
+    }
+
+    //the address of the wifi interface, or 0.0.0.0 to let the system choose
+    private fun getDiscoveryInterface(): String {
+        for (iface in NetworkInterface.getNetworkInterfaces()) {
+            if (iface.isUp && !iface.isLoopback && iface.supportsMulticast()) {
+                for (addr in iface.inetAddresses) {
+                    if (addr is Inet4Address && addr.isSiteLocalAddress) {
+                        return addr.hostAddress!!
+                    }
+                }
+            }
+        }
+        return "0.0.0.0"
+    }
+
+    //peers recognized as contacts are connected first, and skipped if they are already online
+    private fun handleDiscoveredPeers(peers: List<DiscoveredPeer>) {
+        val (contactPeers, otherPeers) = peers.partition { it.contactUuid != null }
+        for (peer in contactPeers) {
+            val sessionId = contacts.entries.find { it.value.uuid == peer.contactUuid }?.key
+            if (sessionId == null || !isOnline(sessionId)) {
+                connectTo(peer.ip, peer.port)
+            }
+        }
+        for (peer in otherPeers) {
+            connectTo(peer.ip, peer.port)
+        }
     }
 
     private fun startDiscovery() {
-        nsdManager.discoverServices(Constants.mDNSServiceType, NsdManager.PROTOCOL_DNS_SD, nsdDiscoveryListener)
+        multicastLock = (applicationContext.getSystemService(Context.WIFI_SERVICE) as WifiManager).createMulticastLock("AIRA discovery").apply {
+            setReferenceCounted(false)
+            acquire()
+        }
+        val discoveryInterface = getDiscoveryInterface()
+        Thread {
+            if (!AIRADatabase.runDiscoveryResponder(discoveryInterface, Constants.port)) {
+                Log.w("mDNS", "Discovery responder failed")
+            }
+        }.start()
+        Thread {
+            while (isServiceRunning) {
+                AIRADatabase.discoverPeers(discoveryInterface, DISCOVERY_TIMEOUT)?.let { peers ->
+                    if (isServiceRunning) {
+                        handleDiscoveredPeers(peers)
+                    }
+                }
+                synchronized(discoveryLock) {
+                    discoveryLock.wait(DISCOVERY_INTERVAL)
+                }
+            }
+        }.start()
     }
 
     @RequiresApi(Build.VERSION_CODES.O)
@@ -990,23 +1282,12 @@ class AIRAService : Service() {
             .build()
         startForeground(1, notification)
 
-        val serviceInfo = NsdServiceInfo().apply {
-            serviceName = Constants.mDNSServiceName
-            serviceType = Constants.mDNSServiceType
-            port = Constants.port
-        }
-        nsdManager = getSystemService(Context.NSD_SERVICE) as NsdManager
-        try {
-            nsdManager.registerService(serviceInfo, NsdManager.PROTOCOL_DNS_SD, nsdRegistrationListener)
-            startDiscovery()
-        } catch (e: IllegalArgumentException) {
-            //can happen if service is restarted too quickly
-        }
-
         startListening()
 
         isServiceRunning = true
 
+        startDiscovery()
+
         return START_STICKY
     }
 
@@ -2,7 +2,7 @@ package sushi.hardcore.aira.background_service
 
 class Contact(
     val uuid: String,
-    val publicKey: ByteArray,
+    var publicKey: ByteArray,
     var name: String,
     var avatar: String?,
     var verified: Boolean,
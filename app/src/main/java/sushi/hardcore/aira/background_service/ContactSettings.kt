@@ -0,0 +1,17 @@
+package sushi.hardcore.aira.background_service
+
+class ContactSettings(
+    var padding: Int,
+    var notifications: Int,
+    var autoAcceptFileSize: Long,
+    var retention: Long,
+) {
+    companion object {
+        const val PADDING_DEFAULT = 0
+        const val PADDING_ALWAYS = 1
+        const val PADDING_NEVER = 2
+        const val NOTIFICATIONS_DEFAULT = 0
+        const val NOTIFICATIONS_SILENT = 1
+        const val NOTIFICATIONS_MUTED = 2
+    }
+}
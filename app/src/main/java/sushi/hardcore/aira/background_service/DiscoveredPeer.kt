@@ -0,0 +1,7 @@
+package sushi.hardcore.aira.background_service
+
+class DiscoveredPeer(
+    val ip: String,
+    val port: Int,
+    val contactUuid: String?, //set if the announcement carried a token from one of our contacts
+)
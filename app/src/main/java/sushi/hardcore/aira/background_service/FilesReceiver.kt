@@ -18,6 +18,11 @@ class FilesReceiver(
 ): FilesTransfer(context, notificationManager, files.size) {
     var shouldAsk = true
 
+    fun accept() {
+        shouldAsk = false
+        onAccepted(this)
+    }
+
     @SuppressLint("SetTextI18n")
     fun ask(activity: AppCompatActivity, sessionName: String) {
         val dialogBinding = DialogAskFileBinding.inflate(activity.layoutInflater)
@@ -0,0 +1,9 @@
+package sushi.hardcore.aira.background_service
+
+class Group(
+    val uuid: String,
+    var name: String,
+    members: Array<String>
+) {
+    val members = members.toMutableList()
+}
\ No newline at end of file
@@ -0,0 +1,3 @@
+package sushi.hardcore.aira.background_service
+
+class PreviousKey(val publicKey: ByteArray, val timestamp: Long)
\ No newline at end of file
@@ -1,6 +1,7 @@
 package sushi.hardcore.aira.background_service
 
 import java.nio.ByteBuffer
+import java.util.*
 
 class Protocol {
     companion object {
@@ -15,6 +16,21 @@ class Protocol {
         const val LARGE_FILE_CHUNK: Byte = 0x08
         const val ACK_CHUNK: Byte = 0x09
         const val ABORT_FILES_TRANSFER: Byte = 0x0a
+        const val KEY_ROTATIONS: Byte = 0x0b
+        const val GROUP_INFO: Byte = 0x0c
+        const val GROUP_MESSAGE: Byte = 0x0d
+        const val DUMMY: Byte = 0x0e
+        private const val PUBLIC_KEY_LEN = 32
+
+        private fun uuidToBytes(uuid: String): ByteArray {
+            val javaUuid = UUID.fromString(uuid)
+            return ByteBuffer.allocate(16).putLong(javaUuid.mostSignificantBits).putLong(javaUuid.leastSignificantBits).array()
+        }
+
+        private fun bytesToUuid(buffer: ByteArray, offset: Int): String {
+            val byteBuffer = ByteBuffer.wrap(buffer, offset, 16)
+            return UUID(byteBuffer.long, byteBuffer.long).toString()
+        }
 
         fun askProfileInfo(): ByteArray {
             return byteArrayOf(ASK_PROFILE_INFO)
@@ -64,6 +80,47 @@ class Protocol {
             return byteArrayOf(ACK_CHUNK)
         }
 
+        fun keyRotations(keyRotations: ByteArray): ByteArray {
+            return byteArrayOf(KEY_ROTATIONS)+keyRotations
+        }
+
+        //members are identified by their public keys since contacts UUIDs are local
+        fun groupInfo(groupUuid: String, name: String, members: List<ByteArray>): ByteArray {
+            val nameBytes = name.toByteArray()
+            var buff = byteArrayOf(GROUP_INFO)+uuidToBytes(groupUuid)+ByteBuffer.allocate(2).putShort(nameBytes.size.toShort()).array()+nameBytes
+            for (member in members) {
+                buff += member
+            }
+            return buff
+        }
+
+        fun groupMessage(groupUuid: String, msg: ByteArray): ByteArray {
+            return byteArrayOf(GROUP_MESSAGE)+uuidToBytes(groupUuid)+msg
+        }
+
+        class GroupInfo(val groupUuid: String, val name: String, val members: List<ByteArray>)
+
+        fun parseGroupInfo(buffer: ByteArray): GroupInfo? {
+            if (buffer.size > 19) {
+                val nameLen = ByteBuffer.wrap(ByteArray(2)+buffer.sliceArray(17..18)).int
+                if (buffer.size >= 19+nameLen && (buffer.size-19-nameLen)%PUBLIC_KEY_LEN == 0) {
+                    val name = buffer.sliceArray(19 until 19+nameLen).decodeToString()
+                    val members = (19+nameLen until buffer.size step PUBLIC_KEY_LEN).map { buffer.sliceArray(it until it+PUBLIC_KEY_LEN) }
+                    return GroupInfo(bytesToUuid(buffer, 1), name, members)
+                }
+            }
+            return null
+        }
+
+        class GroupMessage(val groupUuid: String, val msg: ByteArray)
+
+        fun parseGroupMessage(buffer: ByteArray): GroupMessage? {
+            if (buffer.size > 17) {
+                return GroupMessage(bytesToUuid(buffer, 1), buffer.sliceArray(17 until buffer.size))
+            }
+            return null
+        }
+
         class SmallFile(val rawFileName: ByteArray, val fileContent: ByteArray)
 
         fun parseSmallFile(buffer: ByteArray): SmallFile? {
@@ -8,6 +8,7 @@ import net.i2p.crypto.eddsa.spec.EdDSAPublicKeySpec
 import org.whispersystems.curve25519.Curve25519
 import sushi.hardcore.aira.AIRADatabase
 import java.io.ByteArrayOutputStream
+import java.io.IOException
 import java.io.OutputStream
 import java.nio.ByteBuffer
 import java.nio.channels.*
@@ -27,6 +28,9 @@ class Session(private val socket: SocketChannel, val outgoing: Boolean): Selecta
     private external fun computeHandshakeFinished(localHandshakeTrafficSecret: ByteArray, handshakeHash: ByteArray): ByteArray
     private external fun verifyHandshakeFinished(peerHandshakeFinished: ByteArray, peerHandshakeTrafficSecret: ByteArray, handshakeHash: ByteArray): Boolean
     private external fun deriveApplicationKeys(handshakeSecret: ByteArray, handshakeHash: ByteArray, iAmBob: Boolean): ApplicationKeys
+    private external fun pad(input: ByteArray, usePadding: Boolean): ByteArray
+    private external fun unpad(input: ByteArray): ByteArray?
+    external fun startCoverTraffic()
 
     companion object {
         private const val RANDOM_LEN = 64
@@ -49,6 +53,7 @@ class Session(private val socket: SocketChannel, val outgoing: Boolean): Selecta
     private var localCounter = 0L
     private lateinit var applicationKeys: ApplicationKeys
     lateinit var peerPublicKey: ByteArray
+    var paddingOverride: Boolean? = null
     val ip: String = socket.socket().inetAddress.hostAddress
 
     private fun handshakeWrite(buffer: ByteArray, handshakeSentBuff: OutputStream) {
@@ -158,27 +163,6 @@ class Session(private val socket: SocketChannel, val outgoing: Boolean): Selecta
         return false
     }
 
-    private fun pad(input: ByteArray, usePadding: Boolean): ByteArray {
-        val encodedLen = ByteBuffer.allocate(MESSAGE_LEN_LEN).putInt(input.size).array()
-        return if (usePadding) {
-            val msgLen = input.size + MESSAGE_LEN_LEN
-            var len = 1000
-            while (len < msgLen) {
-                len *= 2
-            }
-            val padding = ByteArray(len-msgLen)
-            prng.nextBytes(padding)
-            encodedLen + input + padding
-        } else {
-            encodedLen + input
-        }
-    }
-
-    private fun unpad(input: ByteArray): ByteArray {
-        val messageLen = ByteBuffer.wrap(input.sliceArray(0..MESSAGE_LEN_LEN)).int
-        return input.sliceArray(MESSAGE_LEN_LEN until MESSAGE_LEN_LEN+messageLen)
-    }
-
     fun writeAll(buffer: ByteArray) {
         val byteBuffer = ByteBuffer.wrap(buffer)
         while (byteBuffer.remaining() > 0) {
@@ -187,7 +171,7 @@ class Session(private val socket: SocketChannel, val outgoing: Boolean): Selecta
     }
 
     fun encrypt(plainText: ByteArray, usePadding: Boolean): ByteArray {
-        val padded = pad(plainText, usePadding)
+        val padded = pad(plainText, paddingOverride ?: usePadding)
         val rawMsgLen = ByteBuffer.allocate(MESSAGE_LEN_LEN).putInt(padded.size+AES_TAG_LEN).array()
         val nonce = ivToNonce(applicationKeys.localIv, localCounter)
         localCounter++
@@ -196,10 +180,22 @@ class Session(private val socket: SocketChannel, val outgoing: Boolean): Selecta
         return rawMsgLen+localCipher.doFinal(padded)
     }
 
+    @Synchronized
     fun encryptAndSend(plainText: ByteArray, usePadding: Boolean) {
         writeAll(encrypt(plainText, usePadding))
     }
 
+    //called by the native cover traffic scheduler, returning false stops it
+    @Suppress("unused")
+    private fun sendDummy(record: ByteArray, usePadding: Boolean): Boolean {
+        return try {
+            encryptAndSend(record, paddingOverride ?: usePadding)
+            true
+        } catch (e: IOException) {
+            false
+        }
+    }
+
     fun ByteArray.toHexString() = joinToString("") { "%02x".format(it) }
 
     private fun readAll(buffer: ByteBuffer): Boolean {
@@ -229,7 +225,9 @@ class Session(private val socket: SocketChannel, val outgoing: Boolean): Selecta
                     rawMessageLen.position(0)
                     peerCipher.updateAAD(rawMessageLen)
                     try {
-                        return unpad(peerCipher.doFinal(cipherText.array()))
+                        val buffer = unpad(peerCipher.doFinal(cipherText.array()))
+                        //cover traffic is dropped here, the caller only gets an empty buffer
+                        return if (buffer?.firstOrNull() == Protocol.DUMMY) ByteArray(0) else buffer
                     } catch (e: AEADBadTagException) {
                         Log.w("AEADBadTagException", ip)
                     }
@@ -18,8 +18,7 @@ class SystemBroadcastReceiver: BroadcastReceiver() {
             if (PreferenceManager.getDefaultSharedPreferences(context).getBoolean("startAtBoot", true) && !AIRAService.isServiceRunning) {
                 val databaseFolder = Constants.getDatabaseFolder(context)
                 val isProtected = AIRADatabase.isIdentityProtected(databaseFolder)
-                val name = AIRADatabase.getIdentityName(databaseFolder)
-                if (name != null && !isProtected) {
+                if (AIRADatabase.identityExists(databaseFolder) && !isProtected) {
                     if (AIRADatabase.loadIdentity(databaseFolder, null)) {
                         AIRADatabase.clearCache()
                         val serviceIntent = Intent(context, AIRAService::class.java)
@@ -0,0 +1,950 @@
+use std::{convert::TryInto, fmt::Display, net::Ipv4Addr, str::FromStr, sync::{Condvar, Mutex, atomic::{AtomicBool, Ordering}}, thread, time::Duration};
+use lazy_static::lazy_static;
+use uuid::Uuid;
+use zeroize::Zeroize;
+use aira_core::{cover_traffic, discovery, print_error, crypto::{self, HandshakeKeys, ApplicationKeys}, identity::{Identity, Contact, ContactSettings, DuressAction, Group, Message, NotificationMode, PaddingPreference}, padding::{self, PaddingPolicy}, protocol};
+
+lazy_static! {
+    static ref loaded_identity: Mutex<Option<Identity>> = Mutex::new(None);
+    //bumped to stop the running cover traffic schedulers
+    static ref COVER_TRAFFIC_GENERATION: (Mutex<u64>, Condvar) = (Mutex::new(0), Condvar::new());
+}
+
+use jni::JNIEnv;
+use jni::objects::{JClass, JObject, JString, JList, JValue};
+use jni::sys::{jboolean, jint, jlong, jbyteArray, jlongArray, jobject, jobjectArray};
+
+fn jstring_to_string(env: JNIEnv, input: JString) -> String {
+    String::from(env.get_string(input).unwrap())
+}
+
+fn jstring_to_uuid(env: JNIEnv, input: JString) -> Option<Uuid> {
+    match env.get_string(input) {
+        Ok(uuid) => Some(Uuid::from_str(&String::from(uuid)).unwrap()),
+        Err(_) => None
+    }
+}
+
+fn jstring_array_to_uuids(env: JNIEnv, input: jobjectArray) -> Vec<Uuid> {
+    (0..env.get_array_length(input).unwrap()).filter_map(|i| {
+        jstring_to_uuid(env, env.get_object_array_element(input, i).unwrap().into())
+    }).collect()
+}
+
+fn jboolean_to_bool(input: jboolean) -> bool {
+    input == 1
+}
+
+fn bool_to_jboolean(input: bool) -> u8 {
+    if input { 1 } else { 0 }
+}
+
+fn result_to_jboolean<T, E: Display>(result: Result<T, E>) -> jboolean {
+    match result {
+        Ok(_) => 1,
+        Err(e) => {
+            print_error!(e);
+            0
+        }
+    }
+}
+
+static DISCOVERY_RUNNING: AtomicBool = AtomicBool::new(false);
+static DISCOVERY_CONTACTS_CHANGED: AtomicBool = AtomicBool::new(false); //the responder announces one token per contact
+
+fn slice_to_jvalue<'a>(env: JNIEnv, input: &'a [u8]) -> JValue<'a> {
+    JValue::Object(env.byte_array_from_slice(input).unwrap().into())
+}
+
+#[cfg(target_os="android")]
+#[no_mangle]
+pub extern fn Java_sushi_hardcore_aira_AIRADatabase_initLogging(_: JNIEnv, _: JClass) -> jboolean {
+    bool_to_jboolean(android_log::init("AIRA Native").is_ok())
+}
+
+#[allow(non_snake_case)]
+#[no_mangle]
+pub extern fn Java_sushi_hardcore_aira_CreateIdentityFragment_createNewIdentity(env: JNIEnv, _: JClass, database_folder: JString, name: JString, login_label: JString, encrypt_database: jboolean, password: jbyteArray) -> jboolean {
+    let database_folder = jstring_to_string(env, database_folder);
+    let name = jstring_to_string(env, name);
+    let login_label = env.get_string(login_label).ok().map(String::from);
+    match Identity::create_identidy(database_folder, &name, login_label.as_deref(), jboolean_to_bool(encrypt_database), env.convert_byte_array(password).ok().as_deref()) {
+        Ok(identity) => {
+            *loaded_identity.lock().unwrap() = Some(identity);
+            1
+        }
+        Err(e) => {
+            print_error!(e);
+            0
+        }
+    }
+}
+
+
+#[no_mangle]
+pub extern fn Java_sushi_hardcore_aira_AIRADatabase_identityExists(env: JNIEnv, _: JClass, database_folder: JString) -> jboolean {
+    match Identity::exists(&jstring_to_string(env, database_folder)) {
+        Ok(exists) => bool_to_jboolean(exists),
+        Err(e) => {
+            print_error!(e);
+            0
+        }
+    }
+}
+
+#[allow(non_snake_case)]
+#[no_mangle]
+pub fn Java_sushi_hardcore_aira_AIRADatabase_getLoginLabel(env: JNIEnv, _: JClass, database_folder: JString) -> jobject {
+    *match Identity::get_login_label(&jstring_to_string(env, database_folder)) {
+        Ok(Some(login_label)) => *env.new_string(login_label).unwrap(),
+        Ok(None) => JObject::null(),
+        Err(e) => {
+            print_error!(e);
+            JObject::null()
+        }
+    }
+}
+
+#[allow(non_snake_case)]
+#[no_mangle]
+pub fn Java_sushi_hardcore_aira_AIRADatabase_setLoginLabel(env: JNIEnv, _: JClass, database_folder: JString, login_label: JString) -> jboolean {
+    let login_label = env.get_string(login_label).ok().map(String::from);
+    result_to_jboolean(Identity::set_login_label(&jstring_to_string(env, database_folder), login_label.as_deref()))
+}
+
+#[allow(non_snake_case)]
+#[no_mangle]
+pub fn Java_sushi_hardcore_aira_AIRADatabase_getIdentityName(env: JNIEnv, _: JClass) -> jobject {
+    **env.new_string(&loaded_identity.lock().unwrap().as_ref().unwrap().name).unwrap()
+}
+
+#[no_mangle]
+pub extern fn Java_sushi_hardcore_aira_AIRADatabase_isIdentityProtected(env: JNIEnv, _: JClass, database_folder: JString) -> jboolean {
+    match Identity::is_protected(jstring_to_string(env, database_folder)) {
+        Ok(is_protected) => bool_to_jboolean(is_protected),
+        Err(e) => {
+            print_error!(e);
+            0
+        }
+    }
+}
+
+#[no_mangle]
+pub extern fn Java_sushi_hardcore_aira_AIRADatabase_loadIdentity(env: JNIEnv, _: JClass, database_folder: JString, password: jbyteArray) -> jboolean {
+    let database_folder = jstring_to_string(env, database_folder);
+    match Identity::load_identity(database_folder, env.convert_byte_array(password).ok().as_deref()) {
+        Ok(identity) => {
+            *loaded_identity.lock().unwrap() = Some(identity);
+            1
+        }
+        Err(e) => {
+            print_error!(e);
+            0
+        }
+    }
+}
+
+#[allow(non_snake_case)]
+#[no_mangle]
+pub fn Java_sushi_hardcore_aira_AIRADatabase_changePassword(env: JNIEnv, _: JClass, database_folder: JString, old_password: jbyteArray, new_password: jbyteArray) -> jboolean {
+    let database_folder = jstring_to_string(env, database_folder);
+    match Identity::change_password(database_folder, env.convert_byte_array(old_password).ok().as_deref(), env.convert_byte_array(new_password).ok().as_deref()) {
+        Ok(success) => bool_to_jboolean(success),
+        Err(e) => {
+            print_error!(e);
+            0
+        }
+    }
+}
+
+#[allow(non_snake_case)]
+#[no_mangle]
+pub fn Java_sushi_hardcore_aira_AIRADatabase_destroyIdentity(env: JNIEnv, _: JClass, database_folder: JString) -> jboolean {
+    result_to_jboolean(Identity::destroy(&jstring_to_string(env, database_folder)))
+}
+
+#[allow(non_snake_case)]
+#[no_mangle]
+pub fn Java_sushi_hardcore_aira_AIRADatabase_setDuressPassword(env: JNIEnv, _: JClass, duressPassword: jbyteArray, decoyName: JString) -> jboolean {
+    let action = match env.get_string(decoyName) {
+        Ok(decoy_name) => DuressAction::Decoy(decoy_name.into()),
+        Err(_) => DuressAction::Destroy
+    };
+    let mut duress_password = env.convert_byte_array(duressPassword).unwrap();
+    let result = loaded_identity.lock().unwrap().as_ref().unwrap().set_duress_password(&duress_password, action);
+    duress_password.zeroize();
+    result_to_jboolean(result)
+}
+
+#[allow(non_snake_case)]
+#[no_mangle]
+pub fn Java_sushi_hardcore_aira_AIRADatabase_removeDuressPassword(_: JNIEnv, _: JClass) -> jboolean {
+    result_to_jboolean(loaded_identity.lock().unwrap().as_ref().unwrap().remove_duress_password())
+}
+
+#[allow(non_snake_case)]
+#[no_mangle]
+pub fn Java_sushi_hardcore_aira_AIRADatabase_setIdentityAvatar(env: JNIEnv, _: JClass, database_folder: JString, avatar: jbyteArray) -> jboolean {
+    result_to_jboolean(Identity::set_identity_avatar(&jstring_to_string(env, database_folder), &env.convert_byte_array(avatar).unwrap()))
+}
+
+#[allow(non_snake_case)]
+#[no_mangle]
+pub fn Java_sushi_hardcore_aira_AIRADatabase_removeIdentityAvatar(env: JNIEnv, _: JClass, database_folder: JString) -> jboolean {
+    result_to_jboolean(Identity::remove_identity_avatar(&jstring_to_string(env, database_folder)))
+}
+
+#[allow(non_snake_case)]
+#[no_mangle]
+pub fn Java_sushi_hardcore_aira_AIRADatabase_getIdentityAvatar(env: JNIEnv, _: JClass, database_folder: JString) -> jbyteArray {
+    match Identity::get_identity_avatar(&jstring_to_string(env, database_folder)) {
+        Ok(avatar) => env.byte_array_from_slice(&avatar).unwrap(),
+        Err(e) => {
+            print_error!(e);
+            *JObject::null()
+        }
+    }
+}
+
+#[allow(non_snake_case)]
+#[no_mangle]
+pub fn Java_sushi_hardcore_aira_AIRADatabase_getIdentityPublicKey(env: JNIEnv, _: JClass) -> jbyteArray {
+    env.byte_array_from_slice(&loaded_identity.lock().unwrap().as_ref().unwrap().get_public_key()).unwrap()
+}
+
+#[allow(non_snake_case)]
+#[no_mangle]
+pub fn Java_sushi_hardcore_aira_background_1service_Session_sign(env: JNIEnv, _: JClass, input: jbyteArray) -> jbyteArray {
+    env.byte_array_from_slice(&loaded_identity.lock().unwrap().as_ref().unwrap().sign(&env.convert_byte_array(input).unwrap())).unwrap()
+}
+
+//contacts forcing padding get the default policy when padding is globally disabled
+#[allow(non_snake_case)]
+#[no_mangle]
+pub fn Java_sushi_hardcore_aira_background_1service_Session_pad(env: JNIEnv, _: JClass, input: jbyteArray, use_padding: jboolean) -> jbyteArray {
+    let padding_policy = if jboolean_to_bool(use_padding) {
+        match loaded_identity.lock().unwrap().as_ref().unwrap().padding_policy {
+            PaddingPolicy::None => PaddingPolicy::default(),
+            padding_policy => padding_policy
+        }
+    } else {
+        PaddingPolicy::None
+    };
+    env.byte_array_from_slice(&padding_policy.pad(&env.convert_byte_array(input).unwrap())).unwrap()
+}
+
+#[allow(non_snake_case)]
+#[no_mangle]
+pub fn Java_sushi_hardcore_aira_background_1service_Session_unpad(env: JNIEnv, _: JClass, input: jbyteArray) -> jbyteArray {
+    match padding::unpad(&env.convert_byte_array(input).unwrap()) {
+        Some(msg) => env.byte_array_from_slice(msg).unwrap(),
+        None => *JObject::null()
+    }
+}
+
+fn stop_cover_traffic() {
+    let (generation, condvar) = &*COVER_TRAFFIC_GENERATION;
+    *generation.lock().unwrap() += 1;
+    condvar.notify_all();
+}
+
+//one scheduler thread per session. It stops when the session is closed, the rate changes or the identity is released.
+#[allow(non_snake_case)]
+#[no_mangle]
+pub fn Java_sushi_hardcore_aira_background_1service_Session_startCoverTraffic(env: JNIEnv, session: JObject) {
+    let vm = env.get_java_vm().unwrap();
+    let session = env.new_global_ref(session).unwrap();
+    let started = *COVER_TRAFFIC_GENERATION.0.lock().unwrap();
+    thread::spawn(move || {
+        let env = match vm.attach_current_thread() {
+            Ok(env) => env,
+            Err(e) => {
+                print_error!(e);
+                return;
+            }
+        };
+        let (generation, condvar) = &*COVER_TRAFFIC_GENERATION;
+        loop {
+            let (delay, use_padding) = match loaded_identity.lock().unwrap().as_ref() {
+                Some(identity) => match cover_traffic::next_delay(identity.cover_traffic_rate) {
+                    Some(delay) => (delay, identity.padding_policy != PaddingPolicy::None),
+                    None => break
+                }
+                None => break
+            };
+            let wait = condvar.wait_timeout_while(generation.lock().unwrap(), Duration::from_millis(delay), |generation| *generation == started).unwrap().1;
+            if !wait.timed_out() {
+                break;
+            }
+            let dummy = protocol::dummy(&cover_traffic::generate_dummy_record());
+            match env.call_method(session.as_obj(), "sendDummy", "([BZ)Z", &[slice_to_jvalue(*env, &dummy), JValue::Bool(bool_to_jboolean(use_padding))]).and_then(|sent| sent.z()) {
+                Ok(true) => {}
+                Ok(false) => break,
+                Err(e) => {
+                    print_error!(e);
+                    break;
+                }
+            }
+        }
+    });
+}
+
+#[allow(non_snake_case)]
+#[no_mangle]
+pub fn Java_sushi_hardcore_aira_background_1service_Session_deriveHandshakeKeys(env: JNIEnv, _: JClass, shared_secret: jbyteArray, handshake_hash: jbyteArray, i_am_bob: jboolean) -> jobject {
+    let shared_secret = env.convert_byte_array(shared_secret).unwrap();
+    let handshake_hash = env.convert_byte_array(handshake_hash).unwrap();
+    let handshake_keys = HandshakeKeys::derive_keys(shared_secret.as_slice().try_into().unwrap(), handshake_hash.as_slice().try_into().unwrap(), jboolean_to_bool(i_am_bob));
+    
+    let args: Vec<JValue<'_>> = [&handshake_keys.local_key[..], &handshake_keys.local_iv, &handshake_keys.local_handshake_traffic_secret, &handshake_keys.peer_key, &handshake_keys.peer_iv, &handshake_keys.peer_handshake_traffic_secret, &handshake_keys.handshake_secret].iter().map(|field|{
+        slice_to_jvalue(env, field)
+    }).collect();
+    let handshake_keys_class = env.find_class("sushi/hardcore/aira/background_service/HandshakeKeys").unwrap();
+    *env.new_object(handshake_keys_class, "([B[B[B[B[B[B[B)V", &args).unwrap()
+}
+
+#[allow(non_snake_case)]
+#[no_mangle]
+pub fn Java_sushi_hardcore_aira_background_1service_Session_computeHandshakeFinished(env: JNIEnv, _: JClass, local_handshake_traffic_secret: jbyteArray, handshake_hash: jbyteArray) -> jbyteArray {
+    env.byte_array_from_slice(&crypto::compute_handshake_finished(env.convert_byte_array(local_handshake_traffic_secret).unwrap()[..].try_into().unwrap(), env.convert_byte_array(handshake_hash).unwrap()[..].try_into().unwrap())).unwrap()
+}
+
+#[allow(non_snake_case)]
+#[no_mangle]
+pub fn Java_sushi_hardcore_aira_background_1service_Session_verifyHandshakeFinished(env: JNIEnv, _: JClass, peer_handshake_finished: jbyteArray, peer_handshake_traffic_secret: jbyteArray, handshake_hash: jbyteArray) -> jboolean {
+    bool_to_jboolean(crypto::verify_handshake_finished(env.convert_byte_array(peer_handshake_finished).unwrap()[..].try_into().unwrap(), env.convert_byte_array(peer_handshake_traffic_secret).unwrap()[..].try_into().unwrap(), env.convert_byte_array(handshake_hash).unwrap()[..].try_into().unwrap()))
+}
+
+#[allow(non_snake_case)]
+#[no_mangle]
+pub fn Java_sushi_hardcore_aira_background_1service_Session_deriveApplicationKeys(env: JNIEnv, _: JClass, handshake_secret: jbyteArray, handshake_hash: jbyteArray, i_am_bob: jboolean) -> jobject {
+    let handshake_secret = env.convert_byte_array(handshake_secret).unwrap();
+    let handshake_hash = env.convert_byte_array(handshake_hash).unwrap();
+    let application_keys = ApplicationKeys::derive_keys(handshake_secret.as_slice().try_into().unwrap(), handshake_hash.as_slice().try_into().unwrap(), jboolean_to_bool(i_am_bob));
+    
+    let args: Vec<JValue<'_>> = [&application_keys.local_key[..], &application_keys.local_iv, &application_keys.peer_key, &application_keys.peer_iv].iter().map(|field|{
+        slice_to_jvalue(env, field)
+    }).collect();
+    let application_keys_class = env.find_class("sushi/hardcore/aira/background_service/ApplicationKeys").unwrap();
+    *env.new_object(application_keys_class, "([B[B[B[B)V", &args).unwrap()
+}
+
+#[allow(non_snake_case)]
+#[no_mangle]
+pub fn Java_sushi_hardcore_aira_background_1service_AIRAService_releaseIdentity(_: JNIEnv, _: JClass) {
+    let mut identity = loaded_identity.lock().unwrap();
+    identity.as_mut().unwrap().zeroize();
+    *identity = None;
+    drop(identity);
+    stop_cover_traffic();
+}
+
+fn new_contact(env: JNIEnv, contact: Contact) -> JObject {
+    let contact_class = env.find_class("sushi/hardcore/aira/background_service/Contact").unwrap();
+    let avatar_uuid = match contact.avatar {
+        Some(uuid) => JValue::Object(*env.new_string(uuid.to_string()).unwrap()),
+        None => JValue::Object(JObject::null())
+    };
+    env.new_object(contact_class, "(Ljava/lang/String;[BLjava/lang/String;Ljava/lang/String;ZZ)V", &[
+                   JValue::Object(*env.new_string(contact.uuid.to_string()).unwrap()),
+                   slice_to_jvalue(env, &contact.public_key),
+                   JValue::Object(*env.new_string(contact.name).unwrap()),
+                   avatar_uuid,
+                   JValue::Bool(bool_to_jboolean(contact.verified)),
+                   JValue::Bool(bool_to_jboolean(contact.seen))
+    ]).unwrap()
+}
+
+#[allow(non_snake_case)]
+#[no_mangle]
+pub fn Java_sushi_hardcore_aira_AIRADatabase_addContact(env: JNIEnv, _: JClass, name: JString, avatarUuid: JString, public_key: jbyteArray) -> jobject {
+    DISCOVERY_CONTACTS_CHANGED.store(true, Ordering::Relaxed);
+    *match loaded_identity.lock().unwrap().as_ref().unwrap().add_contact(jstring_to_string(env, name), jstring_to_uuid(env, avatarUuid), env.convert_byte_array(public_key).unwrap().try_into().unwrap()) {
+        Ok(contact) => new_contact(env, contact),
+        Err(e) => {
+            print_error!(e);
+            JObject::null()
+        }
+    }
+}
+
+#[allow(non_snake_case)]
+#[no_mangle]
+pub fn Java_sushi_hardcore_aira_AIRADatabase_removeContact(env: JNIEnv, _: JClass, uuid: JString) -> jboolean {
+    DISCOVERY_CONTACTS_CHANGED.store(true, Ordering::Relaxed);
+    result_to_jboolean(loaded_identity.lock().unwrap().as_ref().unwrap().remove_contact(&Uuid::from_str(&jstring_to_string(env, uuid)).unwrap()))
+}
+
+#[allow(non_snake_case)]
+#[no_mangle]
+pub fn Java_sushi_hardcore_aira_AIRADatabase_loadContacts(env: JNIEnv, _: JClass) -> jobject {
+    *match loaded_identity.lock().unwrap().as_ref().unwrap().load_contacts() {
+        Some(contacts) => {
+            let array_list_class = env.find_class("java/util/ArrayList").unwrap();
+            let array_list = env.new_object(array_list_class, "(I)V", &[JValue::Int(contacts.len().try_into().unwrap())]).unwrap();
+            let array_list = JList::from_env(&env, array_list).unwrap();
+            for contact in contacts {
+                array_list.add(new_contact(env, contact)).unwrap();
+            }
+            *array_list
+        }
+        None => JObject::null()
+    }
+}
+
+
+#[allow(non_snake_case)]
+#[no_mangle]
+pub fn Java_sushi_hardcore_aira_AIRADatabase_setVerified(env: JNIEnv, _: JClass, uuid: JString) -> jboolean {
+    result_to_jboolean(loaded_identity.lock().unwrap().as_ref().unwrap().set_verified(&jstring_to_uuid(env, uuid).unwrap()))
+}
+
+#[allow(non_snake_case)]
+#[no_mangle]
+pub fn Java_sushi_hardcore_aira_AIRADatabase_setUnverified(env: JNIEnv, _: JClass, uuid: JString) -> jboolean {
+    result_to_jboolean(loaded_identity.lock().unwrap().as_ref().unwrap().set_unverified(&jstring_to_uuid(env, uuid).unwrap()))
+}
+
+#[allow(non_snake_case)]
+#[no_mangle]
+pub fn Java_sushi_hardcore_aira_AIRADatabase_changeContactKey(env: JNIEnv, _: JClass, contactUuid: JString, newPublicKey: jbyteArray, timestamp: jlong) -> jboolean {
+    DISCOVERY_CONTACTS_CHANGED.store(true, Ordering::Relaxed);
+    result_to_jboolean(loaded_identity.lock().unwrap().as_ref().unwrap().change_contact_key(&jstring_to_uuid(env, contactUuid).unwrap(), env.convert_byte_array(newPublicKey).unwrap().try_into().unwrap(), timestamp as u64))
+}
+
+#[allow(non_snake_case)]
+#[no_mangle]
+pub fn Java_sushi_hardcore_aira_AIRADatabase_loadKeyHistory(env: JNIEnv, _: JClass, contactUuid: JString) -> jobject {
+    *match loaded_identity.lock().unwrap().as_ref().unwrap().load_key_history(&jstring_to_uuid(env, contactUuid).unwrap()) {
+        Ok(previous_keys) => {
+            let array_list_class = env.find_class("java/util/ArrayList").unwrap();
+            let array_list = env.new_object(array_list_class, "(I)V", &[JValue::Int(previous_keys.len().try_into().unwrap())]).unwrap();
+            let array_list = JList::from_env(&env, array_list).unwrap();
+            let previous_key_class = env.find_class("sushi/hardcore/aira/background_service/PreviousKey").unwrap();
+            for previous_key in previous_keys {
+                let previous_key_object = env.new_object(previous_key_class, "([BJ)V", &[
+                    slice_to_jvalue(env, &previous_key.public_key),
+                    JValue::Long(previous_key.timestamp as jlong),
+                ]).unwrap();
+                array_list.add(previous_key_object).unwrap();
+            }
+            *array_list
+        }
+        Err(e) => {
+            print_error!(e);
+            JObject::null()
+        }
+    }
+}
+
+#[allow(non_snake_case)]
+#[no_mangle]
+pub fn Java_sushi_hardcore_aira_AIRADatabase_getSafetyNumber(env: JNIEnv, _: JClass, peerPublicKey: jbyteArray) -> jobject {
+    **env.new_string(loaded_identity.lock().unwrap().as_ref().unwrap().get_safety_number(&env.convert_byte_array(peerPublicKey).unwrap())).unwrap()
+}
+
+#[allow(non_snake_case)]
+#[no_mangle]
+pub fn Java_sushi_hardcore_aira_AIRADatabase_getIdentityQrPayload(env: JNIEnv, _: JClass) -> jobject {
+    **env.new_string(loaded_identity.lock().unwrap().as_ref().unwrap().get_qr_payload()).unwrap()
+}
+
+#[allow(non_snake_case)]
+#[no_mangle]
+pub fn Java_sushi_hardcore_aira_AIRADatabase_verifyContactQrPayload(env: JNIEnv, _: JClass, contactUuid: JString, payload: JString) -> jboolean {
+    match loaded_identity.lock().unwrap().as_ref().unwrap().verify_contact_qr_payload(&jstring_to_uuid(env, contactUuid).unwrap(), &jstring_to_string(env, payload)) {
+        Ok(verified) => bool_to_jboolean(verified),
+        Err(e) => {
+            print_error!(e);
+            0
+        }
+    }
+}
+
+#[allow(non_snake_case)]
+#[no_mangle]
+pub fn Java_sushi_hardcore_aira_AIRADatabase_blockPeer(env: JNIEnv, _: JClass, publicKey: jbyteArray) -> jboolean {
+    result_to_jboolean(loaded_identity.lock().unwrap().as_ref().unwrap().block(&env.convert_byte_array(publicKey).unwrap()))
+}
+
+#[allow(non_snake_case)]
+#[no_mangle]
+pub fn Java_sushi_hardcore_aira_AIRADatabase_unblockPeer(env: JNIEnv, _: JClass, publicKey: jbyteArray) -> jboolean {
+    result_to_jboolean(loaded_identity.lock().unwrap().as_ref().unwrap().unblock(&env.convert_byte_array(publicKey).unwrap()))
+}
+
+//fails closed: a database error is treated as blocked
+#[allow(non_snake_case)]
+#[no_mangle]
+pub fn Java_sushi_hardcore_aira_AIRADatabase_isPeerBlocked(env: JNIEnv, _: JClass, publicKey: jbyteArray) -> jboolean {
+    match loaded_identity.lock().unwrap().as_ref().unwrap().is_blocked(&env.convert_byte_array(publicKey).unwrap()) {
+        Ok(blocked) => bool_to_jboolean(blocked),
+        Err(e) => {
+            print_error!(e);
+            1
+        }
+    }
+}
+
+#[allow(non_snake_case)]
+#[no_mangle]
+pub fn Java_sushi_hardcore_aira_AIRADatabase_loadBlockedPeers(env: JNIEnv, _: JClass) -> jobject {
+    *match loaded_identity.lock().unwrap().as_ref().unwrap().load_blocked() {
+        Ok(blocked) => {
+            let array_list_class = env.find_class("java/util/ArrayList").unwrap();
+            let array_list = env.new_object(array_list_class, "(I)V", &[JValue::Int(blocked.len().try_into().unwrap())]).unwrap();
+            let array_list = JList::from_env(&env, array_list).unwrap();
+            for public_key in blocked {
+                array_list.add(env.byte_array_from_slice(&public_key).unwrap().into()).unwrap();
+            }
+            *array_list
+        }
+        Err(e) => {
+            print_error!(e);
+            JObject::null()
+        }
+    }
+}
+
+//blocks until stopDiscoveryResponder is called
+#[allow(non_snake_case)]
+#[no_mangle]
+pub fn Java_sushi_hardcore_aira_AIRADatabase_runDiscoveryResponder(env: JNIEnv, _: JClass, interface: JString, port: jint) -> jboolean {
+    let interface = match Ipv4Addr::from_str(&jstring_to_string(env, interface)) {
+        Ok(interface) => interface,
+        Err(e) => {
+            print_error!(e);
+            return 0;
+        }
+    };
+    DISCOVERY_RUNNING.store(true, Ordering::Relaxed);
+    result_to_jboolean(discovery::Responder::new(interface, discovery::MDNS_PORT).and_then(|responder| responder.run(&DISCOVERY_RUNNING, &DISCOVERY_CONTACTS_CHANGED, |epoch| {
+        //stop if the identity has been released in the meantime
+        loaded_identity.lock().unwrap().as_ref().map(|identity| identity.get_discovery_announcement(port as u16, epoch))
+    })))
+}
+
+#[allow(non_snake_case)]
+#[no_mangle]
+pub fn Java_sushi_hardcore_aira_AIRADatabase_stopDiscoveryResponder(_: JNIEnv, _: JClass) {
+    DISCOVERY_RUNNING.store(false, Ordering::Relaxed);
+}
+
+#[allow(non_snake_case)]
+#[no_mangle]
+pub fn Java_sushi_hardcore_aira_AIRADatabase_discoverPeers(env: JNIEnv, _: JClass, interface: JString, timeout: jint) -> jobject {
+    let peers = Ipv4Addr::from_str(&jstring_to_string(env, interface)).map_err(|e| e.to_string()).and_then(|interface| {
+        discovery::Browser::new(interface, discovery::MDNS_PORT)
+            .and_then(|browser| browser.browse(Duration::from_millis(timeout as u64)))
+            .map_err(|e| e.to_string())
+    });
+    *match peers {
+        Ok(peers) => {
+            let array_list_class = env.find_class("java/util/ArrayList").unwrap();
+            let array_list = env.new_object(array_list_class, "(I)V", &[JValue::Int(peers.len().try_into().unwrap())]).unwrap();
+            let array_list = JList::from_env(&env, array_list).unwrap();
+            let discovered_peer_class = env.find_class("sushi/hardcore/aira/background_service/DiscoveredPeer").unwrap();
+            let epoch = discovery::current_epoch();
+            for peer in peers {
+                let contact_uuid = loaded_identity.lock().unwrap().as_ref().and_then(|identity| identity.find_contact_by_discovery_tokens(&peer.tokens, epoch));
+                let discovered_peer_object = env.new_object(discovered_peer_class, "(Ljava/lang/String;ILjava/lang/String;)V", &[
+                    JValue::Object(*env.new_string(peer.ip.to_string()).unwrap()),
+                    JValue::Int(peer.port as jint),
+                    match contact_uuid {
+                        Some(contact_uuid) => JValue::Object(*env.new_string(contact_uuid.to_string()).unwrap()),
+                        None => JValue::Object(JObject::null())
+                    },
+                ]).unwrap();
+                array_list.add(discovered_peer_object).unwrap();
+            }
+            *array_list
+        }
+        Err(e) => {
+            print_error!(e);
+            JObject::null()
+        }
+    }
+}
+
+#[allow(non_snake_case)]
+#[no_mangle]
+pub fn Java_sushi_hardcore_aira_AIRADatabase_getContactSettings(env: JNIEnv, _: JClass, contactUuid: JString) -> jobject {
+    *match loaded_identity.lock().unwrap().as_ref().unwrap().get_contact_settings(&jstring_to_uuid(env, contactUuid).unwrap()) {
+        Ok(settings) => {
+            let contact_settings_class = env.find_class("sushi/hardcore/aira/background_service/ContactSettings").unwrap();
+            env.new_object(contact_settings_class, "(IIJJ)V", &[
+                JValue::Int(settings.padding as jint),
+                JValue::Int(settings.notifications as jint),
+                JValue::Long(settings.auto_accept_size as jlong),
+                JValue::Long(settings.retention as jlong),
+            ]).unwrap()
+        }
+        Err(e) => {
+            print_error!(e);
+            JObject::null()
+        }
+    }
+}
+
+#[allow(non_snake_case)]
+#[no_mangle]
+pub fn Java_sushi_hardcore_aira_AIRADatabase_setContactSettings(env: JNIEnv, _: JClass, contactUuid: JString, padding: jint, notifications: jint, autoAcceptSize: jlong, retention: jlong) -> jboolean {
+    let settings = ContactSettings {
+        padding: match padding {
+            1 => PaddingPreference::Always,
+            2 => PaddingPreference::Never,
+            _ => PaddingPreference::Default
+        },
+        notifications: match notifications {
+            1 => NotificationMode::Silent,
+            2 => NotificationMode::Muted,
+            _ => NotificationMode::Default
+        },
+        auto_accept_size: autoAcceptSize as u64,
+        retention: retention as u64,
+    };
+    result_to_jboolean(loaded_identity.lock().unwrap().as_ref().unwrap().set_contact_settings(&jstring_to_uuid(env, contactUuid).unwrap(), &settings))
+}
+
+#[allow(non_snake_case)]
+#[no_mangle]
+pub fn Java_sushi_hardcore_aira_AIRADatabase_deleteExpiredMsgs(env: JNIEnv, _: JClass, contactUuid: JString, now: jlong) -> jboolean {
+    result_to_jboolean(loaded_identity.lock().unwrap().as_ref().unwrap().delete_expired_msgs(&jstring_to_uuid(env, contactUuid).unwrap(), now as u64))
+}
+
+#[allow(non_snake_case)]
+#[no_mangle]
+pub fn Java_sushi_hardcore_aira_AIRADatabase_changeContactName(env: JNIEnv, _: JClass, contactUuid: JString, newName: JString) -> jboolean {
+    result_to_jboolean(loaded_identity.lock().unwrap().as_ref().unwrap().change_contact_name(&jstring_to_uuid(env, contactUuid).unwrap(), &jstring_to_string(env, newName)))
+}
+
+#[allow(non_snake_case)]
+#[no_mangle]
+pub fn Java_sushi_hardcore_aira_AIRADatabase_setContactAvatar(env: JNIEnv, _: JClass, contactUuid: JString, avatarUuid: JString) -> jboolean {
+    result_to_jboolean(loaded_identity.lock().unwrap().as_ref().unwrap().set_contact_avatar(&jstring_to_uuid(env, contactUuid).unwrap(), jstring_to_uuid(env, avatarUuid).as_ref()))
+}
+
+#[allow(non_snake_case)]
+#[no_mangle]    
+pub fn Java_sushi_hardcore_aira_AIRADatabase_setContactSeen(env: JNIEnv, _: JClass, contactUuid: JString, seen: jboolean) -> jboolean {
+    result_to_jboolean(loaded_identity.lock().unwrap().as_ref().unwrap().set_contact_seen(&jstring_to_uuid(env, contactUuid).unwrap(), jboolean_to_bool(seen)))
+}
+
+#[allow(non_snake_case)]
+#[no_mangle]
+pub fn Java_sushi_hardcore_aira_AIRADatabase_storeMsg(env: JNIEnv, _: JClass, contactUuid: JString, outgoing: jboolean, timestamp: jlong, data: jbyteArray) -> jboolean {
+    let message = Message {
+        outgoing: jboolean_to_bool(outgoing),
+        timestamp: timestamp as u64,
+        data: env.convert_byte_array(data).unwrap(),
+    };
+    result_to_jboolean(loaded_identity.lock().unwrap().as_ref().unwrap().store_msg(&jstring_to_uuid(env, contactUuid).unwrap(), message))
+}
+
+//msgs is a List<ChatItem>. Returns the ids of the stored messages.
+#[allow(non_snake_case)]
+#[no_mangle]
+pub fn Java_sushi_hardcore_aira_AIRADatabase_storeMsgs(env: JNIEnv, _: JClass, contactUuid: JString, msgs: JObject) -> jlongArray {
+    let msgs = JList::from_env(&env, msgs).unwrap();
+    let messages: Vec<Message> = msgs.iter().unwrap().map(|chat_item| Message {
+        outgoing: env.get_field(chat_item, "outgoing", "Z").unwrap().z().unwrap(),
+        timestamp: env.get_field(chat_item, "timestamp", "J").unwrap().j().unwrap() as u64,
+        data: env.convert_byte_array(*env.get_field(chat_item, "data", "[B").unwrap().l().unwrap()).unwrap(),
+    }).collect();
+    match loaded_identity.lock().unwrap().as_ref().unwrap().store_msgs(&jstring_to_uuid(env, contactUuid).unwrap(), &messages) {
+        Ok(ids) => {
+            let array = env.new_long_array(ids.len().try_into().unwrap()).unwrap();
+            env.set_long_array_region(array, 0, &ids).unwrap();
+            array
+        }
+        Err(e) => {
+            print_error!(e);
+            *JObject::null()
+        }
+    }
+}
+
+#[allow(non_snake_case)]
+#[no_mangle]
+pub fn Java_sushi_hardcore_aira_AIRADatabase_loadMsgs(env: JNIEnv, _: JClass, uuid: JString, offset: jint, count: jint) -> jobject {
+    *match loaded_identity.lock().unwrap().as_ref().unwrap().load_msgs(&jstring_to_uuid(env, uuid).unwrap(), offset as usize, count as usize) {
+        Some(msgs) => {
+            let array_list_class = env.find_class("java/util/ArrayList").unwrap();
+            let array_list = env.new_object(array_list_class, "(I)V", &[JValue::Int(msgs.len().try_into().unwrap())]).unwrap();
+            let array_list = JList::from_env(&env, array_list).unwrap();
+            let chat_item_class = env.find_class("sushi/hardcore/aira/ChatItem").unwrap();
+            for msg in msgs {
+                let chat_item_object = env.new_object(chat_item_class, "(ZJ[B)V", &[
+                    JValue::Bool(bool_to_jboolean(msg.outgoing)),
+                    JValue::Long(msg.timestamp as jlong),
+                    slice_to_jvalue(env, &msg.data),
+                ]).unwrap();
+                array_list.add(chat_item_object).unwrap();
+            }
+            *array_list
+        }
+        None => JObject::null()
+    }
+}
+
+fn new_group(env: JNIEnv, group: Group) -> JObject {
+    let string_class = env.find_class("java/lang/String").unwrap();
+    let members = env.new_object_array(group.members.len().try_into().unwrap(), string_class, JObject::null()).unwrap();
+    for (i, member) in group.members.iter().enumerate() {
+        env.set_object_array_element(members, i.try_into().unwrap(), env.new_string(member.to_string()).unwrap()).unwrap();
+    }
+    let group_class = env.find_class("sushi/hardcore/aira/background_service/Group").unwrap();
+    env.new_object(group_class, "(Ljava/lang/String;Ljava/lang/String;[Ljava/lang/String;)V", &[
+                   JValue::Object(*env.new_string(group.uuid.to_string()).unwrap()),
+                   JValue::Object(*env.new_string(group.name).unwrap()),
+                   JValue::Object(members.into()),
+    ]).unwrap()
+}
+
+#[allow(non_snake_case)]
+#[no_mangle]
+pub fn Java_sushi_hardcore_aira_AIRADatabase_addGroup(env: JNIEnv, _: JClass, groupUuid: JString, name: JString, members: jobjectArray) -> jobject {
+    *match loaded_identity.lock().unwrap().as_ref().unwrap().add_group(jstring_to_uuid(env, groupUuid), jstring_to_string(env, name), jstring_array_to_uuids(env, members)) {
+        Ok(group) => new_group(env, group),
+        Err(e) => {
+            print_error!(e);
+            JObject::null()
+        }
+    }
+}
+
+#[allow(non_snake_case)]
+#[no_mangle]
+pub fn Java_sushi_hardcore_aira_AIRADatabase_loadGroups(env: JNIEnv, _: JClass) -> jobject {
+    *match loaded_identity.lock().unwrap().as_ref().unwrap().load_groups() {
+        Some(groups) => {
+            let array_list_class = env.find_class("java/util/ArrayList").unwrap();
+            let array_list = env.new_object(array_list_class, "(I)V", &[JValue::Int(groups.len().try_into().unwrap())]).unwrap();
+            let array_list = JList::from_env(&env, array_list).unwrap();
+            for group in groups {
+                array_list.add(new_group(env, group)).unwrap();
+            }
+            *array_list
+        }
+        None => JObject::null()
+    }
+}
+
+#[allow(non_snake_case)]
+#[no_mangle]
+pub fn Java_sushi_hardcore_aira_AIRADatabase_setGroupMembers(env: JNIEnv, _: JClass, groupUuid: JString, members: jobjectArray) -> jboolean {
+    result_to_jboolean(loaded_identity.lock().unwrap().as_ref().unwrap().set_group_members(&jstring_to_uuid(env, groupUuid).unwrap(), &jstring_array_to_uuids(env, members)))
+}
+
+#[allow(non_snake_case)]
+#[no_mangle]
+pub fn Java_sushi_hardcore_aira_AIRADatabase_changeGroupName(env: JNIEnv, _: JClass, groupUuid: JString, newName: JString) -> jboolean {
+    result_to_jboolean(loaded_identity.lock().unwrap().as_ref().unwrap().change_group_name(&jstring_to_uuid(env, groupUuid).unwrap(), &jstring_to_string(env, newName)))
+}
+
+#[allow(non_snake_case)]
+#[no_mangle]
+pub fn Java_sushi_hardcore_aira_AIRADatabase_removeGroup(env: JNIEnv, _: JClass, groupUuid: JString) -> jboolean {
+    result_to_jboolean(loaded_identity.lock().unwrap().as_ref().unwrap().remove_group(&jstring_to_uuid(env, groupUuid).unwrap()))
+}
+
+#[allow(non_snake_case)]
+#[no_mangle]
+pub fn Java_sushi_hardcore_aira_AIRADatabase_storeGroupMsg(env: JNIEnv, _: JClass, groupUuid: JString, senderUuid: JString, timestamp: jlong, data: jbyteArray) -> jboolean {
+    let sender = jstring_to_uuid(env, senderUuid);
+    let message = Message {
+        outgoing: sender.is_none(),
+        timestamp: timestamp as u64,
+        data: env.convert_byte_array(data).unwrap(),
+    };
+    result_to_jboolean(loaded_identity.lock().unwrap().as_ref().unwrap().store_group_msg(&jstring_to_uuid(env, groupUuid).unwrap(), sender.as_ref(), message))
+}
+
+#[allow(non_snake_case)]
+#[no_mangle]
+pub fn Java_sushi_hardcore_aira_AIRADatabase_loadGroupMsgs(env: JNIEnv, _: JClass, groupUuid: JString, offset: jint, count: jint) -> jobject {
+    *match loaded_identity.lock().unwrap().as_ref().unwrap().load_group_msgs(&jstring_to_uuid(env, groupUuid).unwrap(), offset as usize, count as usize) {
+        Some(msgs) => {
+            let array_list_class = env.find_class("java/util/ArrayList").unwrap();
+            let array_list = env.new_object(array_list_class, "(I)V", &[JValue::Int(msgs.len().try_into().unwrap())]).unwrap();
+            let array_list = JList::from_env(&env, array_list).unwrap();
+            let group_chat_item_class = env.find_class("sushi/hardcore/aira/GroupChatItem").unwrap();
+            for (sender, msg) in msgs {
+                let sender = match sender {
+                    Some(uuid) => JValue::Object(*env.new_string(uuid.to_string()).unwrap()),
+                    None => JValue::Object(JObject::null())
+                };
+                let group_chat_item_object = env.new_object(group_chat_item_class, "(Ljava/lang/String;J[B)V", &[
+                    sender,
+                    JValue::Long(msg.timestamp as jlong),
+                    slice_to_jvalue(env, &msg.data),
+                ]).unwrap();
+                array_list.add(group_chat_item_object).unwrap();
+            }
+            *array_list
+        }
+        None => JObject::null()
+    }
+}
+
+#[allow(non_snake_case)]
+#[no_mangle]
+pub fn Java_sushi_hardcore_aira_AIRADatabase_storeFile(env: JNIEnv, _: JClass, contactUuid: JString, data: jbyteArray) -> jbyteArray {
+    match loaded_identity.lock().unwrap().as_ref().unwrap().store_file(jstring_to_uuid(env, contactUuid), &env.convert_byte_array(data).unwrap()) {
+        Ok(uuid) => env.byte_array_from_slice(uuid.as_bytes()).unwrap(),
+        Err(_) => *JObject::null()
+    }
+}
+
+#[allow(non_snake_case)]
+#[no_mangle]
+pub fn Java_sushi_hardcore_aira_AIRADatabase_loadFile(env: JNIEnv, _: JClass, rawUuid: jbyteArray) -> jbyteArray {
+    match loaded_identity.lock().unwrap().as_ref().unwrap().load_file(Uuid::from_bytes(env.convert_byte_array(rawUuid).unwrap().try_into().unwrap())) {
+        Some(buffer) => env.byte_array_from_slice(&buffer).unwrap(),
+        None => *JObject::null()
+    }
+}
+
+#[allow(non_snake_case)]
+#[no_mangle]
+pub fn Java_sushi_hardcore_aira_AIRADatabase_deleteConversation(env: JNIEnv, _: JClass, contactUuid: JString) -> jboolean {
+    let contact_uuid = jstring_to_uuid(env, contactUuid).unwrap();
+    result_to_jboolean(loaded_identity.lock().unwrap().as_ref().unwrap().delete_conversation(&contact_uuid))
+}
+
+#[allow(non_snake_case)]
+#[no_mangle]
+pub fn Java_sushi_hardcore_aira_AIRADatabase_clearCache(_: JNIEnv, _: JClass) {
+    if let Err(e) = loaded_identity.lock().unwrap().as_ref().unwrap().clear_cache() {
+        print_error!(e);
+    }
+}
+
+#[allow(non_snake_case)]
+#[no_mangle]
+pub fn Java_sushi_hardcore_aira_AIRADatabase_rotateKeypair(_: JNIEnv, _: JClass) -> jboolean {
+    match loaded_identity.lock().unwrap().as_mut().unwrap().rotate_keypair() {
+        Ok(_) => {
+            DISCOVERY_CONTACTS_CHANGED.store(true, Ordering::Relaxed); //tokens depend on our key too
+            1
+        }
+        Err(e) => {
+            print_error!(e);
+            0
+        }
+    }
+}
+
+#[allow(non_snake_case)]
+#[no_mangle]
+pub fn Java_sushi_hardcore_aira_AIRADatabase_getKeyRotations(env: JNIEnv, _: JClass) -> jbyteArray {
+    match loaded_identity.lock().unwrap().as_ref().unwrap().get_key_rotations() {
+        Ok(key_rotations) => env.byte_array_from_slice(&key_rotations).unwrap(),
+        Err(e) => {
+            print_error!(e);
+            *JObject::null()
+        }
+    }
+}
+
+#[allow(non_snake_case)]
+#[no_mangle]
+pub fn Java_sushi_hardcore_aira_AIRADatabase_findRotatedContact(env: JNIEnv, _: JClass, keyRotations: jbyteArray, peerPublicKey: jbyteArray) -> jobject {
+    *match loaded_identity.lock().unwrap().as_ref().unwrap().find_rotated_contact(&env.convert_byte_array(keyRotations).unwrap(), &env.convert_byte_array(peerPublicKey).unwrap()) {
+        Some(uuid) => env.new_string(uuid.to_string()).unwrap().into(),
+        None => JObject::null()
+    }
+}
+
+#[allow(non_snake_case)]
+#[no_mangle]
+pub fn Java_sushi_hardcore_aira_AIRADatabase_changeName(env: JNIEnv, _: JClass, new_name: JString) -> jboolean {
+    let new_name = jstring_to_string(env, new_name);
+    match loaded_identity.lock().unwrap().as_mut().unwrap().change_name(new_name) {
+        Ok(u) => bool_to_jboolean(u == 1),
+        Err(e) => {
+            print_error!(e);
+            0
+        }
+    }
+}
+
+#[allow(non_snake_case)]
+#[no_mangle]
+pub fn Java_sushi_hardcore_aira_AIRADatabase_getPaddingPolicy(_: JNIEnv, _: JClass) -> jint {
+    loaded_identity.lock().unwrap().as_ref().unwrap().padding_policy.tag() as jint
+}
+
+#[allow(non_snake_case)]
+#[no_mangle]
+pub fn Java_sushi_hardcore_aira_AIRADatabase_setPaddingPolicy(_: JNIEnv, _: JClass, policy: jint) -> jboolean {
+    match PaddingPolicy::from_tag(policy as u8) {
+        Some(padding_policy) => result_to_jboolean(loaded_identity.lock().unwrap().as_mut().unwrap().set_padding_policy(padding_policy)),
+        None => 0
+    }
+}
+
+#[allow(non_snake_case)]
+#[no_mangle]
+pub fn Java_sushi_hardcore_aira_AIRADatabase_getCoverTrafficRate(_: JNIEnv, _: JClass) -> jint {
+    loaded_identity.lock().unwrap().as_ref().unwrap().cover_traffic_rate as jint
+}
+
+#[allow(non_snake_case)]
+#[no_mangle]
+pub fn Java_sushi_hardcore_aira_AIRADatabase_setCoverTrafficRate(_: JNIEnv, _: JClass, rate: jint) -> jboolean {
+    if rate < 0 {
+        return 0;
+    }
+    let result = loaded_identity.lock().unwrap().as_mut().unwrap().set_cover_traffic_rate(rate as u32);
+    stop_cover_traffic(); //restarted with the new rate by the service
+    result_to_jboolean(result)
+}
+
+#[allow(non_snake_case)]
+#[no_mangle]
+pub fn Java_sushi_hardcore_aira_AIRADatabase_getEncryptDatabase(_: JNIEnv, _: JClass) -> jboolean {
+    bool_to_jboolean(loaded_identity.lock().unwrap().as_ref().unwrap().encrypt_database)
+}
+
+#[allow(non_snake_case)]
+#[no_mangle]
+pub fn Java_sushi_hardcore_aira_AIRADatabase_setEncryptDatabase(_: JNIEnv, _: JClass, encrypt_database: jboolean) -> jboolean {
+    result_to_jboolean(loaded_identity.lock().unwrap().as_mut().unwrap().set_encrypt_database(jboolean_to_bool(encrypt_database)))
+}
+
+#[allow(non_snake_case)]
+#[no_mangle]
+pub fn Java_sushi_hardcore_aira_AIRADatabase_storeAvatar(env: JNIEnv, _: JClass, avatar: jbyteArray) -> jobject {
+    *match loaded_identity.lock().unwrap().as_ref().unwrap().store_avatar(&env.convert_byte_array(avatar).unwrap()) {
+        Ok(uuid) => *env.new_string(uuid.to_string()).unwrap(),
+        Err(_) => JObject::null()
+    }
+}
+
+#[allow(non_snake_case)]
+#[no_mangle]
+pub fn Java_sushi_hardcore_aira_AIRADatabase_getAvatar(env: JNIEnv, _: JClass, avatarUuid: JString) -> jbyteArray {
+    match loaded_identity.lock().unwrap().as_ref().unwrap().get_avatar(&jstring_to_uuid(env, avatarUuid).unwrap()) {
+        Some(buffer) => env.byte_array_from_slice(&buffer).unwrap(),
+        None => *JObject::null()
+    }
+}
+
+#[allow(non_snake_case)]
+#[no_mangle]
+pub fn Java_sushi_hardcore_aira_AIRADatabase_getIdentityFingerprint(env: JNIEnv, _: JClass) -> jobject {
+    **env.new_string(crypto::generate_fingerprint(&loaded_identity.lock().unwrap().as_ref().unwrap().get_public_key())).unwrap()
+}
+
+#[allow(non_snake_case)]
+#[no_mangle]
+pub fn Java_sushi_hardcore_aira_ChatActivity_generateFingerprint(env: JNIEnv, _: JClass, publicKey: jbyteArray) -> jobject {
+    **env.new_string(crypto::generate_fingerprint(&env.convert_byte_array(publicKey).unwrap())).unwrap()
+}
+
+#[allow(non_snake_case)]
+#[no_mangle]
+pub fn Java_sushi_hardcore_aira_SettingsActivity_00024MySettingsFragment_generateFingerprint(env: JNIEnv, _: JClass, publicKey: jbyteArray) -> jobject {
+    **env.new_string(crypto::generate_fingerprint(&env.convert_byte_array(publicKey).unwrap())).unwrap()
+}
@@ -11,7 +11,7 @@ else
   declare -a androidABIs=("arm64-v8a" "armeabi-v7a" "x86_64" "x86")
   declare -a targets=("aarch64-linux-android" "armv7-linux-androideabi" "x86_64-linux-android" "i686-linux-android")
   for (( i=0; i < ${#targets[@]}; i++ )) do
-    cargo build --target ${targets[i]} --release || exit 1
+    cargo build -p aira-android --target ${targets[i]} --release || exit 1
     TARGET_DIR=../jniLibs/${androidABIs[i]}
     mkdir -p $TARGET_DIR && cp target/${targets[i]}/release/libaira.so $TARGET_DIR
   done
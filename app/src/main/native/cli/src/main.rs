@@ -0,0 +1,475 @@
+use std::{collections::HashMap, convert::TryInto, env, fs::{self, File}, io::{Read, Write}, net::{IpAddr, Ipv4Addr, SocketAddr, ToSocketAddrs}, path::{Path, PathBuf}, process, sync::Arc, time::{SystemTime, UNIX_EPOCH}};
+use ed25519_dalek::PUBLIC_KEY_LENGTH;
+use tokio::{net::TcpListener, sync::mpsc::{self, UnboundedReceiver, UnboundedSender}};
+use uuid::Uuid;
+use aira_core::{crypto, identity::{Contact, Identity, Message}, net::{self, SessionEvents, SessionManager}, protocol};
+
+const USAGE: &str = "Usage: aira-cli [-d <folder>] <command> [args]
+
+Commands:
+    init <name>                       create a new identity
+    whoami                            print the identity name, public key and fingerprint
+    contacts                          list contacts
+    add-contact <name> <public key>   add a contact from its hex public key
+    messages <contact> [count]        print the last messages exchanged with a contact
+    send <address> <message>          connect to a peer and send a message
+    send-file <address> <file>        connect to a peer and send a file
+    listen [port]                     accept peers and print incoming messages
+
+The identity folder defaults to $AIRA_DIR or ~/.aira. The password, if any, is read from $AIRA_PASSWORD.
+Received files are saved in <folder>/downloads. Contacts can be given by name, UUID or fingerprint.";
+
+type PublicKey = [u8; PUBLIC_KEY_LENGTH];
+
+enum Event {
+    Opened(PublicKey, IpAddr),
+    Message(PublicKey, Vec<u8>),
+    Closed(PublicKey),
+}
+
+struct EventForwarder {
+    sender: UnboundedSender<Event>,
+    blocked: Vec<Vec<u8>>,
+}
+
+impl SessionEvents for EventForwarder {
+    fn accept(&self, peer_public_key: &PublicKey) -> bool {
+        !self.blocked.iter().any(|blocked| blocked == peer_public_key)
+    }
+    fn on_session_opened(&self, peer_public_key: &PublicKey, ip: IpAddr, _outgoing: bool) {
+        let _ = self.sender.send(Event::Opened(*peer_public_key, ip));
+    }
+    fn on_message(&self, peer_public_key: &PublicKey, message: Vec<u8>) {
+        let _ = self.sender.send(Event::Message(*peer_public_key, message));
+    }
+    fn on_session_closed(&self, peer_public_key: &PublicKey) {
+        let _ = self.sender.send(Event::Closed(*peer_public_key));
+    }
+}
+
+struct IncomingFiles {
+    files: Vec<(String, u64)>,
+    index: usize,
+    transferred: u64,
+    output: Option<(PathBuf, File)>,
+}
+
+struct Peer {
+    contact: Option<Contact>,
+    name: Option<String>,
+    incoming_files: Option<IncomingFiles>,
+}
+
+struct Node {
+    identity: Identity,
+    manager: Arc<SessionManager>,
+    events: UnboundedReceiver<Event>,
+    downloads: PathBuf,
+    peers: HashMap<PublicKey, Peer>,
+}
+
+fn fail(msg: impl std::fmt::Display) -> ! {
+    eprintln!("Error: {}", msg);
+    process::exit(1)
+}
+
+fn get_timestamp() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+}
+
+//same grouping as StringUtils.beautifyFingerprint
+fn beautify_fingerprint(fingerprint: &str) -> String {
+    fingerprint.as_bytes().chunks(4).map(|group| std::str::from_utf8(group).unwrap()).collect::<Vec<_>>().join(" ")
+}
+
+fn fingerprint(public_key: &[u8]) -> String {
+    beautify_fingerprint(&crypto::generate_fingerprint(public_key))
+}
+
+fn parse_address(address: &str) -> SocketAddr {
+    let address = if address.parse::<IpAddr>().is_ok() || !address.contains(':') {
+        format!("{}:{}", address, net::PORT)
+    } else {
+        address.to_owned()
+    };
+    match address.to_socket_addrs().ok().and_then(|mut addrs| addrs.next()) {
+        Some(addr) => addr,
+        None => fail(format!("Invalid address: {}", address)),
+    }
+}
+
+fn find_contact(identity: &Identity, query: &str) -> Contact {
+    let contacts = identity.load_contacts().unwrap_or_default();
+    let query_fingerprint = query.replace(' ', "").to_uppercase();
+    let mut matching = contacts.into_iter().filter(|contact| {
+        contact.name == query ||
+        contact.uuid.to_string() == query ||
+        (query_fingerprint.len() >= 8 && crypto::generate_fingerprint(&contact.public_key).starts_with(&query_fingerprint))
+    });
+    match (matching.next(), matching.next()) {
+        (Some(contact), None) => contact,
+        (Some(_), Some(_)) => fail(format!("\"{}\" matches several contacts", query)),
+        (None, _) => fail(format!("Contact not found: {}", query)),
+    }
+}
+
+//never trust file names sent by peers
+fn download_path(downloads: &Path, raw_file_name: &str) -> PathBuf {
+    let file_name = Path::new(raw_file_name).file_name().and_then(|name| name.to_str()).unwrap_or("file");
+    let mut path = downloads.join(file_name);
+    let mut n = 1;
+    while path.exists() {
+        path = downloads.join(format!("{} ({})", file_name, n));
+        n += 1;
+    }
+    path
+}
+
+impl Node {
+    fn new(identity: Identity, database_folder: &str) -> Node {
+        let (sender, events) = mpsc::unbounded_channel();
+        let blocked = identity.load_blocked().unwrap_or_default();
+        let manager = SessionManager::new(identity.get_keypair(), Arc::new(EventForwarder { sender, blocked }));
+        manager.set_padding_policy(identity.padding_policy);
+        manager.set_cover_traffic_rate(identity.cover_traffic_rate);
+        Node {
+            identity,
+            manager,
+            events,
+            downloads: Path::new(database_folder).join("downloads"),
+            peers: HashMap::new(),
+        }
+    }
+
+    fn display_name(&self, public_key: &PublicKey) -> String {
+        match self.peers.get(public_key) {
+            Some(Peer { contact: Some(contact), .. }) => contact.name.clone(),
+            Some(Peer { name: Some(name), .. }) => format!("{} ({})", name, fingerprint(public_key)),
+            _ => fingerprint(public_key),
+        }
+    }
+
+    fn save_msg(&self, public_key: &PublicKey, outgoing: bool, data: Vec<u8>) {
+        if let Some(Peer { contact: Some(contact), .. }) = self.peers.get(public_key) {
+            if let Err(e) = self.identity.store_msg(&contact.uuid, Message { outgoing, timestamp: get_timestamp(), data }) {
+                eprintln!("Failed to store message: {}", e);
+            }
+        }
+    }
+
+    //FILE messages are stored as: FILE || file UUID || file name
+    fn save_file(&self, public_key: &PublicKey, outgoing: bool, file_name: &[u8], content: &[u8]) {
+        if let Some(Peer { contact: Some(contact), .. }) = self.peers.get(public_key) {
+            match self.identity.store_file(Some(contact.uuid), content) {
+                Ok(file_uuid) => self.save_msg(public_key, outgoing, [&[protocol::FILE], &file_uuid.as_bytes()[..], file_name].concat()),
+                Err(e) => eprintln!("Failed to store file: {}", e),
+            }
+        }
+    }
+
+    async fn connect(&mut self, addr: SocketAddr) -> PublicKey {
+        match self.manager.connect(addr).await {
+            Ok(public_key) => {
+                //process the Opened event so that the peer is known
+                while !self.peers.contains_key(&public_key) {
+                    self.next_event().await;
+                }
+                public_key
+            }
+            Err(e) => fail(format!("Connection to {} failed: {}", addr, e)),
+        }
+    }
+
+    fn send(&self, public_key: &PublicKey, message: &[u8]) {
+        if !self.manager.send(public_key, message) {
+            fail(format!("Disconnected from {}", self.display_name(public_key)));
+        }
+    }
+
+    async fn disconnect(&mut self, public_key: &PublicKey) {
+        self.manager.disconnect(public_key);
+        while self.peers.contains_key(public_key) {
+            self.next_event().await;
+        }
+    }
+
+    //handles the next event and returns it for callers waiting for specific messages
+    async fn next_event(&mut self) -> Event {
+        let event = self.events.recv().await.unwrap();
+        self.handle_event(&event);
+        event
+    }
+
+    fn handle_event(&mut self, event: &Event) {
+        match event {
+            Event::Opened(public_key, ip) => {
+                let contact = self.identity.load_contacts().unwrap_or_default().into_iter().find(|contact| &contact.public_key == public_key);
+                let is_contact = contact.is_some();
+                self.peers.insert(*public_key, Peer { contact, name: None, incoming_files: None });
+                eprintln!("Connected to {} ({})", self.display_name(public_key), ip);
+                if !is_contact {
+                    self.manager.send(public_key, &[protocol::ASK_PROFILE_INFO]);
+                }
+            }
+            Event::Closed(public_key) => {
+                eprintln!("Disconnected from {}", self.display_name(public_key));
+                self.peers.remove(public_key);
+            }
+            Event::Message(public_key, buffer) => {
+                if !buffer.is_empty() {
+                    self.handle_message(public_key, buffer);
+                }
+            }
+        }
+    }
+
+    fn handle_message(&mut self, public_key: &PublicKey, buffer: &[u8]) {
+        match buffer[0] {
+            protocol::MESSAGE => {
+                println!("[{}] {}", self.display_name(public_key), String::from_utf8_lossy(&buffer[1..]));
+                self.save_msg(public_key, false, buffer.to_vec());
+            }
+            protocol::FILE => {
+                if let Some((raw_file_name, content)) = protocol::parse_small_file(buffer) {
+                    let path = download_path(&self.downloads, &String::from_utf8_lossy(raw_file_name));
+                    match fs::create_dir_all(&self.downloads).and_then(|_| fs::write(&path, content)) {
+                        Ok(_) => println!("[{}] file saved to {}", self.display_name(public_key), path.display()),
+                        Err(e) => eprintln!("Failed to save {}: {}", path.display(), e),
+                    }
+                    self.save_file(public_key, false, raw_file_name, content);
+                }
+            }
+            protocol::ASK_PROFILE_INFO => {
+                self.manager.send(public_key, &protocol::name(&self.identity.name));
+            }
+            protocol::NAME => {
+                let name = String::from_utf8_lossy(&buffer[1..]).into_owned();
+                if let Some(peer) = self.peers.get_mut(public_key) {
+                    peer.name = Some(name);
+                }
+            }
+            protocol::ASK_LARGE_FILES => self.handle_ask_large_files(public_key, buffer),
+            protocol::LARGE_FILE_CHUNK => self.handle_large_file_chunk(public_key, &buffer[1..]),
+            protocol::ABORT_FILES_TRANSFER => {
+                if let Some(peer) = self.peers.get_mut(public_key) {
+                    if let Some(IncomingFiles { output: Some((path, _)), .. }) = peer.incoming_files.take() {
+                        let _ = fs::remove_file(path);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    //files are only accepted from contacts
+    fn handle_ask_large_files(&mut self, public_key: &PublicKey, buffer: &[u8]) {
+        let files = protocol::parse_ask_files(buffer);
+        let name = self.display_name(public_key);
+        let accepted = match (self.peers.get_mut(public_key), files) {
+            (Some(peer), Some(files)) if peer.contact.is_some() && peer.incoming_files.is_none() => {
+                for (file_name, file_size) in &files {
+                    eprintln!("Receiving {} ({} bytes) from {}", file_name, file_size, name);
+                }
+                peer.incoming_files = Some(IncomingFiles { files, index: 0, transferred: 0, output: None });
+                true
+            }
+            _ => false,
+        };
+        self.manager.send(public_key, &[if accepted { protocol::ACCEPT_LARGE_FILES } else { protocol::ABORT_FILES_TRANSFER }]);
+    }
+
+    fn handle_large_file_chunk(&mut self, public_key: &PublicKey, chunk: &[u8]) {
+        let name = self.display_name(public_key);
+        let downloads = self.downloads.clone();
+        let incoming_files = match self.peers.get_mut(public_key).and_then(|peer| peer.incoming_files.as_mut()) {
+            Some(incoming_files) => incoming_files,
+            None => return,
+        };
+        if incoming_files.output.is_none() {
+            let path = download_path(&downloads, &incoming_files.files[incoming_files.index].0);
+            match fs::create_dir_all(&downloads).and_then(|_| File::create(&path)) {
+                Ok(file) => incoming_files.output = Some((path, file)),
+                Err(e) => eprintln!("Failed to create {}: {}", path.display(), e),
+            }
+        }
+        let written = match incoming_files.output.as_mut() {
+            Some((_, file)) => file.write_all(chunk).is_ok(),
+            None => false,
+        };
+        if !written {
+            self.peers.get_mut(public_key).unwrap().incoming_files = None;
+            self.manager.send(public_key, &[protocol::ABORT_FILES_TRANSFER]);
+            return;
+        }
+        self.manager.send(public_key, &[protocol::ACK_CHUNK]);
+        incoming_files.transferred += chunk.len() as u64;
+        if incoming_files.transferred >= incoming_files.files[incoming_files.index].1 {
+            let (path, _) = incoming_files.output.take().unwrap();
+            println!("[{}] file saved to {}", name, path.display());
+            incoming_files.index += 1;
+            incoming_files.transferred = 0;
+            if incoming_files.index == incoming_files.files.len() {
+                self.peers.get_mut(public_key).unwrap().incoming_files = None;
+            }
+        }
+    }
+
+    //waits for one of the expected message types, fails if the peer aborts or leaves
+    async fn wait_for(&mut self, public_key: &PublicKey, expected: u8) {
+        loop {
+            match self.next_event().await {
+                Event::Message(sender, buffer) if &sender == public_key && !buffer.is_empty() => {
+                    if buffer[0] == expected {
+                        return;
+                    } else if buffer[0] == protocol::ABORT_FILES_TRANSFER {
+                        fail("Transfer aborted by peer");
+                    }
+                }
+                Event::Closed(peer) if &peer == public_key => fail("Peer disconnected"),
+                _ => {}
+            }
+        }
+    }
+
+    async fn send_file(&mut self, public_key: &PublicKey, path: &Path) {
+        let file_name = path.file_name().and_then(|name| name.to_str()).unwrap_or_else(|| fail("Invalid file name")).to_owned();
+        let mut file = File::open(path).unwrap_or_else(|e| fail(format!("Can't open {}: {}", path.display(), e)));
+        let file_size = file.metadata().unwrap_or_else(|e| fail(e)).len();
+        if file_size <= protocol::FILE_SIZE_LIMIT {
+            let mut content = Vec::new();
+            file.read_to_end(&mut content).unwrap_or_else(|e| fail(e));
+            self.send(public_key, &protocol::new_file(&file_name, &content));
+            self.save_file(public_key, true, file_name.as_bytes(), &content);
+        } else {
+            self.send(public_key, &protocol::ask_large_files(&[(file_name, file_size)]));
+            eprintln!("Waiting for {} to accept the file...", self.display_name(public_key));
+            self.wait_for(public_key, protocol::ACCEPT_LARGE_FILES).await;
+            let mut chunk = vec![0; protocol::FILE_CHUNK_SIZE-1];
+            let mut transferred = 0;
+            while transferred < file_size {
+                let read = file.read(&mut chunk).unwrap_or_else(|e| fail(e));
+                if read == 0 {
+                    fail("File truncated while sending");
+                }
+                self.send(public_key, &protocol::large_file_chunk(&chunk[..read]));
+                self.wait_for(public_key, protocol::ACK_CHUNK).await;
+                transferred += read as u64;
+                eprint!("\r{}/{} bytes", transferred, file_size);
+            }
+            eprintln!();
+        }
+    }
+}
+
+fn get_password() -> Option<Vec<u8>> {
+    env::var("AIRA_PASSWORD").ok().filter(|password| !password.is_empty()).map(String::into_bytes)
+}
+
+fn load_identity(database_folder: &str) -> Identity {
+    match Identity::exists(database_folder) {
+        Ok(true) => {}
+        _ => fail(format!("No identity found in {}. Create one with \"aira-cli init <name>\"", database_folder)),
+    }
+    let password = get_password();
+    if password.is_none() && Identity::is_protected(database_folder.to_owned()).unwrap_or(false) {
+        fail("This identity is protected by a password. Set it in $AIRA_PASSWORD");
+    }
+    Identity::load_identity(database_folder.to_owned(), password.as_deref()).unwrap_or_else(|e| fail(e))
+}
+
+#[tokio::main]
+async fn main() {
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let mut database_folder = env::var("AIRA_DIR").unwrap_or_else(|_| {
+        Path::new(&env::var("HOME").unwrap_or_else(|_| ".".to_owned())).join(".aira").to_str().unwrap().to_owned()
+    });
+    if args.len() >= 2 && (args[0] == "-d" || args[0] == "--dir") {
+        database_folder = args.remove(1);
+        args.remove(0);
+    }
+    if args.is_empty() || args[0] == "-h" || args[0] == "--help" {
+        println!("{}", USAGE);
+        return;
+    }
+    let arg = |i: usize| -> &str {
+        match args.get(i) {
+            Some(arg) => arg,
+            None => {
+                eprintln!("{}", USAGE);
+                process::exit(2)
+            }
+        }
+    };
+    match arg(0) {
+        "init" => {
+            if Identity::exists(&database_folder).unwrap_or(false) {
+                fail(format!("An identity already exists in {}", database_folder));
+            }
+            fs::create_dir_all(&database_folder).unwrap_or_else(|e| fail(e));
+            let password = get_password();
+            let identity = Identity::create_identidy(database_folder.clone(), arg(1), None, password.is_some(), password.as_deref()).unwrap_or_else(|e| fail(e));
+            println!("{}", fingerprint(&identity.get_public_key()));
+        }
+        "whoami" => {
+            let identity = load_identity(&database_folder);
+            println!("Name: {}", identity.name);
+            println!("Public key: {}", hex::encode(identity.get_public_key()));
+            println!("Fingerprint: {}", fingerprint(&identity.get_public_key()));
+        }
+        "contacts" => {
+            let identity = load_identity(&database_folder);
+            for contact in identity.load_contacts().unwrap_or_default() {
+                println!("{}\t{}\t{}{}", contact.uuid, contact.name, fingerprint(&contact.public_key), if contact.verified { "\tverified" } else { "" });
+            }
+        }
+        "add-contact" => {
+            let identity = load_identity(&database_folder);
+            let public_key: PublicKey = hex::decode(arg(2)).ok().and_then(|key| key.try_into().ok()).unwrap_or_else(|| fail("Invalid public key"));
+            let contact = identity.add_contact(arg(1).to_owned(), None, public_key).unwrap_or_else(|e| fail(e));
+            println!("{}", contact.uuid);
+        }
+        "messages" => {
+            let identity = load_identity(&database_folder);
+            let contact = find_contact(&identity, arg(1));
+            let count = args.get(2).map(|count| count.parse().unwrap_or_else(|_| fail("Invalid count"))).unwrap_or(20);
+            for msg in identity.load_msgs(&contact.uuid, 0, count).unwrap_or_default() {
+                let sender = if msg.outgoing { "me" } else { &contact.name };
+                match msg.data.first() {
+                    Some(&protocol::MESSAGE) => println!("{}\t[{}] {}", msg.timestamp, sender, String::from_utf8_lossy(&msg.data[1..])),
+                    Some(&protocol::FILE) if msg.data.len() > 17 => {
+                        let file_uuid = Uuid::from_slice(&msg.data[1..17]).unwrap();
+                        println!("{}\t[{}] file {} ({})", msg.timestamp, sender, String::from_utf8_lossy(&msg.data[17..]), file_uuid);
+                    }
+                    _ => {}
+                }
+            }
+        }
+        "send" => {
+            let mut node = Node::new(load_identity(&database_folder), &database_folder);
+            arg(2);
+            let public_key = node.connect(parse_address(arg(1))).await;
+            let message = protocol::new_message(&args[2..].join(" "));
+            node.send(&public_key, &message);
+            node.save_msg(&public_key, true, message);
+            node.disconnect(&public_key).await;
+        }
+        "send-file" => {
+            let path = PathBuf::from(arg(2));
+            let mut node = Node::new(load_identity(&database_folder), &database_folder);
+            let public_key = node.connect(parse_address(arg(1))).await;
+            node.send_file(&public_key, &path).await;
+            node.disconnect(&public_key).await;
+        }
+        "listen" => {
+            let port = args.get(1).map(|port| port.parse().unwrap_or_else(|_| fail("Invalid port"))).unwrap_or(net::PORT);
+            let mut node = Node::new(load_identity(&database_folder), &database_folder);
+            let listener = TcpListener::bind((Ipv4Addr::UNSPECIFIED, port)).await.unwrap_or_else(|e| fail(e));
+            eprintln!("Listening on port {} as {}", port, fingerprint(&node.identity.get_public_key()));
+            tokio::spawn(node.manager.clone().serve(listener));
+            loop {
+                node.next_event().await;
+            }
+        }
+        command => fail(format!("Unknown command: {}", command)),
+    }
+}
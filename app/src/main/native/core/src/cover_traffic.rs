@@ -0,0 +1,48 @@
+use rand::{Rng, RngCore, rngs::OsRng};
+
+pub const COVER_TRAFFIC_RATE_LEN: usize = 4;
+const MAX_DUMMY_RECORD_LEN: usize = 256;
+const MS_PER_HOUR: f64 = 3_600_000.;
+
+//dummy records form a Poisson process: the delays between them are exponentially distributed with mean 1/rate
+pub fn next_delay(rate: u32) -> Option<u64> {
+    if rate == 0 {
+        return None;
+    }
+    let uniform: f64 = OsRng.gen_range(f64::EPSILON..1.);
+    Some((-uniform.ln()*MS_PER_HOUR/rate as f64) as u64)
+}
+
+//random length so that dummies can't be told apart from short messages when padding is disabled
+pub fn generate_dummy_record() -> Vec<u8> {
+    let mut record = vec![0; OsRng.gen_range(1..=MAX_DUMMY_RECORD_LEN)];
+    OsRng.fill_bytes(&mut record);
+    record
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delays() {
+        assert_eq!(next_delay(0), None);
+        let rate = 3600; //one per second
+        let delays: Vec<u64> = (0..10000).map(|_| next_delay(rate).unwrap()).collect();
+        //the uniform sample is at least EPSILON so delays are bounded
+        let max_delay = (-f64::EPSILON.ln()*MS_PER_HOUR/rate as f64) as u64;
+        assert!(delays.iter().all(|delay| *delay <= max_delay));
+        let mean = delays.iter().sum::<u64>() as f64/delays.len() as f64;
+        assert!((950. ..1050.).contains(&mean), "{}", mean);
+        //P(X < mean) = 1-1/e for an exponential distribution
+        let below_mean = delays.iter().filter(|delay| **delay < 1000).count() as f64/delays.len() as f64;
+        assert!((0.6..0.66).contains(&below_mean), "{}", below_mean);
+    }
+
+    #[test]
+    fn dummy_records() {
+        let records: Vec<Vec<u8>> = (0..100).map(|_| generate_dummy_record()).collect();
+        assert!(records.iter().all(|record| (1..=MAX_DUMMY_RECORD_LEN).contains(&record.len())));
+        assert!(records.iter().any(|record| record.len() != records[0].len()));
+    }
+}
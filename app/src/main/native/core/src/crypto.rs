@@ -0,0 +1,528 @@
+use std::{convert::TryInto, fmt::Display};
+use hkdf::Hkdf;
+use sha2::Sha384;
+use hmac::{Hmac, Mac};
+use scrypt::{scrypt, Params};
+use rand::{RngCore, rngs::OsRng};
+use aes_gcm::{aead::Aead, NewAead, Nonce};
+use aes_gcm_siv::Aes256GcmSiv;
+use zeroize::Zeroize;
+use ed25519_dalek::{ExpandedSecretKey, SecretKey, PUBLIC_KEY_LENGTH};
+use curve25519_dalek::{edwards::CompressedEdwardsY, scalar::Scalar};
+
+pub const HASH_OUTPUT_LEN: usize = 48; //SHA384
+pub const KEY_LEN: usize = 16;
+pub const IV_LEN: usize = 12;
+pub const AES_TAG_LEN: usize = 16;
+pub const SALT_LEN: usize = 32;
+const PASSWORD_HASH_LEN: usize = 32;
+pub const MASTER_KEY_LEN: usize = 32;
+pub const FINGERPRINT_LEN: usize = 16;
+pub const DISCOVERY_TOKEN_LEN: usize = 16;
+pub const LOOKUP_KEY_LEN: usize = 16;
+const SAFETY_NUMBER_GROUPS: usize = 12;
+
+fn hkdf_expand_label(key: &[u8], label: &str, context: Option<&[u8]>, okm: &mut [u8]) {
+    let hkdf = Hkdf::<Sha384>::from_prk(key).unwrap();
+    //can't set info conditionnally because of different array size
+    match context {
+        Some(context) => {
+            let info = [&(label.len() as u32).to_be_bytes(), label.as_bytes(), &(context.len() as u32).to_be_bytes(), context];
+            hkdf.expand_multi_info(&info, okm).unwrap();
+        }
+        None => {
+            let info = [&(label.len() as u32).to_be_bytes(), label.as_bytes()];
+            hkdf.expand_multi_info(&info, okm).unwrap();
+        }
+    };    
+}
+
+fn get_labels(handshake: bool, i_am_bob: bool) -> (String, String) {
+    let mut label = if handshake {
+        "handshake"
+    } else {
+        "application"
+    }.to_owned();
+    label += "_i_am_";
+    let local_label = label.clone() + if i_am_bob {
+        "bob"
+    } else {
+        "alice"
+    };
+    let peer_label = label + if i_am_bob {
+        "alice"
+    } else {
+        "bob"
+    };
+    (local_label, peer_label)
+}
+
+pub struct HandshakeKeys {
+    pub local_key: [u8; KEY_LEN],
+    pub local_iv: [u8; IV_LEN],
+    pub local_handshake_traffic_secret: [u8; HASH_OUTPUT_LEN],
+    pub peer_key: [u8; KEY_LEN],
+    pub peer_iv: [u8; IV_LEN],
+    pub peer_handshake_traffic_secret: [u8; HASH_OUTPUT_LEN],
+    pub handshake_secret: [u8; HASH_OUTPUT_LEN],
+}
+
+impl HandshakeKeys {
+    pub fn derive_keys(shared_secret: [u8; 32], handshake_hash: [u8; HASH_OUTPUT_LEN], i_am_bob: bool) -> HandshakeKeys {
+        let (handshake_secret, _) = Hkdf::<Sha384>::extract(None, &shared_secret);
+
+        let (local_label, peer_label) = get_labels(true, i_am_bob);
+
+        let mut local_handshake_traffic_secret = [0; HASH_OUTPUT_LEN];
+        hkdf_expand_label(handshake_secret.as_slice(), &local_label, Some(&handshake_hash), &mut local_handshake_traffic_secret);
+
+        let mut peer_handshake_traffic_secret = [0; HASH_OUTPUT_LEN];
+        hkdf_expand_label(handshake_secret.as_slice(), &peer_label, Some(&handshake_hash), &mut peer_handshake_traffic_secret);
+
+        let mut local_handshake_key = [0; KEY_LEN];
+        hkdf_expand_label(&local_handshake_traffic_secret, "key", None, &mut local_handshake_key);
+        let mut local_handshake_iv = [0; IV_LEN];
+        hkdf_expand_label(&local_handshake_traffic_secret, "iv", None, &mut local_handshake_iv);
+    
+        let mut peer_handshake_key = [0; KEY_LEN];
+        hkdf_expand_label(&peer_handshake_traffic_secret, "key", None, &mut peer_handshake_key);
+        let mut peer_handshake_iv = [0; IV_LEN];
+        hkdf_expand_label(&peer_handshake_traffic_secret,"iv", None, &mut peer_handshake_iv);
+
+        HandshakeKeys {
+            local_key: local_handshake_key,
+            local_iv: local_handshake_iv,
+            local_handshake_traffic_secret,
+            peer_key: peer_handshake_key,
+            peer_iv: peer_handshake_iv,
+            peer_handshake_traffic_secret,
+            handshake_secret: handshake_secret.as_slice().try_into().unwrap(),
+        }
+    }
+}
+
+pub struct ApplicationKeys {
+    pub local_key: [u8; KEY_LEN],
+    pub local_iv: [u8; IV_LEN],
+    pub peer_key: [u8; KEY_LEN],
+    pub peer_iv: [u8; IV_LEN],
+}
+
+impl ApplicationKeys {
+    pub fn derive_keys(handshake_secret: [u8; HASH_OUTPUT_LEN], handshake_hash: [u8; HASH_OUTPUT_LEN], i_am_bob: bool) -> ApplicationKeys {
+        let mut derived_secret = [0; HASH_OUTPUT_LEN];
+        hkdf_expand_label(&handshake_secret, "derived", None, &mut derived_secret);
+        let (master_secret, _) = Hkdf::<Sha384>::extract(Some(&derived_secret), b"");
+
+        let (local_label, peer_label) = get_labels(false, i_am_bob);
+
+        let mut local_application_traffic_secret = [0; HASH_OUTPUT_LEN];
+        hkdf_expand_label(&master_secret, &local_label, Some(&handshake_hash), &mut local_application_traffic_secret);
+    
+        let mut peer_application_traffic_secret = [0; HASH_OUTPUT_LEN];
+        hkdf_expand_label(&master_secret, &peer_label, Some(&handshake_hash), &mut peer_application_traffic_secret);
+
+        let mut local_application_key = [0; KEY_LEN];
+        hkdf_expand_label(&local_application_traffic_secret, "key", None, &mut local_application_key);
+        let mut local_application_iv = [0; IV_LEN];
+        hkdf_expand_label(&local_application_traffic_secret, "iv", None, &mut local_application_iv);
+    
+        let mut peer_application_key = [0; KEY_LEN];
+        hkdf_expand_label(&peer_application_traffic_secret, "key", None, &mut peer_application_key);
+        let mut peer_application_iv = [0; IV_LEN];
+        hkdf_expand_label(&peer_application_traffic_secret,"iv", None, &mut peer_application_iv);
+
+        ApplicationKeys {
+            local_key: local_application_key,
+            local_iv: local_application_iv,
+            peer_key: peer_application_key,
+            peer_iv: peer_application_iv,
+        }
+    }
+}
+
+pub fn compute_handshake_finished(local_handshake_traffic_secret: [u8; HASH_OUTPUT_LEN], handshake_hash: [u8; HASH_OUTPUT_LEN]) -> [u8; HASH_OUTPUT_LEN] {
+    let mut finished_key = [0; HASH_OUTPUT_LEN];
+    hkdf_expand_label(&local_handshake_traffic_secret, "finished", None, &mut finished_key);
+    let mut hmac = Hmac::<Sha384>::new_from_slice(&finished_key).unwrap();
+    hmac.update(&handshake_hash);
+    hmac.finalize().into_bytes().as_slice().try_into().unwrap()
+}
+
+pub fn verify_handshake_finished(peer_handshake_finished: [u8; HASH_OUTPUT_LEN], peer_handshake_traffic_secret: [u8; HASH_OUTPUT_LEN], handshake_hash: [u8; HASH_OUTPUT_LEN]) -> bool {
+    let mut peer_finished_key = [0; HASH_OUTPUT_LEN];
+    hkdf_expand_label(&peer_handshake_traffic_secret, "finished", None, &mut peer_finished_key);
+    let mut hmac = Hmac::<Sha384>::new_from_slice(&peer_finished_key).unwrap();
+    hmac.update(&handshake_hash);
+    hmac.verify_slice(&peer_handshake_finished).is_ok()
+}
+
+
+
+pub fn compute_fingerprint(public_key: &[u8]) -> [u8; FINGERPRINT_LEN] {
+    let mut raw_fingerprint = [0; FINGERPRINT_LEN];
+    Hkdf::<Sha384>::new(None, public_key).expand(&[], &mut raw_fingerprint).unwrap();
+    raw_fingerprint
+}
+
+pub fn generate_fingerprint(public_key: &[u8]) -> String {
+    hex::encode(compute_fingerprint(public_key)).to_uppercase()
+}
+
+//keys are sorted so that both peers compute the same number
+pub fn generate_safety_number(local_public_key: &[u8], peer_public_key: &[u8]) -> String {
+    let (first, second) = if local_public_key <= peer_public_key {
+        (local_public_key, peer_public_key)
+    } else {
+        (peer_public_key, local_public_key)
+    };
+    let mut raw_safety_number = [0; SAFETY_NUMBER_GROUPS*5];
+    Hkdf::<Sha384>::new(None, &[first, second].concat()).expand(b"safety_number", &mut raw_safety_number).unwrap();
+    raw_safety_number.chunks(5).map(|chunk| {
+        let mut group = [0; 8];
+        group[3..].copy_from_slice(chunk);
+        format!("{:05}", u64::from_be_bytes(group)%100000)
+    }).collect::<Vec<String>>().join(" ")
+}
+
+//static Diffie-Hellman between the Ed25519 identity keys: a*(b*G) = b*(a*G)
+pub fn derive_pairwise_secret(secret_key: &SecretKey, local_public_key: &[u8], peer_public_key: &[u8]) -> Option<[u8; MASTER_KEY_LEN]> {
+    if peer_public_key.len() != PUBLIC_KEY_LENGTH {
+        return None;
+    }
+    let peer_point = CompressedEdwardsY::from_slice(peer_public_key).decompress()?;
+    if peer_point.is_small_order() {
+        return None;
+    }
+    let mut expanded_secret_key = ExpandedSecretKey::from(secret_key).to_bytes();
+    let scalar = Scalar::from_bits(expanded_secret_key[..32].try_into().unwrap()); //already clamped
+    expanded_secret_key.zeroize();
+    let mut shared_secret = (scalar*peer_point).compress().to_bytes();
+    let (first, second) = if local_public_key <= peer_public_key {
+        (local_public_key, peer_public_key)
+    } else {
+        (peer_public_key, local_public_key)
+    };
+    let mut pairwise_secret = [0; MASTER_KEY_LEN];
+    Hkdf::<Sha384>::new(Some(&[first, second].concat()), &shared_secret).expand(b"pairwise_secret", &mut pairwise_secret).unwrap();
+    shared_secret.zeroize();
+    Some(pairwise_secret)
+}
+
+//bound to the announcer, otherwise both contacts would announce the same token and could be linked
+pub fn compute_discovery_token(pairwise_secret: &[u8], announcer_public_key: &[u8], epoch: u64) -> [u8; DISCOVERY_TOKEN_LEN] {
+    let mut hmac = Hmac::<Sha384>::new_from_slice(pairwise_secret).unwrap();
+    hmac.update(b"discovery_token");
+    hmac.update(announcer_public_key);
+    hmac.update(&epoch.to_be_bytes());
+    hmac.finalize().into_bytes()[..DISCOVERY_TOKEN_LEN].try_into().unwrap()
+}
+
+//deterministic, so only stored for values unique in their table: equal lookup keys would link rows together
+pub fn compute_lookup_key(master_key: &[u8], value: &[u8]) -> [u8; LOOKUP_KEY_LEN] {
+    let mut lookup_key = [0; MASTER_KEY_LEN];
+    Hkdf::<Sha384>::new(None, master_key).expand(b"lookup", &mut lookup_key).unwrap();
+    let mut hmac = Hmac::<Sha384>::new_from_slice(&lookup_key).unwrap();
+    lookup_key.zeroize();
+    hmac.update(value);
+    hmac.finalize().into_bytes()[..LOOKUP_KEY_LEN].try_into().unwrap()
+}
+
+pub fn derive_database_key(master_key: &[u8]) -> [u8; MASTER_KEY_LEN] {
+    let mut database_key = [0; MASTER_KEY_LEN];
+    Hkdf::<Sha384>::new(None, master_key).expand(b"database", &mut database_key).unwrap();
+    database_key
+}
+
+pub fn generate_master_key() -> [u8; MASTER_KEY_LEN] {
+    let mut master_key = [0; MASTER_KEY_LEN];
+    OsRng.fill_bytes(&mut master_key);
+    master_key
+}
+
+pub fn encrypt_data(data: &[u8], master_key: &[u8]) -> Result<Vec<u8>, CryptoError> {
+    if master_key.len() != MASTER_KEY_LEN {
+        return Err(CryptoError::InvalidLength);
+    }
+    let cipher = Aes256GcmSiv::new_from_slice(master_key).unwrap();
+    let mut iv = [0; IV_LEN];
+    OsRng.fill_bytes(&mut iv); //use it for IV for now
+    let mut cipher_text = iv.to_vec();
+    cipher_text.extend(cipher.encrypt(Nonce::from_slice(&iv), data).unwrap());
+    Ok(cipher_text)
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum CryptoError {
+    DecryptionFailed,
+    InvalidLength
+}
+
+impl Display for CryptoError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            CryptoError::DecryptionFailed => "Decryption failed",
+            CryptoError::InvalidLength => "Invalid length",
+        })
+    }
+}
+
+impl std::error::Error for CryptoError {}
+
+pub fn decrypt_data(data: &[u8], master_key: &[u8]) -> Result<Vec<u8>, CryptoError> {
+    if data.len() <= IV_LEN || master_key.len() != MASTER_KEY_LEN {
+        return Err(CryptoError::InvalidLength);
+    }
+    let cipher = Aes256GcmSiv::new_from_slice(master_key).unwrap();
+    match cipher.decrypt(Nonce::from_slice(&data[..IV_LEN]), &data[IV_LEN..]) {
+        Ok(data) => {
+            Ok(data)
+        },
+        Err(_) => Err(CryptoError::DecryptionFailed)
+    }
+}
+
+fn scrypt_params() -> Params {
+    Params::new(16, 8, 1).unwrap()
+}
+
+pub fn encrypt_master_key(mut master_key: [u8; MASTER_KEY_LEN], password: &[u8]) -> (
+    [u8; SALT_LEN], //salt
+    [u8; IV_LEN+MASTER_KEY_LEN+AES_TAG_LEN] //encrypted masterkey with IV
+) {
+    let mut salt = [0; SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+    let mut password_hash = [0; PASSWORD_HASH_LEN];
+    scrypt(password, &salt, &scrypt_params(), &mut password_hash).unwrap();
+    let mut output = [0; IV_LEN+MASTER_KEY_LEN+AES_TAG_LEN];
+    OsRng.fill_bytes(&mut output); //use it for IV for now
+    let cipher = Aes256GcmSiv::new_from_slice(&password_hash).unwrap();
+    let encrypted_master_key = cipher.encrypt(Nonce::from_slice(&output[..IV_LEN]), master_key.as_ref()).unwrap();
+    master_key.zeroize();
+    password_hash.zeroize();
+    encrypted_master_key.into_iter().enumerate().for_each(|i|{
+        output[IV_LEN+i.0] = i.1; //append encrypted master key to IV
+    });
+    (salt, output)
+}
+
+pub fn decrypt_master_key(encrypted_master_key: &[u8], password: &[u8], salt: &[u8]) -> Result<[u8; MASTER_KEY_LEN], CryptoError> {
+    if encrypted_master_key.len() != IV_LEN+MASTER_KEY_LEN+AES_TAG_LEN || salt.len() != SALT_LEN {
+        return Err(CryptoError::InvalidLength);
+    }
+    let mut password_hash = [0; PASSWORD_HASH_LEN];
+    scrypt(password, salt, &scrypt_params(), &mut password_hash).unwrap();
+    let cipher = Aes256GcmSiv::new_from_slice(&password_hash).unwrap();
+    let result = match cipher.decrypt(Nonce::from_slice(&encrypted_master_key[..IV_LEN]), &encrypted_master_key[IV_LEN..]) {
+        Ok(master_key) => {
+            if master_key.len() == MASTER_KEY_LEN {
+                Ok(master_key.try_into().unwrap())
+            } else {
+                return Err(CryptoError::InvalidLength)
+            }
+        },
+        Err(_) => Err(CryptoError::DecryptionFailed)
+    };
+    password_hash.zeroize();
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //values from tests/vectors/gen_psec_vectors.py
+    const SHARED_SECRET: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+    const HANDSHAKE_HASH: &str = "520cde83e730ef1c04fe443dc399ded36c0f275993c190b6fc9fc11db7dae644ae6073f4371fa061a7482e6cc2e90ed7";
+    const HANDSHAKE_SECRET: &str = "79faab675751f724f82634db3212fedb20fc76151305be0d12c946a7f62a9aac22af76ea232f604fb4a30c23e291fb53";
+    const ALICE_HANDSHAKE_TRAFFIC_SECRET: &str = "4117a4df374b8e613fc39369fb422c458ab7b8098a2ae608987eb13f3854c6dd7fa04a0a4fef79ff67a60f4748eb89f7";
+    const BOB_HANDSHAKE_TRAFFIC_SECRET: &str = "ac2628802da8a0241efcd0e47e6b6116a75aadfd7e39bb4f815a001ea58051881701b98bc0fba209c2608bc0a6c85ae5";
+
+    fn from_hex<const N: usize>(input: &str) -> [u8; N] {
+        hex::decode(input).unwrap().try_into().unwrap()
+    }
+
+    fn random<const N: usize>() -> [u8; N] {
+        let mut output = [0; N];
+        OsRng.fill_bytes(&mut output);
+        output
+    }
+
+    #[test]
+    fn expand_label_vectors() {
+        let key: Vec<u8> = (0..HASH_OUTPUT_LEN as u8).collect();
+        let mut okm = [0; KEY_LEN];
+        hkdf_expand_label(&key, "key", None, &mut okm);
+        assert_eq!(hex::encode(okm), "69f70006b10383b1e14ef3613f0750e4");
+        let mut okm = [0; HASH_OUTPUT_LEN];
+        hkdf_expand_label(&key, "handshake_i_am_alice", Some(b"context"), &mut okm);
+        assert_eq!(hex::encode(okm), "81aa6e47aa8eda8df98a96c72d94e087f5e06b2fbad80fcf5590f2120645a869f9117bfb1ea650f18f29ddd1acbfe090");
+        //several HMAC blocks
+        let mut okm = [0; 100];
+        hkdf_expand_label(&key, "derived", None, &mut okm);
+        assert_eq!(hex::encode(okm), "69a8c3005dfc6f357c47e530bfdcfba29cc1f2b233af29114cd9f30cc1466a278bfd2ee51445a28700e0dd0a6c242be7b2ecbb02652d150761f7e8280b696460963a7e658fb79efc8ce07255226035883819355729354aa58a4a685b3d84e9ebd5490506");
+    }
+
+    #[test]
+    fn handshake_keys_vectors() {
+        let alice = HandshakeKeys::derive_keys(from_hex(SHARED_SECRET), from_hex(HANDSHAKE_HASH), false);
+        assert_eq!(hex::encode(alice.handshake_secret), HANDSHAKE_SECRET);
+        assert_eq!(hex::encode(alice.local_handshake_traffic_secret), ALICE_HANDSHAKE_TRAFFIC_SECRET);
+        assert_eq!(hex::encode(alice.peer_handshake_traffic_secret), BOB_HANDSHAKE_TRAFFIC_SECRET);
+        assert_eq!(hex::encode(alice.local_key), "ee541b8ef71367de5d9187feb6b7dab9");
+        assert_eq!(hex::encode(alice.local_iv), "5ff8a0fef6fd3fef07a2d518");
+        assert_eq!(hex::encode(alice.peer_key), "71818afb4c3880c9621a5a89a352b7ed");
+        assert_eq!(hex::encode(alice.peer_iv), "147ce340375b6a93197a9fb9");
+
+        let bob = HandshakeKeys::derive_keys(from_hex(SHARED_SECRET), from_hex(HANDSHAKE_HASH), true);
+        assert_eq!(hex::encode(bob.handshake_secret), HANDSHAKE_SECRET);
+        assert_eq!(hex::encode(bob.local_handshake_traffic_secret), BOB_HANDSHAKE_TRAFFIC_SECRET);
+        assert_eq!(hex::encode(bob.peer_handshake_traffic_secret), ALICE_HANDSHAKE_TRAFFIC_SECRET);
+        assert_eq!(hex::encode(bob.local_key), "71818afb4c3880c9621a5a89a352b7ed");
+        assert_eq!(hex::encode(bob.local_iv), "147ce340375b6a93197a9fb9");
+        assert_eq!(hex::encode(bob.peer_key), "ee541b8ef71367de5d9187feb6b7dab9");
+        assert_eq!(hex::encode(bob.peer_iv), "5ff8a0fef6fd3fef07a2d518");
+    }
+
+    #[test]
+    fn application_keys_vectors() {
+        let alice = ApplicationKeys::derive_keys(from_hex(HANDSHAKE_SECRET), from_hex(HANDSHAKE_HASH), false);
+        assert_eq!(hex::encode(alice.local_key), "5196014a9918c1d579803d48933faa3a");
+        assert_eq!(hex::encode(alice.local_iv), "33ca85d95aefb96b1dd6dba0");
+        assert_eq!(hex::encode(alice.peer_key), "0ec26302e12bebe09df38be3f68c0336");
+        assert_eq!(hex::encode(alice.peer_iv), "4c293f3792ae1f6e0956b847");
+
+        let bob = ApplicationKeys::derive_keys(from_hex(HANDSHAKE_SECRET), from_hex(HANDSHAKE_HASH), true);
+        assert_eq!(hex::encode(bob.local_key), "0ec26302e12bebe09df38be3f68c0336");
+        assert_eq!(hex::encode(bob.local_iv), "4c293f3792ae1f6e0956b847");
+        assert_eq!(hex::encode(bob.peer_key), "5196014a9918c1d579803d48933faa3a");
+        assert_eq!(hex::encode(bob.peer_iv), "33ca85d95aefb96b1dd6dba0");
+    }
+
+    #[test]
+    fn handshake_finished_vectors() {
+        let handshake_hash = from_hex(HANDSHAKE_HASH);
+        let alice_finished = compute_handshake_finished(from_hex(ALICE_HANDSHAKE_TRAFFIC_SECRET), handshake_hash);
+        assert_eq!(hex::encode(alice_finished), "b413755808d36b82c876f018f9de4ff8ef4a5d2fde09976402b4915b73380365afce6cea946a6312429c1eb63d1fbe84");
+        let bob_finished = compute_handshake_finished(from_hex(BOB_HANDSHAKE_TRAFFIC_SECRET), handshake_hash);
+        assert_eq!(hex::encode(bob_finished), "47db615c66bad776b2605666f26fe718f9d571b2de5637e45f26fa3899001d181ded051079506d3aa8cd09c0460b9d83");
+
+        assert!(verify_handshake_finished(alice_finished, from_hex(ALICE_HANDSHAKE_TRAFFIC_SECRET), handshake_hash));
+        assert!(verify_handshake_finished(bob_finished, from_hex(BOB_HANDSHAKE_TRAFFIC_SECRET), handshake_hash));
+        assert!(!verify_handshake_finished(alice_finished, from_hex(BOB_HANDSHAKE_TRAFFIC_SECRET), handshake_hash));
+        assert!(!verify_handshake_finished(alice_finished, from_hex(ALICE_HANDSHAKE_TRAFFIC_SECRET), [0; HASH_OUTPUT_LEN]));
+        let mut tampered = alice_finished;
+        tampered[HASH_OUTPUT_LEN-1] ^= 1;
+        assert!(!verify_handshake_finished(tampered, from_hex(ALICE_HANDSHAKE_TRAFFIC_SECRET), handshake_hash));
+    }
+
+    #[test]
+    fn fingerprint_vectors() {
+        assert_eq!(generate_fingerprint(&[0; PUBLIC_KEY_LENGTH]), "C53A272E2AA33E9568219E906277FCC1");
+        let public_key: Vec<u8> = (0..PUBLIC_KEY_LENGTH as u8).collect();
+        assert_eq!(generate_fingerprint(&public_key), "B99D268D378EE1D239E9ABB2AD84714B");
+        assert_eq!(compute_fingerprint(&public_key)[..], hex::decode("B99D268D378EE1D239E9ABB2AD84714B").unwrap()[..]);
+    }
+
+    #[test]
+    fn safety_number() {
+        let expected = "32574 64295 59632 55047 64855 06307 93876 79826 03181 90918 16615 82338";
+        assert_eq!(generate_safety_number(&[1; PUBLIC_KEY_LENGTH], &[2; PUBLIC_KEY_LENGTH]), expected);
+        assert_eq!(generate_safety_number(&[2; PUBLIC_KEY_LENGTH], &[1; PUBLIC_KEY_LENGTH]), expected);
+        for _ in 0..20 {
+            let (a, b) = (random::<PUBLIC_KEY_LENGTH>(), random::<PUBLIC_KEY_LENGTH>());
+            let safety_number = generate_safety_number(&a, &b);
+            assert_eq!(safety_number, generate_safety_number(&b, &a));
+            assert_eq!(safety_number.len(), SAFETY_NUMBER_GROUPS*6-1);
+            assert_ne!(safety_number, generate_safety_number(&a, &random::<PUBLIC_KEY_LENGTH>()));
+        }
+    }
+
+    #[test]
+    fn roles_are_symmetric() {
+        for _ in 0..20 {
+            let (shared_secret, handshake_hash) = (random(), random());
+            let alice = HandshakeKeys::derive_keys(shared_secret, handshake_hash, false);
+            let bob = HandshakeKeys::derive_keys(shared_secret, handshake_hash, true);
+            assert_eq!(alice.handshake_secret, bob.handshake_secret);
+            assert_eq!(alice.local_key, bob.peer_key);
+            assert_eq!(alice.local_iv, bob.peer_iv);
+            assert_eq!(alice.peer_key, bob.local_key);
+            assert_eq!(alice.peer_iv, bob.local_iv);
+            assert_ne!(alice.local_key, alice.peer_key);
+            let finished = compute_handshake_finished(alice.local_handshake_traffic_secret, handshake_hash);
+            assert!(verify_handshake_finished(finished, bob.peer_handshake_traffic_secret, handshake_hash));
+
+            let alice = ApplicationKeys::derive_keys(alice.handshake_secret, handshake_hash, false);
+            let bob = ApplicationKeys::derive_keys(bob.handshake_secret, handshake_hash, true);
+            assert_eq!(alice.local_key, bob.peer_key);
+            assert_eq!(alice.local_iv, bob.peer_iv);
+            assert_eq!(alice.peer_key, bob.local_key);
+            assert_eq!(alice.peer_iv, bob.local_iv);
+            assert_ne!(alice.local_key, alice.peer_key);
+        }
+    }
+
+    #[test]
+    fn data_round_trip() {
+        for len in [0, 1, 15, 16, 17, 1000, 100_000] {
+            let master_key = generate_master_key();
+            let mut data = vec![0; len];
+            OsRng.fill_bytes(&mut data);
+            let encrypted = encrypt_data(&data, &master_key).unwrap();
+            assert_eq!(encrypted.len(), IV_LEN+len+AES_TAG_LEN);
+            assert_eq!(decrypt_data(&encrypted, &master_key).unwrap(), data);
+            //random IV
+            assert_ne!(encrypt_data(&data, &master_key).unwrap(), encrypted);
+        }
+        assert_eq!(encrypt_data(b"data", &[0; MASTER_KEY_LEN-1]), Err(CryptoError::InvalidLength));
+    }
+
+    #[test]
+    fn data_tampering() {
+        let master_key = generate_master_key();
+        let encrypted = encrypt_data(b"some secret data", &master_key).unwrap();
+        for i in 0..encrypted.len() {
+            let mut tampered = encrypted.clone();
+            tampered[i] ^= 1 << (i%8);
+            assert_eq!(decrypt_data(&tampered, &master_key), Err(CryptoError::DecryptionFailed));
+        }
+        assert_eq!(decrypt_data(&encrypted, &generate_master_key()), Err(CryptoError::DecryptionFailed));
+        assert_eq!(decrypt_data(&encrypted[..encrypted.len()-1], &master_key), Err(CryptoError::DecryptionFailed));
+        assert_eq!(decrypt_data(&encrypted[..IV_LEN], &master_key), Err(CryptoError::InvalidLength));
+        assert_eq!(decrypt_data(&encrypted, &master_key[..MASTER_KEY_LEN-1]), Err(CryptoError::InvalidLength));
+    }
+
+    #[test]
+    fn lookup_keys() {
+        let master_key = generate_master_key();
+        let lookup_key = compute_lookup_key(&master_key, b"value");
+        assert_eq!(compute_lookup_key(&master_key, b"value"), lookup_key);
+        assert_ne!(compute_lookup_key(&master_key, b"other value"), lookup_key);
+        //the decoy identity shares the tables: its lookup keys must not match ours
+        assert_ne!(compute_lookup_key(&generate_master_key(), b"value"), lookup_key);
+    }
+
+    #[test]
+    fn master_key_encryption() {
+        let master_key = generate_master_key();
+        let (salt, encrypted_master_key) = encrypt_master_key(master_key, b"password");
+        assert_eq!(decrypt_master_key(&encrypted_master_key, b"password", &salt), Ok(master_key));
+        assert_eq!(decrypt_master_key(&encrypted_master_key, b"Password", &salt), Err(CryptoError::DecryptionFailed));
+        assert_eq!(decrypt_master_key(&encrypted_master_key, b"", &salt), Err(CryptoError::DecryptionFailed));
+        assert_eq!(decrypt_master_key(&encrypted_master_key, b"password", &[0; SALT_LEN]), Err(CryptoError::DecryptionFailed));
+        let mut tampered = encrypted_master_key;
+        tampered[IV_LEN] ^= 1;
+        assert_eq!(decrypt_master_key(&tampered, b"password", &salt), Err(CryptoError::DecryptionFailed));
+
+        assert_eq!(decrypt_master_key(&encrypted_master_key[..encrypted_master_key.len()-1], b"password", &salt), Err(CryptoError::InvalidLength));
+        assert_eq!(decrypt_master_key(&[], b"password", &salt), Err(CryptoError::InvalidLength));
+        assert_eq!(decrypt_master_key(&encrypted_master_key, b"password", &salt[..SALT_LEN-1]), Err(CryptoError::InvalidLength));
+        assert_eq!(decrypt_master_key(&[&encrypted_master_key[..], &[0]].concat(), b"password", &salt), Err(CryptoError::InvalidLength));
+
+        //random salt and IV
+        let (other_salt, other_encrypted_master_key) = encrypt_master_key(master_key, b"password");
+        assert_ne!(salt, other_salt);
+        assert_ne!(encrypted_master_key, other_encrypted_master_key);
+    }
+}
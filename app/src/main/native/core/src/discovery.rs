@@ -0,0 +1,495 @@
+use std::{convert::{TryFrom, TryInto}, io, net::{Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket}, sync::atomic::{AtomicBool, Ordering}, time::{Duration, Instant, SystemTime, UNIX_EPOCH}};
+use rand::{RngCore, rngs::OsRng, seq::SliceRandom};
+use socket2::{Domain, Protocol, Socket, Type};
+use crate::crypto;
+
+pub const SERVICE_TYPE: &str = "_aira._tcp.local";
+pub const MDNS_ADDR: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 251);
+pub const MDNS_PORT: u16 = 5353;
+pub const EPOCH_DURATION: u64 = 900; //seconds
+pub const TOKEN_BUCKET: usize = 16;
+const TOKENS_PER_PACKET: usize = 2*TOKEN_BUCKET; //keeps each response within MAX_RESPONSE_SIZE
+pub const MAX_TOKENS: usize = 64*TOKEN_BUCKET; //further contacts are not announced
+const TXT_VERSION: &str = "4";
+const INSTANCE_LEN: usize = 8;
+const RECORD_TTL: u32 = 120;
+const MAX_PACKET_SIZE: usize = 9000;
+const MAX_RESPONSE_SIZE: usize = 1472; //one Ethernet MTU without the IP and UDP headers: fragmented multicast is often dropped
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+const TYPE_A: u16 = 1;
+const TYPE_PTR: u16 = 12;
+const TYPE_TXT: u16 = 16;
+const TYPE_SRV: u16 = 33;
+const TYPE_ANY: u16 = 255;
+const CLASS_IN: u16 = 1;
+const CACHE_FLUSH: u16 = 0x8000;
+const FLAG_RESPONSE: u16 = 0x8400;
+
+//TXT record: v=<version> followed by one t=<hex token> entry per contact
+//tokens and instance names change every epoch, so strangers only see random identifiers
+//the list is padded with random tokens to a multiple of TOKEN_BUCKET so that it doesn't reveal the number of contacts
+//and split across several responses of TOKENS_PER_PACKET tokens, each one with its own TXT record
+pub struct Announcement {
+    pub instance: String,
+    pub port: u16,
+    pub tokens: Vec<[u8; crypto::DISCOVERY_TOKEN_LEN]>,
+}
+
+pub fn current_epoch() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()/EPOCH_DURATION
+}
+
+impl Announcement {
+    pub fn new(port: u16, mut tokens: Vec<[u8; crypto::DISCOVERY_TOKEN_LEN]>) -> Announcement {
+        if tokens.len() > MAX_TOKENS {
+            log::warn!("{} contacts are not announced", tokens.len()-MAX_TOKENS);
+            tokens.shuffle(&mut OsRng); //a different subset every epoch
+            tokens.truncate(MAX_TOKENS);
+        }
+        let padded_len = (tokens.len()/TOKEN_BUCKET+1).min(MAX_TOKENS/TOKEN_BUCKET)*TOKEN_BUCKET;
+        while tokens.len() < padded_len {
+            let mut token = [0; crypto::DISCOVERY_TOKEN_LEN];
+            OsRng.fill_bytes(&mut token);
+            tokens.push(token);
+        }
+        tokens.shuffle(&mut OsRng);
+        let mut instance = [0; INSTANCE_LEN];
+        OsRng.fill_bytes(&mut instance);
+        Announcement {
+            instance: hex::encode(instance),
+            port,
+            tokens,
+        }
+    }
+
+    fn txt_entries(tokens: &[[u8; crypto::DISCOVERY_TOKEN_LEN]]) -> Vec<String> {
+        let mut entries = vec![format!("v={}", TXT_VERSION)];
+        entries.extend(tokens.iter().map(|token| format!("t={}", hex::encode(token))));
+        entries
+    }
+
+    fn from_txt(instance: String, port: u16, entries: &[String]) -> Option<Announcement> {
+        let mut fields = entries.iter().filter_map(|entry| entry.split_once('='));
+        if fields.next() != Some(("v", TXT_VERSION)) {
+            return None;
+        }
+        Some(Announcement {
+            instance,
+            port,
+            tokens: fields.filter(|(key, _)| *key == "t").filter_map(|(_, token)| hex::decode(token).ok()?.try_into().ok()).collect(),
+        })
+    }
+}
+
+pub struct DiscoveredPeer {
+    pub ip: Ipv4Addr,
+    pub port: u16,
+    pub tokens: Vec<[u8; crypto::DISCOVERY_TOKEN_LEN]>, //empty if the peer didn't send an AIRA TXT record
+}
+
+fn multicast_socket(interface: Ipv4Addr, port: u16) -> io::Result<UdpSocket> {
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+    socket.set_reuse_address(true)?; //the system responder may already listen on this port
+    socket.bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, port)).into())?;
+    socket.join_multicast_v4(&MDNS_ADDR, &interface)?;
+    socket.set_multicast_if_v4(&interface)?;
+    socket.set_multicast_loop_v4(true)?;
+    socket.set_multicast_ttl_v4(255)?;
+    socket.set_read_timeout(Some(POLL_INTERVAL))?;
+    Ok(socket.into())
+}
+
+fn recv(socket: &UdpSocket, buff: &mut [u8]) -> io::Result<Option<(usize, SocketAddr)>> {
+    match socket.recv_from(buff) {
+        Ok(received) => Ok(Some(received)),
+        Err(e) if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut => Ok(None),
+        Err(e) => Err(e)
+    }
+}
+
+fn write_name(packet: &mut Vec<u8>, name: &str) {
+    for label in name.split('.').filter(|label| !label.is_empty()) {
+        let label = &label.as_bytes()[..label.len().min(63)];
+        packet.push(label.len() as u8);
+        packet.extend(label);
+    }
+    packet.push(0);
+}
+
+fn write_header(packet: &mut Vec<u8>, flags: u16, questions: u16, answers: u16) {
+    packet.extend(0u16.to_be_bytes()); //mDNS ids are always 0
+    packet.extend(flags.to_be_bytes());
+    packet.extend(questions.to_be_bytes());
+    packet.extend(answers.to_be_bytes());
+    packet.extend([0; 4]);
+}
+
+fn write_record(packet: &mut Vec<u8>, name: &str, record_type: u16, class: u16, data: &[u8]) -> io::Result<()> {
+    let data_len = u16::try_from(data.len()).map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "Record data too large"))?;
+    write_name(packet, name);
+    packet.extend(record_type.to_be_bytes());
+    packet.extend(class.to_be_bytes());
+    packet.extend(RECORD_TTL.to_be_bytes());
+    packet.extend(data_len.to_be_bytes());
+    packet.extend(data);
+    Ok(())
+}
+
+fn build_query() -> Vec<u8> {
+    let mut packet = Vec::new();
+    write_header(&mut packet, 0, 1, 0);
+    write_name(&mut packet, SERVICE_TYPE);
+    packet.extend(TYPE_PTR.to_be_bytes());
+    packet.extend(CLASS_IN.to_be_bytes());
+    packet
+}
+
+//one response per TOKENS_PER_PACKET tokens
+fn build_responses(announcement: &Announcement, address: Ipv4Addr) -> io::Result<Vec<Vec<u8>>> {
+    announcement.tokens.chunks(TOKENS_PER_PACKET).map(|tokens| build_response(announcement, tokens, address)).collect()
+}
+
+fn build_response(announcement: &Announcement, tokens: &[[u8; crypto::DISCOVERY_TOKEN_LEN]], address: Ipv4Addr) -> io::Result<Vec<u8>> {
+    let instance_name = format!("{}.{}", announcement.instance, SERVICE_TYPE);
+    let host_name = format!("{}.local", announcement.instance);
+    let mut packet = Vec::new();
+    write_header(&mut packet, FLAG_RESPONSE, 0, if address.is_unspecified() { 3 } else { 4 });
+    let mut ptr = Vec::new();
+    write_name(&mut ptr, &instance_name);
+    write_record(&mut packet, SERVICE_TYPE, TYPE_PTR, CLASS_IN, &ptr)?;
+    let mut srv = vec![0; 4]; //priority and weight
+    srv.extend(announcement.port.to_be_bytes());
+    write_name(&mut srv, &host_name);
+    write_record(&mut packet, &instance_name, TYPE_SRV, CLASS_IN | CACHE_FLUSH, &srv)?;
+    let mut txt = Vec::new();
+    for entry in Announcement::txt_entries(tokens) {
+        txt.push(u8::try_from(entry.len()).map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "TXT entry too large"))?);
+        txt.extend(entry.as_bytes());
+    }
+    //no cache flush: the TXT records of the other responses belong to the same set
+    write_record(&mut packet, &instance_name, TYPE_TXT, CLASS_IN, &txt)?;
+    if !address.is_unspecified() {
+        write_record(&mut packet, &host_name, TYPE_A, CLASS_IN | CACHE_FLUSH, &address.octets())?;
+    }
+    if packet.len() > MAX_RESPONSE_SIZE {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Announcement too large"));
+    }
+    Ok(packet)
+}
+
+fn read_u16(packet: &[u8], pos: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(packet.get(pos..pos+2)?.try_into().unwrap()))
+}
+
+//returns the name and the position right after it, following compression pointers
+fn read_name(packet: &[u8], mut pos: usize) -> Option<(String, usize)> {
+    let mut labels = Vec::new();
+    let mut end = None;
+    for _ in 0..128 { //bound the number of jumps to reject pointer loops
+        let len = *packet.get(pos)? as usize;
+        if len == 0 {
+            return Some((labels.join("."), end.unwrap_or(pos+1)));
+        }
+        if len & 0xc0 == 0xc0 {
+            end.get_or_insert(pos+2);
+            pos = (read_u16(packet, pos)? & 0x3fff) as usize;
+        } else {
+            labels.push(String::from_utf8_lossy(packet.get(pos+1..pos+1+len)?).into_owned());
+            pos += 1+len;
+        }
+    }
+    None
+}
+
+struct Record {
+    name: String,
+    record_type: u16,
+    data_pos: usize,
+    data: Vec<u8>,
+}
+
+struct Packet {
+    is_response: bool,
+    questions: Vec<(String, u16)>,
+    records: Vec<Record>,
+}
+
+fn parse_packet(packet: &[u8]) -> Option<Packet> {
+    let flags = read_u16(packet, 2)?;
+    let question_count = read_u16(packet, 4)?;
+    let record_count = read_u16(packet, 6)? as usize + read_u16(packet, 8)? as usize + read_u16(packet, 10)? as usize;
+    let mut pos = 12;
+    let mut questions = Vec::new();
+    for _ in 0..question_count {
+        let (name, next) = read_name(packet, pos)?;
+        questions.push((name, read_u16(packet, next)?));
+        pos = next+4;
+    }
+    let mut records = Vec::new();
+    for _ in 0..record_count {
+        let (name, next) = read_name(packet, pos)?;
+        let record_type = read_u16(packet, next)?;
+        let data_len = read_u16(packet, next+8)? as usize;
+        let data_pos = next+10;
+        records.push(Record {
+            name,
+            record_type,
+            data_pos,
+            data: packet.get(data_pos..data_pos+data_len)?.to_vec(),
+        });
+        pos = data_pos+data_len;
+    }
+    Some(Packet {
+        is_response: flags & 0x8000 != 0,
+        questions,
+        records,
+    })
+}
+
+fn names_equal(first: &str, second: &str) -> bool {
+    first.trim_end_matches('.').eq_ignore_ascii_case(second.trim_end_matches('.'))
+}
+
+fn parse_txt(data: &[u8]) -> Vec<String> {
+    let mut entries = Vec::new();
+    let mut pos = 0;
+    while let Some(len) = data.get(pos) {
+        match data.get(pos+1..pos+1+*len as usize) {
+            Some(entry) => entries.push(String::from_utf8_lossy(entry).into_owned()),
+            None => break
+        }
+        pos += 1+*len as usize;
+    }
+    entries
+}
+
+fn parse_peers(raw_packet: &[u8], packet: &Packet, source: Ipv4Addr) -> Vec<DiscoveredPeer> {
+    let mut peers = Vec::new();
+    for srv in packet.records.iter().filter(|record| record.record_type == TYPE_SRV) {
+        let instance = match srv.name.len().checked_sub(SERVICE_TYPE.len()+1) {
+            Some(len) if names_equal(&srv.name[len+1..], SERVICE_TYPE) => srv.name[..len].to_owned(),
+            _ => continue
+        };
+        let port = match read_u16(&srv.data, 4) {
+            Some(port) => port,
+            None => continue
+        };
+        let target = read_name(raw_packet, srv.data_pos+6).map(|(target, _)| target);
+        let ip = packet.records.iter()
+            .find(|record| record.record_type == TYPE_A && record.data.len() == 4 && target.as_deref().is_some_and(|target| names_equal(&record.name, target)))
+            .map(|record| Ipv4Addr::new(record.data[0], record.data[1], record.data[2], record.data[3]))
+            .unwrap_or(source);
+        let tokens = packet.records.iter()
+            .find(|record| record.record_type == TYPE_TXT && names_equal(&record.name, &srv.name))
+            .and_then(|txt| Announcement::from_txt(instance.clone(), port, &parse_txt(&txt.data)))
+            .map(|announcement| announcement.tokens)
+            .unwrap_or_default();
+        peers.push(DiscoveredPeer {
+            ip,
+            port,
+            tokens,
+        });
+    }
+    peers
+}
+
+pub struct Responder {
+    socket: UdpSocket,
+    group: SocketAddr,
+    address: Ipv4Addr,
+}
+
+impl Responder {
+    //address is advertised in the A record, if unspecified peers use the source address of the response
+    pub fn new(interface: Ipv4Addr, mdns_port: u16) -> io::Result<Responder> {
+        Ok(Responder {
+            socket: multicast_socket(interface, mdns_port)?,
+            group: SocketAddrV4::new(MDNS_ADDR, mdns_port).into(),
+            address: interface,
+        })
+    }
+
+    fn announce(&self, announcement: &Announcement) -> io::Result<()> {
+        for response in build_responses(announcement, self.address)? {
+            self.socket.send_to(&response, self.group)?;
+        }
+        Ok(())
+    }
+
+    //answer queries until running is cleared or no announcement can be built for the new epoch
+    //setting contacts_changed rebuilds the announcement before the end of the epoch
+    pub fn run<F: FnMut(u64) -> Option<Announcement>>(&self, running: &AtomicBool, contacts_changed: &AtomicBool, mut announcement_for_epoch: F) -> io::Result<()> {
+        let mut epoch = current_epoch();
+        contacts_changed.store(false, Ordering::Relaxed);
+        let mut announcement = match announcement_for_epoch(epoch) {
+            Some(announcement) => announcement,
+            None => return Ok(())
+        };
+        self.announce(&announcement)?;
+        let mut buff = [0; MAX_PACKET_SIZE];
+        while running.load(Ordering::Relaxed) {
+            let new_epoch = current_epoch() != epoch;
+            if new_epoch || contacts_changed.swap(false, Ordering::Relaxed) {
+                epoch = current_epoch();
+                let instance = announcement.instance;
+                announcement = match announcement_for_epoch(epoch) {
+                    Some(announcement) => announcement,
+                    None => return Ok(())
+                };
+                if !new_epoch { //browsers update the same service instead of seeing a new one
+                    announcement.instance = instance;
+                }
+                self.announce(&announcement)?;
+            }
+            if let Some((len, _)) = recv(&self.socket, &mut buff)? {
+                if let Some(packet) = parse_packet(&buff[..len]) {
+                    if !packet.is_response && packet.questions.iter().any(|(name, question_type)| {
+                        (*question_type == TYPE_PTR || *question_type == TYPE_ANY) && names_equal(name, SERVICE_TYPE)
+                    }) {
+                        self.announce(&announcement)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+pub struct Browser {
+    socket: UdpSocket,
+    group: SocketAddr,
+}
+
+impl Browser {
+    pub fn new(interface: Ipv4Addr, mdns_port: u16) -> io::Result<Browser> {
+        Ok(Browser {
+            socket: multicast_socket(interface, mdns_port)?,
+            group: SocketAddrV4::new(MDNS_ADDR, mdns_port).into(),
+        })
+    }
+
+    //send a query and collect the answers received before the timeout, one entry per address and port
+    //gathering the tokens of all its responses
+    pub fn browse(&self, timeout: Duration) -> io::Result<Vec<DiscoveredPeer>> {
+        self.socket.send_to(&build_query(), self.group)?;
+        let deadline = Instant::now()+timeout;
+        let mut peers: Vec<DiscoveredPeer> = Vec::new();
+        let mut buff = [0; MAX_PACKET_SIZE];
+        while Instant::now() < deadline {
+            if let Some((len, SocketAddr::V4(source))) = recv(&self.socket, &mut buff)? {
+                if let Some(packet) = parse_packet(&buff[..len]) {
+                    if packet.is_response {
+                        for peer in parse_peers(&buff[..len], &packet, *source.ip()) {
+                            match peers.iter_mut().find(|known| known.ip == peer.ip && known.port == peer.port) {
+                                Some(known) => for token in peer.tokens {
+                                    if !known.tokens.contains(&token) {
+                                        known.tokens.push(token);
+                                    }
+                                }
+                                None => peers.push(peer)
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        Ok(peers)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{sync::{Arc, Mutex}, thread};
+
+    const TEST_MDNS_PORT: u16 = 53535; //avoid interfering with a system responder
+
+    #[test]
+    fn announcement_txt_roundtrip() {
+        let announcement = Announcement::new(7530, vec![[1; crypto::DISCOVERY_TOKEN_LEN], [2; crypto::DISCOVERY_TOKEN_LEN]]);
+        let parsed = Announcement::from_txt(announcement.instance.clone(), 7530, &Announcement::txt_entries(&announcement.tokens)).unwrap();
+        assert_eq!(parsed.tokens, announcement.tokens);
+        assert!(Announcement::from_txt(announcement.instance.clone(), 7530, &["v=1".to_owned()]).is_none());
+        //instance names are random, they can't be used to follow a device across epochs
+        assert_ne!(Announcement::new(7530, Vec::new()).instance, announcement.instance);
+    }
+
+    #[test]
+    fn tokens_are_padded() {
+        for (count, padded_len) in [(0, TOKEN_BUCKET), (1, TOKEN_BUCKET), (TOKEN_BUCKET-1, TOKEN_BUCKET), (TOKEN_BUCKET, 2*TOKEN_BUCKET), (MAX_TOKENS-1, MAX_TOKENS), (MAX_TOKENS, MAX_TOKENS), (MAX_TOKENS+100, MAX_TOKENS)] {
+            let tokens: Vec<[u8; crypto::DISCOVERY_TOKEN_LEN]> = (0..count).map(|i| [i as u8; crypto::DISCOVERY_TOKEN_LEN]).collect();
+            let announcement = Announcement::new(7530, tokens.clone());
+            assert_eq!(announcement.tokens.len(), padded_len);
+            if count <= MAX_TOKENS {
+                assert!(tokens.iter().all(|token| announcement.tokens.contains(token)));
+            }
+            let responses = build_responses(&announcement, Ipv4Addr::LOCALHOST).unwrap();
+            assert_eq!(responses.len(), padded_len.div_ceil(TOKENS_PER_PACKET));
+            assert!(responses.iter().all(|response| response.len() <= MAX_RESPONSE_SIZE));
+        }
+        //real tokens are not always first
+        let tokens = vec![[0; crypto::DISCOVERY_TOKEN_LEN]];
+        assert!((0..20).any(|_| Announcement::new(7530, tokens.clone()).tokens[0] != tokens[0]));
+    }
+
+    #[test]
+    fn oversize_announcements_are_rejected() {
+        let announcement = Announcement::new(7530, Vec::new());
+        let tokens = vec![[1; crypto::DISCOVERY_TOKEN_LEN]; 2000];
+        assert_eq!(build_response(&announcement, &tokens, Ipv4Addr::LOCALHOST).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        //fits in the TXT record but not in one MTU
+        assert_eq!(build_response(&announcement, &tokens[..2*TOKENS_PER_PACKET], Ipv4Addr::LOCALHOST).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn response_parsing() {
+        let announcement = Announcement::new(7530, vec![[1; crypto::DISCOVERY_TOKEN_LEN]]);
+        let response = build_response(&announcement, &announcement.tokens, Ipv4Addr::new(192, 168, 1, 2)).unwrap();
+        let packet = parse_packet(&response).unwrap();
+        assert!(packet.is_response);
+        let peers = parse_peers(&response, &packet, Ipv4Addr::new(10, 0, 0, 1));
+        assert_eq!(peers.len(), 1);
+        assert_eq!(peers[0].ip, Ipv4Addr::new(192, 168, 1, 2));
+        assert_eq!(peers[0].port, 7530);
+        assert_eq!(peers[0].tokens, announcement.tokens);
+        //truncated packets must not panic
+        for len in 0..response.len() {
+            if let Some(packet) = parse_packet(&response[..len]) {
+                parse_peers(&response[..len], &packet, Ipv4Addr::LOCALHOST);
+            }
+        }
+    }
+
+    #[test]
+    fn discovery_over_loopback() {
+        let token = [3; crypto::DISCOVERY_TOKEN_LEN];
+        let new_token = [4; crypto::DISCOVERY_TOKEN_LEN];
+        let responder = Responder::new(Ipv4Addr::LOCALHOST, TEST_MDNS_PORT).unwrap();
+        let running = Arc::new(AtomicBool::new(true));
+        let contacts_changed = Arc::new(AtomicBool::new(false));
+        //more tokens than one response can hold
+        let mut initial_tokens: Vec<[u8; crypto::DISCOVERY_TOKEN_LEN]> = (0..TOKENS_PER_PACKET as u8).map(|i| [100+i; crypto::DISCOVERY_TOKEN_LEN]).collect();
+        initial_tokens.push(token);
+        let tokens = Arc::new(Mutex::new(initial_tokens.clone()));
+        let responder_thread = {
+            let (running, contacts_changed, tokens) = (running.clone(), contacts_changed.clone(), tokens.clone());
+            thread::spawn(move || responder.run(&running, &contacts_changed, |_| Some(Announcement::new(7530, tokens.lock().unwrap().clone()))).unwrap())
+        };
+        let browser = Browser::new(Ipv4Addr::LOCALHOST, TEST_MDNS_PORT).unwrap();
+        let peers = browser.browse(Duration::from_secs(2)).unwrap();
+        let peer = peers.iter().find(|peer| peer.tokens.contains(&token)).unwrap();
+        assert!(initial_tokens.iter().all(|token| peer.tokens.contains(token)));
+        assert_eq!(peer.ip, Ipv4Addr::LOCALHOST);
+        assert_eq!(peer.port, 7530);
+
+        //a new contact is announced without waiting for the next epoch
+        tokens.lock().unwrap().push(new_token);
+        contacts_changed.store(true, Ordering::Relaxed);
+        thread::sleep(POLL_INTERVAL*2);
+        let peers = browser.browse(Duration::from_secs(2)).unwrap();
+        running.store(false, Ordering::Relaxed);
+        responder_thread.join().unwrap();
+        assert!(peers.iter().any(|peer| peer.tokens.contains(&token) && peer.tokens.contains(&new_token)));
+    }
+}
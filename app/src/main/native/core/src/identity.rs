@@ -0,0 +1,2387 @@
+use std::{cell::RefCell, collections::HashMap, convert::TryInto, path::Path, str::FromStr};
+use crypto::CryptoError;
+use ed25519_dalek::{Keypair, Signer, KEYPAIR_LENGTH, SIGNATURE_LENGTH, PUBLIC_KEY_LENGTH};
+use rand::{Rng, RngCore, rngs::OsRng};
+use rayon::prelude::*;
+use rusqlite::{params, types::Type, Connection, Error};
+use utils::to_uuid_bytes;
+use uuid::Uuid;
+use zeroize::Zeroize;
+use crate::{cover_traffic::COVER_TRAFFIC_RATE_LEN, crypto, discovery::Announcement, padding::{PaddingPolicy, PADDING_POLICY_LEN}, print_error, storage::{self, MAIN_TABLE, Row, SqliteStorage, Storage, Table}, utils, verification::{KeyRotation, QrPayload, KEY_ROTATION_LEN}};
+
+//lookup columns hold crypto::compute_lookup_key of an encrypted value unique in the table: the file UUID or the blocked key
+const CONTACTS_TABLE: Table = Table { name: "contacts", schema: "uuid BLOB PRIMARY KEY, avatar BLOB, record BLOB", index: None };
+const FILES_TABLE: Table = Table { name: "files", schema: "contact_uuid BLOB, uuid BLOB, data BLOB, lookup BLOB", index: Some("lookup") };
+const AVATARS_TABLE: Table = Table { name: "avatars", schema: "uuid BLOB PRIMARY KEY, data BLOB", index: None };
+const MESSAGES_TABLE: Table = Table { name: "messages", schema: "id INTEGER PRIMARY KEY, contact BLOB, record BLOB", index: None };
+const KEY_HISTORY_TABLE: Table = Table { name: "key_history", schema: "id INTEGER PRIMARY KEY, contact BLOB, key BLOB, timestamp BLOB", index: None };
+const GROUPS_TABLE: Table = Table { name: "groups", schema: "uuid BLOB PRIMARY KEY, name BLOB, members BLOB", index: None };
+const BLOCKED_TABLE: Table = Table { name: "blocked", schema: "id INTEGER PRIMARY KEY, key BLOB, lookup BLOB", index: Some("lookup") };
+const DATA_TABLES: [&Table; 7] = [&CONTACTS_TABLE, &FILES_TABLE, &AVATARS_TABLE, &MESSAGES_TABLE, &KEY_HISTORY_TABLE, &GROUPS_TABLE, &BLOCKED_TABLE];
+
+const CONTACTS_BUCKET_SIZE: usize = 16; //the contacts table always holds a multiple of this number of rows
+const DUMMY_CONTACT_KEY: [u8; PUBLIC_KEY_LENGTH] = [0; PUBLIC_KEY_LENGTH]; //not a valid ed25519 public key
+
+const DATABASE_CORRUPED_ERROR: &str = "Database corrupted";
+const BAD_PASSWORD_ERROR: &str = "Bad password";
+const DURESS_PASSWORD_ERROR: &str = "The duress password must differ from the real one";
+
+const DURESS_DESTROY_KEY: [u8; crypto::MASTER_KEY_LEN] = [0; crypto::MASTER_KEY_LEN];
+
+struct DBKeys;
+impl<'a> DBKeys {
+    pub const NAME: &'a str = "name";
+    pub const NAME_ENCRYPTED: &'a str = "name_encrypted"; //absent from databases that may still hold a plaintext name
+    pub const LOGIN_LABEL: &'a str = "login_label";
+    pub const KEYPAIR: &'a str = "keypair";
+    pub const SALT: &'a str = "salt";
+    pub const MASTER_KEY: &'a str = "master_key";
+    pub const USE_PADDING: &'a str = "use_padding";
+    pub const PADDING_POLICY: &'a str = "padding_policy";
+    pub const COVER_TRAFFIC_RATE: &'a str = "cover_traffic_rate";
+    pub const ENCRYPT_DATABASE: &'a str = "encrypt_database";
+    pub const DURESS_NAME: &'a str = "duress_name";
+    pub const DURESS_KEYPAIR: &'a str = "duress_keypair";
+    pub const DURESS_SALT: &'a str = "duress_salt";
+    pub const DURESS_MASTER_KEY: &'a str = "duress_master_key";
+    pub const DURESS_USE_PADDING: &'a str = "duress_use_padding";
+    pub const DURESS_PADDING_POLICY: &'a str = "duress_padding_policy";
+    pub const DURESS_COVER_TRAFFIC_RATE: &'a str = "duress_cover_traffic_rate";
+    pub const KEY_ROTATIONS: &'a str = "key_rotations";
+    pub const DURESS_KEY_ROTATIONS: &'a str = "duress_key_rotations";
+    pub const DURESS_DATABASE_KEY: &'a str = "duress_database_key"; //key of the encrypted database shared with the real identity
+    pub const AVATAR: &'a str = "avatar";
+}
+
+fn bool_to_byte(b: bool) -> u8 {
+    if b { 75 } else { 30 } //completely arbitrary values
+}
+
+fn byte_to_bool(b: u8) -> Result<bool, ()> {
+    if b == 75 {
+        Ok(true)
+    } else if b == 30 {
+        Ok(false)
+    } else {
+        Err(())
+    }
+}
+
+const MESSAGE_COLUMNS: [&str; 2] = ["contact", "record"];
+
+fn encrypt_message(master_key: &[u8], contact_uuid: &Uuid, message: &Message) -> [Vec<u8>; 2] {
+    [
+        crypto::encrypt_data(contact_uuid.as_bytes(), master_key).unwrap(),
+        crypto::encrypt_data(&message.to_record(), master_key).unwrap(),
+    ]
+}
+
+//decrypt one column of each row on all cores. Rows keep their order, those that can't be decrypted are skipped.
+//The decoy identity shares the data tables with the real one: rows of the other identity are expected
+//and must be skipped without any log, which would reveal that a second identity exists.
+fn decrypt_rows(master_key: &[u8], rows: Vec<(i64, Row)>, column: usize) -> Vec<(i64, Row, Vec<u8>)> {
+    rows.into_par_iter().filter_map(|(id, row)| {
+        let plain_value = crypto::decrypt_data(row[column].as_deref()?, master_key).ok()?;
+        Some((id, row, plain_value))
+    }).collect()
+}
+
+//older versions encrypted each value in its own column: a record is their concatenation.
+//fails if any row can't be decrypted so that the migration is aborted instead of losing it.
+fn merge_legacy_rows(master_key: &[u8], rows: Vec<(i64, Row)>) -> Result<Vec<(i64, Vec<u8>)>, Error> {
+    rows.into_par_iter().map(|(id, row)| {
+        let mut record = Vec::new();
+        for value in row {
+            record.extend(crypto::decrypt_data(value.as_deref().unwrap_or_default(), master_key).map_err(|e| Error::FromSqlConversionFailure(0, Type::Blob, Box::new(e)))?);
+        }
+        Ok((id, crypto::encrypt_data(&record, master_key).unwrap()))
+    }).collect()
+}
+
+fn get_required<S: Storage>(storage: &S, key: &str) -> Result<Vec<u8>, Error> {
+    storage.get_value(key)?.ok_or(Error::QueryReturnedNoRows)
+}
+
+#[derive(Debug, Clone)]
+pub struct Message {
+    pub outgoing: bool,
+    pub timestamp: u64,
+    pub data: Vec<u8>,
+}
+
+const MESSAGE_RECORD_HEADER_LEN: usize = 1+8; //outgoing, timestamp
+
+impl Message {
+    fn to_record(&self) -> Vec<u8> {
+        let mut record = Vec::with_capacity(MESSAGE_RECORD_HEADER_LEN+self.data.len());
+        record.push(bool_to_byte(self.outgoing));
+        record.extend(self.timestamp.to_be_bytes());
+        record.extend(&self.data);
+        record
+    }
+
+    fn from_record(record: &[u8]) -> Option<Message> {
+        if record.len() < MESSAGE_RECORD_HEADER_LEN {
+            return None;
+        }
+        Some(Message {
+            outgoing: byte_to_bool(record[0]).ok()?,
+            timestamp: u64::from_be_bytes(record[1..MESSAGE_RECORD_HEADER_LEN].try_into().unwrap()),
+            data: record[MESSAGE_RECORD_HEADER_LEN..].to_vec(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PaddingPreference {
+    Default, //follow the identity setting
+    Always,
+    Never,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NotificationMode {
+    Default,
+    Silent,
+    Muted,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContactSettings {
+    pub padding: PaddingPreference,
+    pub notifications: NotificationMode,
+    pub auto_accept_size: u64, //largest total size of files accepted without asking, 0 to always ask
+    pub retention: u64, //seconds after which messages are deleted, 0 to keep them forever
+}
+
+const CONTACT_SETTINGS_LEN: usize = 18;
+
+impl Default for ContactSettings {
+    fn default() -> Self {
+        ContactSettings {
+            padding: PaddingPreference::Default,
+            notifications: NotificationMode::Default,
+            auto_accept_size: 0,
+            retention: 0,
+        }
+    }
+}
+
+impl ContactSettings {
+    fn to_bytes(&self) -> [u8; CONTACT_SETTINGS_LEN] {
+        let mut bytes = [0; CONTACT_SETTINGS_LEN];
+        bytes[0] = self.padding as u8;
+        bytes[1] = self.notifications as u8;
+        bytes[2..10].copy_from_slice(&self.auto_accept_size.to_be_bytes());
+        bytes[10..].copy_from_slice(&self.retention.to_be_bytes());
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<ContactSettings> {
+        if bytes.len() != CONTACT_SETTINGS_LEN {
+            return None;
+        }
+        Some(ContactSettings {
+            padding: match bytes[0] {
+                0 => PaddingPreference::Default,
+                1 => PaddingPreference::Always,
+                2 => PaddingPreference::Never,
+                _ => return None
+            },
+            notifications: match bytes[1] {
+                0 => NotificationMode::Default,
+                1 => NotificationMode::Silent,
+                2 => NotificationMode::Muted,
+                _ => return None
+            },
+            auto_accept_size: u64::from_be_bytes(bytes[2..10].try_into().unwrap()),
+            retention: u64::from_be_bytes(bytes[10..].try_into().unwrap()),
+        })
+    }
+}
+
+//everything about a contact but its UUID and avatar, encrypted at once
+struct ContactRecord {
+    public_key: [u8; PUBLIC_KEY_LENGTH],
+    verified: bool,
+    seen: bool,
+    settings: ContactSettings,
+    name: Vec<u8>, //random bytes for dummy contacts
+}
+
+const CONTACT_RECORD_HEADER_LEN: usize = PUBLIC_KEY_LENGTH+2+CONTACT_SETTINGS_LEN;
+
+impl ContactRecord {
+    fn new(public_key: [u8; PUBLIC_KEY_LENGTH], name: Vec<u8>) -> ContactRecord {
+        ContactRecord {
+            public_key,
+            verified: false,
+            seen: true,
+            settings: ContactSettings::default(),
+            name,
+        }
+    }
+
+    //same layout as the concatenation of the legacy key, verified, seen, settings and name columns
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(CONTACT_RECORD_HEADER_LEN+self.name.len());
+        bytes.extend(self.public_key);
+        bytes.push(bool_to_byte(self.verified));
+        bytes.push(bool_to_byte(self.seen));
+        bytes.extend(self.settings.to_bytes());
+        bytes.extend(&self.name);
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<ContactRecord> {
+        if bytes.len() < CONTACT_RECORD_HEADER_LEN {
+            return None;
+        }
+        Some(ContactRecord {
+            public_key: bytes[..PUBLIC_KEY_LENGTH].try_into().unwrap(),
+            verified: byte_to_bool(bytes[PUBLIC_KEY_LENGTH]).ok()?,
+            seen: byte_to_bool(bytes[PUBLIC_KEY_LENGTH+1]).ok()?,
+            settings: ContactSettings::from_bytes(&bytes[PUBLIC_KEY_LENGTH+2..CONTACT_RECORD_HEADER_LEN])?,
+            name: bytes[CONTACT_RECORD_HEADER_LEN..].to_vec(),
+        })
+    }
+}
+
+pub struct Group {
+    pub uuid: Uuid,
+    pub name: String,
+    pub members: Vec<Uuid>, //contacts UUIDs
+}
+
+//a key previously associated with a contact and the time it was replaced
+pub struct PreviousKey {
+    pub public_key: [u8; PUBLIC_KEY_LENGTH],
+    pub timestamp: u64,
+}
+
+pub enum DuressAction {
+    Destroy,
+    Decoy(String), //name of the decoy identity
+}
+
+pub struct Contact {
+    pub uuid: Uuid,
+    pub public_key: [u8; PUBLIC_KEY_LENGTH],
+    pub name: String,
+    pub avatar: Option<Uuid>,
+    pub verified: bool,
+    pub seen: bool,
+}
+
+struct EncryptedIdentity {
+    encrypted_name: Vec<u8>,
+    encrypted_keypair: Vec<u8>,
+    salt: Vec<u8>,
+    encrypted_master_key: Vec<u8>,
+    encrypted_use_padding: Vec<u8>,
+    encrypted_padding_policy: Option<Vec<u8>>,
+    encrypted_cover_traffic_rate: Option<Vec<u8>>,
+    encrypted_encrypt_database: Option<Vec<u8>>,
+    encrypted_database_key: Option<Vec<u8>>, //decoy only
+}
+
+
+pub struct Identity<S: Storage = SqliteStorage> {
+    pub name: String,
+    keypair: Keypair,
+    pub master_key: [u8; crypto::MASTER_KEY_LEN],
+    pub padding_policy: PaddingPolicy,
+    pub cover_traffic_rate: u32, //dummy records per hour, 0 when disabled
+    pub encrypt_database: bool,
+    storage: S,
+    decoy: bool,
+    conversations: RefCell<Option<HashMap<Uuid, Vec<i64>>>>, //message ids of each conversation, built on first use
+}
+
+impl<S: Storage> Identity<S> {
+
+    pub fn sign(&self, input: &[u8]) -> [u8; SIGNATURE_LENGTH] {
+        self.keypair.sign(input).to_bytes()
+    }
+
+    pub fn get_public_key(&self) -> [u8; PUBLIC_KEY_LENGTH] {
+        self.keypair.public.to_bytes()
+    }
+
+    pub fn get_keypair(&self) -> Keypair {
+        Keypair::from_bytes(&self.keypair.to_bytes()).unwrap()
+    }
+
+    fn get_name_key(&self) -> &str {
+        if self.decoy { DBKeys::DURESS_NAME } else { DBKeys::NAME }
+    }
+
+    fn get_use_padding_key(&self) -> &str {
+        if self.decoy { DBKeys::DURESS_USE_PADDING } else { DBKeys::USE_PADDING }
+    }
+
+    fn get_padding_policy_key(&self) -> &str {
+        if self.decoy { DBKeys::DURESS_PADDING_POLICY } else { DBKeys::PADDING_POLICY }
+    }
+
+    fn get_cover_traffic_rate_key(&self) -> &str {
+        if self.decoy { DBKeys::DURESS_COVER_TRAFFIC_RATE } else { DBKeys::COVER_TRAFFIC_RATE }
+    }
+
+    fn get_keypair_key(&self) -> &str {
+        if self.decoy { DBKeys::DURESS_KEYPAIR } else { DBKeys::KEYPAIR }
+    }
+
+    fn get_key_rotations_key(&self) -> &str {
+        if self.decoy { DBKeys::DURESS_KEY_ROTATIONS } else { DBKeys::KEY_ROTATIONS }
+    }
+
+    //rows identified by a plaintext UUID
+    fn find_row(&self, table: &Table, uuid: &Uuid) -> Result<Option<i64>, Error> {
+        Ok(self.storage.find(table, "uuid", uuid.as_bytes())?.first().copied())
+    }
+
+    //rows identified by an encrypted value, through its lookup key
+    fn find_encrypted_row(&self, table: &Table, value: &[u8]) -> Result<Option<i64>, Error> {
+        Ok(self.storage.find(table, "lookup", &crypto::compute_lookup_key(&self.master_key, value))?.first().copied())
+    }
+
+    //rows whose encrypted column decrypts to value, ordered by id. Only used for contact references:
+    //a lookup key would reveal which rows belong to the same contact, so the whole table is decrypted.
+    fn find_rows(&self, table: &Table, column: &str, value: &[u8]) -> Result<Vec<i64>, Error> {
+        let rows = self.storage.select(table, &[column])?;
+        Ok(decrypt_rows(&self.master_key, rows, 0).into_iter().filter(|(_, _, plain_value)| plain_value == value).map(|(id, _, _)| id).collect())
+    }
+
+    fn get_column(&self, table: &Table, id: i64, column: &str) -> Result<Option<Vec<u8>>, Error> {
+        Ok(self.storage.get(table, id, &[column])?.and_then(|mut row| row.remove(0)))
+    }
+
+    fn update_row(&self, table: &Table, uuid: &Uuid, column: &str, value: Option<&[u8]>) -> Result<usize, Error> {
+        match self.find_row(table, uuid)? {
+            Some(id) => self.storage.update(table, id, column, value),
+            None => Ok(0)
+        }
+    }
+
+    fn get_contact_record(&self, id: i64) -> Result<Option<ContactRecord>, Error> {
+        //rows of the other identity are skipped silently like in decrypt_rows
+        Ok(self.get_column(&CONTACTS_TABLE, id, "record")?.and_then(|encrypted_record| {
+            ContactRecord::from_bytes(&crypto::decrypt_data(&encrypted_record, &self.master_key).ok()?)
+        }))
+    }
+
+    fn set_contact_record(&self, id: i64, record: &ContactRecord) -> Result<usize, Error> {
+        let encrypted_record = crypto::encrypt_data(&record.to_bytes(), &self.master_key).unwrap();
+        self.storage.update(&CONTACTS_TABLE, id, "record", Some(&encrypted_record))
+    }
+
+    fn update_contact<F: FnOnce(&mut ContactRecord)>(&self, uuid: &Uuid, f: F) -> Result<usize, Error> {
+        self.storage.transaction(|| {
+            let id = match self.find_row(&CONTACTS_TABLE, uuid)? {
+                Some(id) => id,
+                None => return Ok(0)
+            };
+            match self.get_contact_record(id)? {
+                Some(mut record) => {
+                    f(&mut record);
+                    self.set_contact_record(id, &record)
+                }
+                None => Ok(0)
+            }
+        })
+    }
+
+    //all the rotations of this identity, oldest first, concatenated
+    pub fn get_key_rotations(&self) -> Result<Vec<u8>, Error> {
+        match self.storage.get_value(self.get_key_rotations_key())? {
+            Some(encrypted_key_rotations) => match crypto::decrypt_data(&encrypted_key_rotations, &self.master_key) {
+                Ok(key_rotations) => Ok(key_rotations),
+                Err(e) => {
+                    print_error!(e);
+                    Ok(Vec::new())
+                }
+            }
+            None => Ok(Vec::new())
+        }
+    }
+
+    //replace the identity keypair. The old key signs the new one so that contacts can follow the change.
+    pub fn rotate_keypair(&mut self) -> Result<KeyRotation, Error> {
+        let new_keypair = Keypair::generate(&mut rand_7::rngs::OsRng);
+        let key_rotation = KeyRotation::new(&self.keypair, &new_keypair);
+        let mut key_rotations = self.get_key_rotations()?;
+        key_rotations.extend(key_rotation.to_bytes());
+        let encrypted_key_rotations = crypto::encrypt_data(&key_rotations, &self.master_key).unwrap();
+        let mut keypair_bytes = new_keypair.to_bytes();
+        let encrypted_keypair = crypto::encrypt_data(&keypair_bytes, &self.master_key).unwrap();
+        keypair_bytes.zeroize();
+        self.storage.transaction(|| {
+            self.storage.set_value(self.get_key_rotations_key(), &encrypted_key_rotations)?;
+            self.storage.set_value(self.get_keypair_key(), &encrypted_keypair)
+        })?;
+        self.keypair = new_keypair;
+        Ok(key_rotation)
+    }
+
+    //find the contact whose stored key leads to the peer's key through valid rotations
+    pub fn find_rotated_contact(&self, key_rotations: &[u8], peer_public_key: &[u8]) -> Option<Uuid> {
+        let key_rotations: Vec<KeyRotation> = key_rotations.chunks(KEY_ROTATION_LEN).filter_map(KeyRotation::from_bytes).collect();
+        if key_rotations.is_empty() {
+            return None;
+        }
+        self.load_contacts()?.into_iter().find(|contact| {
+            matches!(KeyRotation::follow_chain(&key_rotations, &contact.public_key, peer_public_key), Some(hops) if hops > 0)
+        }).map(|contact| contact.uuid)
+    }
+
+    fn insert_dummy_contact(&self) -> Result<i64, Error> {
+        let mut name = vec![0; OsRng.gen_range(4..32)];
+        OsRng.fill_bytes(&mut name);
+        let encrypted_record = crypto::encrypt_data(&ContactRecord::new(DUMMY_CONTACT_KEY, name).to_bytes(), &self.master_key).unwrap();
+        self.storage.insert(&CONTACTS_TABLE, &[("uuid", Uuid::new_v4().as_bytes()), ("record", &encrypted_record)])
+    }
+
+    fn find_dummy_contact(&self) -> Result<Option<i64>, Error> {
+        let rows = self.storage.select(&CONTACTS_TABLE, &["record"])?;
+        Ok(decrypt_rows(&self.master_key, rows, 0).into_iter().find(|(_, _, record)| record.starts_with(&DUMMY_CONTACT_KEY)).map(|(id, _, _)| id))
+    }
+
+    //fill the contacts table with dummy rows so that its size doesn't reveal the number of contacts
+    fn pad_contacts(&self) -> Result<(), Error> {
+        let count = self.storage.select(&CONTACTS_TABLE, &["uuid"])?.len();
+        for _ in 0..(CONTACTS_BUCKET_SIZE-count%CONTACTS_BUCKET_SIZE)%CONTACTS_BUCKET_SIZE {
+            self.insert_dummy_contact()?;
+        }
+        Ok(())
+    }
+
+    pub fn add_contact(&self, name: String, avatar_uuid: Option<Uuid>, public_key: [u8; PUBLIC_KEY_LENGTH]) -> Result<Contact, Error> {
+        let contact_uuid = Uuid::new_v4();
+        let encrypted_record = crypto::encrypt_data(&ContactRecord::new(public_key, name.as_bytes().to_vec()).to_bytes(), &self.master_key).unwrap();
+        let mut values = vec![("uuid", &contact_uuid.as_bytes()[..]), ("record", &encrypted_record)];
+        if let Some(avatar_uuid) = &avatar_uuid {
+            values.push(("avatar", avatar_uuid.as_bytes()));
+        }
+        self.storage.transaction(|| {
+            if let Some(dummy_id) = self.find_dummy_contact()? {
+                self.storage.delete(&CONTACTS_TABLE, dummy_id)?;
+            }
+            self.storage.insert(&CONTACTS_TABLE, &values)?;
+            self.pad_contacts()
+        })?;
+        Ok(Contact {
+            uuid: contact_uuid,
+            public_key,
+            name,
+            avatar: avatar_uuid,
+            verified: false,
+            seen: true,
+        })
+    }
+
+    pub fn remove_contact(&self, uuid: &Uuid) -> Result<usize, Error> {
+        self.storage.transaction(|| {
+            self.delete_conversation(uuid)?;
+            for id in self.find_rows(&KEY_HISTORY_TABLE, "contact", uuid.as_bytes())? {
+                self.storage.delete(&KEY_HISTORY_TABLE, id)?;
+            }
+            if let Some(groups) = self.load_groups() {
+                for mut group in groups {
+                    if group.members.contains(uuid) {
+                        group.members.retain(|member| member != uuid);
+                        self.set_group_members(&group.uuid, &group.members)?;
+                    }
+                }
+            }
+            let result = match self.find_row(&CONTACTS_TABLE, uuid)? {
+                Some(id) => self.storage.delete(&CONTACTS_TABLE, id)?,
+                None => 0
+            };
+            self.pad_contacts()?;
+            self.storage.scrub()?;
+            Ok(result)
+        })
+    }
+
+    fn update_verified(&self, uuid: &Uuid, verified: bool) -> Result<usize, Error> {
+        self.update_contact(uuid, |record| record.verified = verified)
+    }
+
+    pub fn set_verified(&self, uuid: &Uuid) -> Result<usize, Error> {
+        self.update_verified(uuid, true)
+    }
+
+    pub fn set_unverified(&self, uuid: &Uuid) -> Result<usize, Error> {
+        self.update_verified(uuid, false)
+    }
+
+    //replace the identity key of a contact, keeping the old one in its history. Verification is revoked since it was bound to the old key.
+    //returns the old key, or None if the key didn't change
+    pub fn change_contact_key(&self, uuid: &Uuid, new_public_key: [u8; PUBLIC_KEY_LENGTH], timestamp: u64) -> Result<Option<[u8; PUBLIC_KEY_LENGTH]>, String> {
+        let id = self.find_row(&CONTACTS_TABLE, uuid).map_err(|e| e.to_string())?.ok_or_else(|| Error::QueryReturnedNoRows.to_string())?;
+        let mut record = self.get_contact_record(id).map_err(|e| e.to_string())?.ok_or_else(|| String::from(DATABASE_CORRUPED_ERROR))?;
+        let old_key = record.public_key;
+        if old_key == new_public_key {
+            return Ok(None);
+        }
+        record.public_key = new_public_key;
+        record.verified = false;
+        let encrypted_contact = crypto::encrypt_data(uuid.as_bytes(), &self.master_key).unwrap();
+        let encrypted_old_key = crypto::encrypt_data(&old_key, &self.master_key).unwrap();
+        let encrypted_timestamp = crypto::encrypt_data(&timestamp.to_be_bytes(), &self.master_key).unwrap();
+        self.storage.transaction(|| {
+            self.storage.insert(&KEY_HISTORY_TABLE, &[("contact", &encrypted_contact), ("key", &encrypted_old_key), ("timestamp", &encrypted_timestamp)])?;
+            self.set_contact_record(id, &record)
+        }).map_err(|e| e.to_string())?;
+        Ok(Some(old_key))
+    }
+
+    pub fn load_key_history(&self, uuid: &Uuid) -> Result<Vec<PreviousKey>, Error> {
+        let mut previous_keys = Vec::new();
+        for id in self.find_rows(&KEY_HISTORY_TABLE, "contact", uuid.as_bytes())? {
+            let row = self.storage.get(&KEY_HISTORY_TABLE, id, &["key", "timestamp"])?.ok_or(Error::QueryReturnedNoRows)?;
+            let (encrypted_key, encrypted_timestamp) = (row[0].clone().unwrap_or_default(), row[1].clone().unwrap_or_default());
+            match (crypto::decrypt_data(&encrypted_key, &self.master_key), crypto::decrypt_data(&encrypted_timestamp, &self.master_key)) {
+                //skip malformed rows like load_groups
+                (Ok(public_key), Ok(timestamp)) => match (public_key.try_into(), timestamp.try_into()) {
+                    (Ok(public_key), Ok(timestamp)) => previous_keys.push(PreviousKey {
+                        public_key,
+                        timestamp: u64::from_be_bytes(timestamp),
+                    }),
+                    _ => print_error!("Malformed key history row")
+                }
+                (Err(e), _) | (_, Err(e)) => print_error!(e)
+            }
+        }
+        Ok(previous_keys)
+    }
+
+    pub fn get_safety_number(&self, peer_public_key: &[u8]) -> String {
+        crypto::generate_safety_number(&self.get_public_key(), peer_public_key)
+    }
+
+    pub fn get_qr_payload(&self) -> String {
+        QrPayload::new(self.get_public_key()).encode()
+    }
+
+    fn get_discovery_token(&self, peer_public_key: &[u8], announcer_public_key: &[u8], epoch: u64) -> Option<[u8; crypto::DISCOVERY_TOKEN_LEN]> {
+        crypto::derive_pairwise_secret(&self.keypair.secret, &self.get_public_key(), peer_public_key)
+            .map(|pairwise_secret| crypto::compute_discovery_token(&pairwise_secret, announcer_public_key, epoch))
+    }
+
+    //one token per contact: only them can recognize us
+    pub fn get_discovery_announcement(&self, port: u16, epoch: u64) -> Announcement {
+        let public_key = self.get_public_key();
+        let tokens = self.load_contacts().unwrap_or_default().iter().filter_map(|contact| {
+            self.get_discovery_token(&contact.public_key, &public_key, epoch)
+        }).collect();
+        Announcement::new(port, tokens)
+    }
+
+    //adjacent epochs are accepted to tolerate clock drift
+    pub fn find_contact_by_discovery_tokens(&self, tokens: &[[u8; crypto::DISCOVERY_TOKEN_LEN]], epoch: u64) -> Option<Uuid> {
+        if tokens.is_empty() {
+            return None;
+        }
+        self.load_contacts()?.into_iter().find(|contact| {
+            (epoch.saturating_sub(1)..=epoch+1).any(|epoch| {
+                self.get_discovery_token(&contact.public_key, &contact.public_key, epoch).is_some_and(|token| tokens.contains(&token))
+            })
+        }).map(|contact| contact.uuid)
+    }
+
+    //returns whether the scanned payload matched the contact's key, in which case the contact is marked as verified
+    pub fn verify_contact_qr_payload(&self, uuid: &Uuid, payload: &str) -> Result<bool, Error> {
+        let contacts = self.load_contacts().ok_or(Error::QueryReturnedNoRows)?;
+        let contact = contacts.iter().find(|contact| contact.uuid == *uuid).ok_or(Error::QueryReturnedNoRows)?;
+        if QrPayload::verify(payload, &contact.public_key) {
+            self.set_verified(uuid)?;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    fn find_blocked(&self, public_key: &[u8]) -> Result<Option<i64>, Error> {
+        self.find_encrypted_row(&BLOCKED_TABLE, public_key)
+    }
+
+    pub fn block(&self, public_key: &[u8]) -> Result<usize, Error> {
+        if self.find_blocked(public_key)?.is_some() {
+            return Ok(0);
+        }
+        let encrypted_public_key = crypto::encrypt_data(public_key, &self.master_key).unwrap();
+        self.storage.insert(&BLOCKED_TABLE, &[("key", &encrypted_public_key), ("lookup", &crypto::compute_lookup_key(&self.master_key, public_key))])?;
+        Ok(1)
+    }
+
+    pub fn unblock(&self, public_key: &[u8]) -> Result<usize, Error> {
+        match self.find_blocked(public_key)? {
+            Some(id) => self.storage.delete(&BLOCKED_TABLE, id),
+            None => Ok(0)
+        }
+    }
+
+    pub fn is_blocked(&self, public_key: &[u8]) -> Result<bool, Error> {
+        Ok(self.find_blocked(public_key)?.is_some())
+    }
+
+    pub fn load_blocked(&self) -> Result<Vec<Vec<u8>>, Error> {
+        let mut blocked = Vec::new();
+        for (_, row) in self.storage.select(&BLOCKED_TABLE, &["key"])? {
+            //rows of the other identity are skipped silently like in decrypt_rows
+            if let Ok(public_key) = crypto::decrypt_data(&row[0].clone().unwrap_or_default(), &self.master_key) {
+                blocked.push(public_key);
+            }
+        }
+        Ok(blocked)
+    }
+
+    pub fn get_contact_settings(&self, uuid: &Uuid) -> Result<ContactSettings, String> {
+        let id = self.find_row(&CONTACTS_TABLE, uuid).map_err(|e| e.to_string())?.ok_or_else(|| Error::QueryReturnedNoRows.to_string())?;
+        match self.get_contact_record(id).map_err(|e| e.to_string())? {
+            Some(record) => Ok(record.settings),
+            None => Err(String::from(DATABASE_CORRUPED_ERROR))
+        }
+    }
+
+    pub fn set_contact_settings(&self, uuid: &Uuid, settings: &ContactSettings) -> Result<usize, Error> {
+        self.update_contact(uuid, |record| record.settings = settings.clone())
+    }
+
+    //delete the messages of a conversation older than its retention period.
+    //This runs on every sweep, so it relies on secure_delete instead of rewriting the whole database with scrub().
+    pub fn delete_expired_msgs(&self, contact_uuid: &Uuid, now: u64) -> Result<usize, String> {
+        let retention = self.get_contact_settings(contact_uuid)?.retention;
+        if retention == 0 {
+            return Ok(0);
+        }
+        let result = self.storage.transaction(|| {
+            let mut deleted = 0;
+            for (id, message) in self.load_messages(&self.get_conversation(contact_uuid)?)? {
+                if message.timestamp.saturating_add(retention) < now {
+                    deleted += self.storage.delete(&MESSAGES_TABLE, id)?;
+                }
+            }
+            Ok(deleted)
+        });
+        if !matches!(result, Ok(0)) {
+            self.invalidate_conversations();
+        }
+        result.map_err(|e| e.to_string())
+    }
+
+    pub fn change_contact_name(&self, uuid: &Uuid, new_name: &str) -> Result<usize, Error> {
+        self.update_contact(uuid, |record| record.name = new_name.as_bytes().to_vec())
+    }
+
+    pub fn set_contact_avatar(&self, contact_uuid: &Uuid, avatar_uuid: Option<&Uuid>) -> Result<usize, Error> {
+        match avatar_uuid {
+            Some(avatar_uuid) => self.update_row(&CONTACTS_TABLE, contact_uuid, "avatar", Some(avatar_uuid.as_bytes())),
+            None => self.storage.transaction(|| {
+                let contact_id = match self.find_row(&CONTACTS_TABLE, contact_uuid)? {
+                    Some(id) => id,
+                    None => return Ok(0)
+                };
+                if let Some(old_avatar) = self.get_column(&CONTACTS_TABLE, contact_id, "avatar")?.and_then(|uuid| to_uuid_bytes(&uuid)) {
+                    if let Some(avatar_id) = self.find_row(&AVATARS_TABLE, &Uuid::from_bytes(old_avatar))? {
+                        self.storage.delete(&AVATARS_TABLE, avatar_id)?;
+                    }
+                }
+                self.storage.update(&CONTACTS_TABLE, contact_id, "avatar", None)
+            })
+        }
+    }
+
+    pub fn set_contact_seen(&self, uuid: &Uuid, seen: bool) -> Result<usize, Error> {
+        self.update_contact(uuid, |record| record.seen = seen)
+    }
+
+    fn parse_contact(row: Row, record: &[u8]) -> Option<Contact> {
+        let record = ContactRecord::from_bytes(record)?;
+        if record.public_key == DUMMY_CONTACT_KEY {
+            return None;
+        }
+        Some(Contact {
+            uuid: Uuid::from_bytes(to_uuid_bytes(row[0].as_deref().unwrap_or_default())?),
+            public_key: record.public_key,
+            name: String::from_utf8(record.name).ok()?,
+            avatar: row[1].as_deref().and_then(to_uuid_bytes).map(Uuid::from_bytes),
+            verified: record.verified,
+            seen: record.seen,
+        })
+    }
+
+    pub fn load_contacts(&self) -> Option<Vec<Contact>> {
+        match self.storage.select(&CONTACTS_TABLE, &["uuid", "avatar", "record"]) {
+            Ok(rows) => Some(decrypt_rows(&self.master_key, rows, 2).into_iter().filter_map(|(_, row, record)| Identity::<S>::parse_contact(row, &record)).collect()),
+            Err(e) => {
+                print_error!(e);
+                None
+            }
+        }
+    }
+
+    //files and avatars not linked to any contact. Only our own rows: those of the other identity sharing the database can't be decrypted.
+    pub fn clear_cache(&self) -> Result<(), Error> {
+        self.storage.transaction(|| {
+            let mut deleted = 0;
+            for (id, row, _) in decrypt_rows(&self.master_key, self.storage.select(&FILES_TABLE, &["contact_uuid", "uuid"])?, 1) {
+                if row[0].is_none() {
+                    deleted += self.storage.delete(&FILES_TABLE, id)?;
+                }
+            }
+            let contact_avatars: Vec<Vec<u8>> = self.storage.select(&CONTACTS_TABLE, &["avatar"])?.into_iter().filter_map(|(_, mut row)| row.remove(0)).collect();
+            for (id, row, _) in decrypt_rows(&self.master_key, self.storage.select(&AVATARS_TABLE, &["uuid", "data"])?, 1) {
+                if !row[0].as_ref().is_some_and(|uuid| contact_avatars.contains(uuid)) {
+                    deleted += self.storage.delete(&AVATARS_TABLE, id)?;
+                }
+            }
+            if deleted > 0 {
+                self.storage.scrub()?;
+            }
+            Ok(())
+        })
+    }
+
+    pub fn load_file(&self, uuid: Uuid) -> Option<Vec<u8>> {
+        let id = match self.find_encrypted_row(&FILES_TABLE, uuid.as_bytes()) {
+            Ok(id) => id?,
+            Err(e) => {
+                print_error!(e);
+                return None;
+            }
+        };
+        match self.get_column(&FILES_TABLE, id, "data") {
+            Ok(encrypted_data) => match crypto::decrypt_data(&encrypted_data?, &self.master_key) {
+                Ok(data) => Some(data),
+                Err(e) => {
+                    print_error!(e);
+                    None
+                }
+            }
+            Err(e) => {
+                print_error!(e);
+                None
+            }
+        }
+    }
+
+    pub fn store_file(&self, contact_uuid: Option<Uuid>, data: &[u8]) -> Result<Uuid, Error> {
+        let file_uuid = Uuid::new_v4();
+        let encrypted_uuid = crypto::encrypt_data(file_uuid.as_bytes(), &self.master_key).unwrap();
+        let encrypted_data = crypto::encrypt_data(data, &self.master_key).unwrap();
+        let lookup_key = crypto::compute_lookup_key(&self.master_key, file_uuid.as_bytes());
+        match contact_uuid {
+            Some(uuid) => {
+                let encrypted_contact = crypto::encrypt_data(uuid.as_bytes(), &self.master_key).unwrap();
+                self.storage.insert(&FILES_TABLE, &[("contact_uuid", &encrypted_contact), ("uuid", &encrypted_uuid), ("data", &encrypted_data), ("lookup", &lookup_key)])?
+            }
+            None => self.storage.insert(&FILES_TABLE, &[("uuid", &encrypted_uuid), ("data", &encrypted_data), ("lookup", &lookup_key)])?
+        };
+        Ok(file_uuid)
+    }
+
+    //the contact reference of each message is encrypted separately so that the database doesn't reveal which messages belong to the same conversation.
+    //they are all decrypted once and then kept in memory, instead of for every page of messages.
+    fn get_conversation(&self, contact_uuid: &Uuid) -> Result<Vec<i64>, Error> {
+        let mut conversations = self.conversations.borrow_mut();
+        if conversations.is_none() {
+            let mut index: HashMap<Uuid, Vec<i64>> = HashMap::new();
+            for (id, _, contact) in decrypt_rows(&self.master_key, self.storage.select(&MESSAGES_TABLE, &["contact"])?, 0) {
+                if let Some(contact) = to_uuid_bytes(&contact) {
+                    index.entry(Uuid::from_bytes(contact)).or_default().push(id);
+                }
+            }
+            *conversations = Some(index);
+        }
+        Ok(conversations.as_ref().unwrap().get(contact_uuid).cloned().unwrap_or_default())
+    }
+
+    fn add_to_conversation(&self, contact_uuid: &Uuid, ids: &[i64]) {
+        if let Some(conversations) = self.conversations.borrow_mut().as_mut() {
+            conversations.entry(*contact_uuid).or_default().extend(ids);
+        }
+    }
+
+    //rebuilt from the database the next time it's needed, which also covers rolled back deletions
+    fn invalidate_conversations(&self) {
+        self.conversations.replace(None);
+    }
+
+    pub fn store_msg(&self, contact_uuid: &Uuid, message: Message) -> Result<usize, Error> {
+        let encrypted_message = encrypt_message(&self.master_key, contact_uuid, &message);
+        let id = self.storage.insert(&MESSAGES_TABLE, &MESSAGE_COLUMNS.iter().copied().zip(encrypted_message.iter().map(Vec::as_slice)).collect::<Vec<_>>())?;
+        self.add_to_conversation(contact_uuid, &[id]);
+        Ok(1)
+    }
+
+    //for bursts of messages received after a reconnection or imported from history. Returns the ids of the new rows in the same order.
+    pub fn store_msgs(&self, contact_uuid: &Uuid, messages: &[Message]) -> Result<Vec<i64>, Error> {
+        let master_key = &self.master_key;
+        let encrypted_messages: Vec<[Vec<u8>; 2]> = messages.par_iter().map(|message| encrypt_message(master_key, contact_uuid, message)).collect();
+        let rows: Vec<Vec<&[u8]>> = encrypted_messages.iter().map(|encrypted_message| encrypted_message.iter().map(Vec::as_slice).collect()).collect();
+        let ids = self.storage.insert_many(&MESSAGES_TABLE, &MESSAGE_COLUMNS, &rows)?;
+        self.add_to_conversation(contact_uuid, &ids);
+        Ok(ids)
+    }
+
+    //fetched in one query but decrypted in parallel, in the order of ids
+    fn load_messages(&self, ids: &[i64]) -> Result<Vec<(i64, Message)>, Error> {
+        let rows = self.storage.get_many(&MESSAGES_TABLE, ids, &["record"])?;
+        Ok(decrypt_rows(&self.master_key, rows, 0).into_iter().filter_map(|(id, _, record)| Some((id, Message::from_record(&record)?))).collect())
+    }
+
+    pub fn load_msgs(&self, contact_uuid: &Uuid, offset: usize, mut count: usize) -> Option<Vec<Message>> {
+        let ids = match self.get_conversation(contact_uuid) {
+            Ok(ids) => ids,
+            Err(e) => {
+                print_error!(e);
+                return None;
+            }
+        };
+        let total = ids.len();
+        if offset >= total {
+            return None;
+        }
+        if offset+count >= total {
+            count = total-offset;
+        }
+        match self.load_messages(&ids[total-offset-count..total-offset]) {
+            Ok(msgs) => Some(msgs.into_iter().map(|(_, message)| message).collect()),
+            Err(e) => {
+                print_error!(e);
+                None
+            }
+        }
+    }
+
+    //groups created locally get a new UUID while groups we are invited to keep the UUID chosen by their creator
+    pub fn add_group(&self, uuid: Option<Uuid>, name: String, members: Vec<Uuid>) -> Result<Group, Error> {
+        let group_uuid = uuid.unwrap_or_else(Uuid::new_v4);
+        let encrypted_name = crypto::encrypt_data(name.as_bytes(), &self.master_key).unwrap();
+        let encrypted_members = crypto::encrypt_data(&members.iter().flat_map(|member| *member.as_bytes()).collect::<Vec<u8>>(), &self.master_key).unwrap();
+        self.storage.insert(&GROUPS_TABLE, &[("uuid", group_uuid.as_bytes()), ("name", &encrypted_name), ("members", &encrypted_members)])?;
+        Ok(Group {
+            uuid: group_uuid,
+            name,
+            members,
+        })
+    }
+
+    pub fn set_group_members(&self, uuid: &Uuid, members: &[Uuid]) -> Result<usize, Error> {
+        let encrypted_members = crypto::encrypt_data(&members.iter().flat_map(|member| *member.as_bytes()).collect::<Vec<u8>>(), &self.master_key).unwrap();
+        self.update_row(&GROUPS_TABLE, uuid, "members", Some(&encrypted_members))
+    }
+
+    pub fn change_group_name(&self, uuid: &Uuid, new_name: &str) -> Result<usize, Error> {
+        let encrypted_name = crypto::encrypt_data(new_name.as_bytes(), &self.master_key).unwrap();
+        self.update_row(&GROUPS_TABLE, uuid, "name", Some(&encrypted_name))
+    }
+
+    pub fn remove_group(&self, uuid: &Uuid) -> Result<usize, Error> {
+        self.storage.transaction(|| {
+            self.delete_conversation(uuid)?;
+            let result = match self.find_row(&GROUPS_TABLE, uuid)? {
+                Some(id) => self.storage.delete(&GROUPS_TABLE, id)?,
+                None => 0
+            };
+            self.storage.scrub()?;
+            Ok(result)
+        })
+    }
+
+    pub fn load_groups(&self) -> Option<Vec<Group>> {
+        match self.storage.select(&GROUPS_TABLE, &["uuid", "name", "members"]) {
+            Ok(rows) => {
+                let mut groups = Vec::new();
+                for (_, row) in rows {
+                    let encrypted_name = row[1].clone().unwrap_or_default();
+                    let encrypted_members = row[2].clone().unwrap_or_default();
+                    //rows of the other identity are skipped silently like in decrypt_rows
+                    if let (Ok(name), Ok(members)) = (crypto::decrypt_data(&encrypted_name, &self.master_key), crypto::decrypt_data(&encrypted_members, &self.master_key)) {
+                        //skip malformed rows like load_contacts
+                        if let (Some(uuid), Ok(name)) = (row[0].as_deref().and_then(to_uuid_bytes), String::from_utf8(name)) {
+                            groups.push(Group {
+                                uuid: Uuid::from_bytes(uuid),
+                                name,
+                                members: members.chunks(16).filter_map(to_uuid_bytes).map(Uuid::from_bytes).collect(),
+                            })
+                        }
+                    }
+                }
+                Some(groups)
+            }
+            Err(e) => {
+                print_error!(e);
+                None
+            }
+        }
+    }
+
+    //group messages are stored once in the group conversation, prefixed by the UUID of the contact who sent them
+    pub fn store_group_msg(&self, group_uuid: &Uuid, sender: Option<&Uuid>, mut message: Message) -> Result<usize, Error> {
+        let sender = match sender {
+            Some(sender) => *sender.as_bytes(),
+            None => *Uuid::nil().as_bytes()
+        };
+        message.data = [&sender[..], &message.data].concat();
+        self.store_msg(group_uuid, message)
+    }
+
+    pub fn load_group_msgs(&self, group_uuid: &Uuid, offset: usize, count: usize) -> Option<Vec<(Option<Uuid>, Message)>> {
+        Some(self.load_msgs(group_uuid, offset, count)?.into_iter().filter(|message| message.data.len() > 16).map(|mut message| {
+            let sender = Uuid::from_bytes(message.data[..16].try_into().unwrap());
+            message.data.drain(..16);
+            (if sender.is_nil() { None } else { Some(sender) }, message)
+        }).collect())
+    }
+
+    pub fn delete_conversation(&self, contact_uuid: &Uuid) -> Result<usize, Error> {
+        let result = self.storage.transaction(|| {
+            for id in self.find_rows(&FILES_TABLE, "contact_uuid", contact_uuid.as_bytes())? {
+                self.storage.delete(&FILES_TABLE, id)?;
+            }
+            let mut deleted = 0;
+            for id in self.get_conversation(contact_uuid)? {
+                deleted += self.storage.delete(&MESSAGES_TABLE, id)?;
+            }
+            self.storage.scrub()?;
+            Ok(deleted)
+        });
+        self.invalidate_conversations();
+        result
+    }
+
+    pub fn change_name(&mut self, new_name: String) -> Result<usize, Error> {
+        let encrypted_name = crypto::encrypt_data(new_name.as_bytes(), &self.master_key).unwrap();
+        let result = self.storage.set_value(self.get_name_key(), &encrypted_name);
+        if result.is_ok() {
+            self.name = new_name;
+        }
+        result
+    }
+
+    //use_padding is kept in sync for databases opened by older versions
+    pub fn set_padding_policy(&mut self, padding_policy: PaddingPolicy) -> Result<usize, Error> {
+        self.padding_policy = padding_policy;
+        let encrypted_use_padding = crypto::encrypt_data(&[bool_to_byte(padding_policy != PaddingPolicy::None)], &self.master_key).unwrap();
+        let encrypted_padding_policy = crypto::encrypt_data(&padding_policy.to_bytes(), &self.master_key).unwrap();
+        self.storage.transaction(|| {
+            self.storage.set_value(self.get_use_padding_key(), &encrypted_use_padding)?;
+            self.storage.set_value(self.get_padding_policy_key(), &encrypted_padding_policy)
+        })
+    }
+
+    pub fn set_cover_traffic_rate(&mut self, cover_traffic_rate: u32) -> Result<usize, Error> {
+        let result = self.storage.set_value(self.get_cover_traffic_rate_key(), &crypto::encrypt_data(&cover_traffic_rate.to_be_bytes(), &self.master_key).unwrap());
+        if result.is_ok() {
+            self.cover_traffic_rate = cover_traffic_rate;
+        }
+        result
+    }
+
+    pub fn store_avatar(&self, avatar: &[u8]) -> Result<Uuid, Error> {
+        let uuid = Uuid::new_v4();
+        let encrypted_avatar = crypto::encrypt_data(avatar, &self.master_key).unwrap();
+        self.storage.insert(&AVATARS_TABLE, &[("uuid", uuid.as_bytes()), ("data", &encrypted_avatar)])?;
+        Ok(uuid)
+    }
+
+    pub fn get_avatar(&self, avatar_uuid: &Uuid) -> Option<Vec<u8>> {
+        let id = self.find_row(&AVATARS_TABLE, avatar_uuid).ok()??;
+        let encrypted_avatar = self.get_column(&AVATARS_TABLE, id, "data").ok()??;
+        crypto::decrypt_data(&encrypted_avatar, &self.master_key).ok() //may belong to the other identity
+    }
+
+    pub fn zeroize(&mut self){
+        self.master_key.zeroize();
+        self.keypair.secret.zeroize();
+    }
+
+    fn load_encrypted_identity(storage: &S, decoy: bool) -> Result<EncryptedIdentity, Error> {
+        if decoy {
+            return Ok(EncryptedIdentity {
+                encrypted_name: get_required(storage, DBKeys::DURESS_NAME)?,
+                encrypted_keypair: get_required(storage, DBKeys::DURESS_KEYPAIR)?,
+                salt: get_required(storage, DBKeys::DURESS_SALT)?,
+                encrypted_master_key: get_required(storage, DBKeys::DURESS_MASTER_KEY)?,
+                encrypted_use_padding: get_required(storage, DBKeys::DURESS_USE_PADDING)?,
+                encrypted_padding_policy: storage.get_value(DBKeys::DURESS_PADDING_POLICY)?,
+                encrypted_cover_traffic_rate: storage.get_value(DBKeys::DURESS_COVER_TRAFFIC_RATE)?,
+                encrypted_encrypt_database: None,
+                encrypted_database_key: storage.get_value(DBKeys::DURESS_DATABASE_KEY)?,
+            });
+        }
+        let encrypted_name = get_required(storage, DBKeys::NAME)?;
+        let encrypted_keypair = get_required(storage, DBKeys::KEYPAIR)?;
+        let salt = get_required(storage, DBKeys::SALT)?;
+        let encrypted_master_key = get_required(storage, DBKeys::MASTER_KEY)?;
+        let encrypted_use_padding = get_required(storage, DBKeys::USE_PADDING)?;
+        let encrypted_padding_policy = storage.get_value(DBKeys::PADDING_POLICY)?;
+        let encrypted_cover_traffic_rate = storage.get_value(DBKeys::COVER_TRAFFIC_RATE)?;
+        let encrypted_encrypt_database = storage.get_value(DBKeys::ENCRYPT_DATABASE)?;
+        Ok(EncryptedIdentity {
+            encrypted_name,
+            encrypted_keypair,
+            salt,
+            encrypted_master_key,
+            encrypted_use_padding,
+            encrypted_padding_policy,
+            encrypted_cover_traffic_rate,
+            encrypted_encrypt_database,
+            encrypted_database_key: None,
+        })
+    }
+
+    pub fn load(storage: S, password: Option<&[u8]>) -> Result<Identity<S>, String> {
+        if let Err(e) = Identity::init_duress_slot(&storage) {
+            print_error!(e);
+        }
+        match Identity::load_encrypted_identity(&storage, false) {
+            Ok(encrypted_identity) => {
+                let master_key: [u8; crypto::MASTER_KEY_LEN] = match password {
+                    Some(password) => {
+                        //always derive both keys so that login time doesn't reveal which password was entered
+                        let duress_master_key = Identity::decrypt_duress_master_key(&storage, password);
+                        match crypto::decrypt_master_key(&encrypted_identity.encrypted_master_key, password, &encrypted_identity.salt) {
+                            Ok(master_key) => master_key,
+                            Err(e) => return match e {
+                                CryptoError::DecryptionFailed => Identity::load_duress_identity(storage, duress_master_key),
+                                CryptoError::InvalidLength => Err(String::from(DATABASE_CORRUPED_ERROR))
+                            }
+                        }
+                    }
+                    None => if encrypted_identity.encrypted_master_key.len() == crypto::MASTER_KEY_LEN {
+                        encrypted_identity.encrypted_master_key[..].try_into().unwrap()
+                    } else {
+                        return Err(String::from(DATABASE_CORRUPED_ERROR))
+                    }
+                };
+                Identity::decrypt_identity(storage, encrypted_identity, master_key, false)
+            }
+            Err(e) => Err(e.to_string())
+        }
+    }
+
+    fn decrypt_identity(mut storage: S, encrypted_identity: EncryptedIdentity, master_key: [u8; crypto::MASTER_KEY_LEN], decoy: bool) -> Result<Identity<S>, String> {
+        let name = if decoy {
+            crypto::decrypt_data(&encrypted_identity.encrypted_name, &master_key).map_err(|e| e.to_string()).and_then(|name| String::from_utf8(name).map_err(|e| e.to_string()))
+        } else {
+            Identity::decrypt_name(&storage, &encrypted_identity.encrypted_name, &master_key)
+        };
+        let name = match name {
+            Ok(name) => name,
+            Err(e) => {
+                print_error!(e);
+                return Err(String::from(DATABASE_CORRUPED_ERROR))
+            }
+        };
+        //the decoy can't read the flag of the real identity but shares its database, whose key was saved in the duress slot
+        let (encrypt_database, mut database_key) = if decoy {
+            let database_key = encrypted_identity.encrypted_database_key.and_then(|encrypted_database_key| crypto::decrypt_data(&encrypted_database_key, &master_key).ok());
+            (storage.has_encrypted_database(), database_key)
+        } else {
+            let encrypt_database = match encrypted_identity.encrypted_encrypt_database {
+                Some(encrypted_encrypt_database) => match crypto::decrypt_data(&encrypted_encrypt_database, &master_key) {
+                    Ok(encrypt_database) => match encrypt_database[..] {
+                        [flag] if byte_to_bool(flag).is_ok() => flag == bool_to_byte(true),
+                        _ => {
+                            print_error!("Invalid database encryption flag");
+                            return Err(String::from(DATABASE_CORRUPED_ERROR))
+                        }
+                    }
+                    Err(e) => {
+                        print_error!(e);
+                        return Err(String::from(DATABASE_CORRUPED_ERROR))
+                    }
+                }
+                None => false
+            };
+            (encrypt_database, Some(crypto::derive_database_key(&master_key).to_vec()))
+        };
+        match crypto::decrypt_data(&encrypted_identity.encrypted_keypair, &master_key) {
+            Ok(keypair) => {
+                match crypto::decrypt_data(&encrypted_identity.encrypted_use_padding, &master_key) {
+                    Ok(use_padding) => {
+                        //databases created before padding policies only have the use_padding flag
+                        let padding_policy = encrypted_identity.encrypted_padding_policy
+                            .and_then(|encrypted_padding_policy| crypto::decrypt_data(&encrypted_padding_policy, &master_key).ok())
+                            .and_then(|padding_policy| PaddingPolicy::from_bytes(&padding_policy))
+                            .unwrap_or(if byte_to_bool(use_padding[0]).unwrap() {
+                                PaddingPolicy::default()
+                            } else {
+                                PaddingPolicy::None
+                            });
+                        let cover_traffic_rate = encrypted_identity.encrypted_cover_traffic_rate
+                            .and_then(|encrypted_cover_traffic_rate| crypto::decrypt_data(&encrypted_cover_traffic_rate, &master_key).ok())
+                            .and_then(|cover_traffic_rate| Some(u32::from_be_bytes(cover_traffic_rate.try_into().ok()?)))
+                            .unwrap_or(0);
+                        storage.unlock(database_key.as_deref().filter(|_| encrypt_database));
+                        database_key.zeroize();
+                        Ok(Identity{
+                            name,
+                            keypair: Keypair::from_bytes(&keypair[..]).unwrap(),
+                            master_key,
+                            padding_policy,
+                            cover_traffic_rate,
+                            encrypt_database,
+                            storage,
+                            decoy,
+                            conversations: RefCell::new(None),
+                        })
+                    }
+                    Err(e) => {
+                        print_error!(e);
+                        Err(String::from(DATABASE_CORRUPED_ERROR))
+                    }
+                }
+            }
+            Err(e) => {
+                print_error!(e);
+                Err(String::from(DATABASE_CORRUPED_ERROR))
+            }
+        }
+    }
+
+    //the duress slot is always filled, with random bytes when no duress password is set, so that its presence can't be detected
+    fn fill_duress_slot(storage: &S) -> Result<(), Error> {
+        let mut name = vec![0; crypto::IV_LEN+OsRng.gen_range(4..32)+crypto::AES_TAG_LEN];
+        let mut keypair = [0; crypto::IV_LEN+KEYPAIR_LENGTH+crypto::AES_TAG_LEN];
+        let mut salt = [0; crypto::SALT_LEN];
+        let mut master_key = [0; crypto::IV_LEN+crypto::MASTER_KEY_LEN+crypto::AES_TAG_LEN];
+        let mut use_padding = [0; crypto::IV_LEN+1+crypto::AES_TAG_LEN];
+        let mut padding_policy = [0; crypto::IV_LEN+PADDING_POLICY_LEN+crypto::AES_TAG_LEN];
+        let mut cover_traffic_rate = [0; crypto::IV_LEN+COVER_TRAFFIC_RATE_LEN+crypto::AES_TAG_LEN];
+        let mut database_key = [0; crypto::IV_LEN+crypto::MASTER_KEY_LEN+crypto::AES_TAG_LEN];
+        storage.transaction(|| {
+            for (key, value) in [
+                (DBKeys::DURESS_NAME, &mut name[..]),
+                (DBKeys::DURESS_KEYPAIR, &mut keypair[..]),
+                (DBKeys::DURESS_SALT, &mut salt[..]),
+                (DBKeys::DURESS_MASTER_KEY, &mut master_key[..]),
+                (DBKeys::DURESS_USE_PADDING, &mut use_padding[..]),
+                (DBKeys::DURESS_PADDING_POLICY, &mut padding_policy[..]),
+                (DBKeys::DURESS_COVER_TRAFFIC_RATE, &mut cover_traffic_rate[..]),
+                (DBKeys::DURESS_DATABASE_KEY, &mut database_key[..]),
+            ] {
+                OsRng.fill_bytes(value);
+                storage.set_value(key, value)?;
+            }
+            Ok(())
+        })
+    }
+
+    fn init_duress_slot(storage: &S) -> Result<(), Error> {
+        storage.transaction(|| match storage.get_value(DBKeys::DURESS_SALT)? {
+            //slots filled by older versions lack the newer settings
+            Some(_) => {
+                for (key, len) in [
+                    (DBKeys::DURESS_PADDING_POLICY, PADDING_POLICY_LEN),
+                    (DBKeys::DURESS_COVER_TRAFFIC_RATE, COVER_TRAFFIC_RATE_LEN),
+                    (DBKeys::DURESS_DATABASE_KEY, crypto::MASTER_KEY_LEN),
+                ] {
+                    if storage.get_value(key)?.is_none() {
+                        let mut value = vec![0; crypto::IV_LEN+len+crypto::AES_TAG_LEN];
+                        OsRng.fill_bytes(&mut value);
+                        storage.set_value(key, &value)?;
+                    }
+                }
+                Ok(())
+            }
+            None => Identity::fill_duress_slot(storage)
+        })
+    }
+
+    fn decrypt_duress_master_key(storage: &S, password: &[u8]) -> Result<[u8; crypto::MASTER_KEY_LEN], CryptoError> {
+        match (storage.get_value(DBKeys::DURESS_MASTER_KEY), storage.get_value(DBKeys::DURESS_SALT)) {
+            (Ok(Some(encrypted_master_key)), Ok(Some(salt))) => crypto::decrypt_master_key(&encrypted_master_key, password, &salt),
+            _ => Err(CryptoError::DecryptionFailed)
+        }
+    }
+
+    fn load_duress_identity(storage: S, duress_master_key: Result<[u8; crypto::MASTER_KEY_LEN], CryptoError>) -> Result<Identity<S>, String> {
+        match duress_master_key {
+            Ok(master_key) if master_key == DURESS_DESTROY_KEY => {
+                if let Err(e) = storage.destroy() {
+                    print_error!(e);
+                }
+                Err(String::from(BAD_PASSWORD_ERROR))
+            }
+            Ok(master_key) => match Identity::load_encrypted_identity(&storage, true) {
+                Ok(encrypted_identity) => Identity::decrypt_identity(storage, encrypted_identity, master_key, true),
+                Err(e) => Err(e.to_string())
+            }
+            Err(_) => Err(String::from(BAD_PASSWORD_ERROR))
+        }
+    }
+
+    //the decoy must behave like the real identity without touching the duress slot: it only pretends to change it
+    pub fn set_duress_password(&self, duress_password: &[u8], action: DuressAction) -> Result<(), String> {
+        if self.decoy {
+            if Identity::decrypt_duress_master_key(&self.storage, duress_password).is_ok() {
+                return Err(String::from(DURESS_PASSWORD_ERROR));
+            }
+            crypto::encrypt_master_key(crypto::generate_master_key(), duress_password); //same work as a real change
+            return Ok(());
+        }
+        let encrypted_master_key = get_required(&self.storage, DBKeys::MASTER_KEY).map_err(|e| e.to_string())?;
+        if encrypted_master_key.len() == crypto::MASTER_KEY_LEN {
+            return Err(String::from("The identity must be protected by a password"));
+        }
+        let salt = get_required(&self.storage, DBKeys::SALT).map_err(|e| e.to_string())?;
+        if crypto::decrypt_master_key(&encrypted_master_key, duress_password, &salt).is_ok() {
+            return Err(String::from(DURESS_PASSWORD_ERROR));
+        }
+        let (master_key, decoy_values) = match action {
+            DuressAction::Destroy => (DURESS_DESTROY_KEY, Vec::new()),
+            DuressAction::Decoy(name) => {
+                let master_key = crypto::generate_master_key();
+                let keypair = Keypair::generate(&mut rand_7::rngs::OsRng);
+                (master_key, vec![
+                    (DBKeys::DURESS_NAME, crypto::encrypt_data(name.as_bytes(), &master_key).unwrap()),
+                    (DBKeys::DURESS_KEYPAIR, crypto::encrypt_data(&keypair.to_bytes(), &master_key).unwrap()),
+                    (DBKeys::DURESS_USE_PADDING, crypto::encrypt_data(&[bool_to_byte(true)], &master_key).unwrap()),
+                    (DBKeys::DURESS_PADDING_POLICY, crypto::encrypt_data(&PaddingPolicy::default().to_bytes(), &master_key).unwrap()),
+                    (DBKeys::DURESS_COVER_TRAFFIC_RATE, crypto::encrypt_data(&0u32.to_be_bytes(), &master_key).unwrap()),
+                    (DBKeys::DURESS_DATABASE_KEY, crypto::encrypt_data(&crypto::derive_database_key(&self.master_key), &master_key).unwrap()),
+                ])
+            }
+        };
+        let (salt, encrypted_master_key) = crypto::encrypt_master_key(master_key, duress_password);
+        self.storage.transaction(|| {
+            Identity::fill_duress_slot(&self.storage)?;
+            for (key, value) in &decoy_values {
+                self.storage.set_value(key, value)?;
+            }
+            self.storage.set_value(DBKeys::DURESS_SALT, &salt)?;
+            self.storage.set_value(DBKeys::DURESS_MASTER_KEY, &encrypted_master_key)?;
+            Ok(())
+        }).map_err(|e| e.to_string())
+    }
+
+    pub fn remove_duress_password(&self) -> Result<(), Error> {
+        if self.decoy {
+            return Ok(());
+        }
+        Identity::fill_duress_slot(&self.storage)
+    }
+
+    //older databases stored the name in plaintext: encrypt it the first time the identity is unlocked.
+    //once flagged, a name that can't be decrypted is damaged and must not be mistaken for a plaintext one.
+    fn decrypt_name(storage: &S, encrypted_name: &[u8], master_key: &[u8]) -> Result<String, String> {
+        let name_encrypted = storage.get_value(DBKeys::NAME_ENCRYPTED).map_err(|e| e.to_string())?.is_some();
+        match crypto::decrypt_data(encrypted_name, master_key) {
+            Ok(name) => {
+                let name = String::from_utf8(name).map_err(|e| e.to_string())?;
+                if !name_encrypted {
+                    storage.set_value(DBKeys::NAME_ENCRYPTED, &[bool_to_byte(true)]).map_err(|e| e.to_string())?;
+                }
+                Ok(name)
+            }
+            Err(e) if name_encrypted => Err(e.to_string()),
+            Err(_) => {
+                let name = std::str::from_utf8(encrypted_name).map_err(|e| e.to_string())?.to_owned();
+                let encrypted_name = crypto::encrypt_data(name.as_bytes(), master_key).unwrap();
+                storage.transaction(|| {
+                    storage.set_value(DBKeys::NAME, &encrypted_name)?;
+                    storage.set_value(DBKeys::NAME_ENCRYPTED, &[bool_to_byte(true)])
+                }).map_err(|e| e.to_string())?;
+                Ok(name)
+            }
+        }
+    }
+
+    pub fn create(mut storage: S, name: &str, login_label: Option<&str>, encrypt_database: bool, password: Option<&[u8]>) -> Result<Identity<S>, Error> {
+        let keypair = Keypair::generate(&mut rand_7::rngs::OsRng);
+        let master_key = crypto::generate_master_key();
+        let encrypted_keypair = crypto::encrypt_data(&keypair.to_bytes(), &master_key).unwrap();
+        let encrypted_name = crypto::encrypt_data(name.as_bytes(), &master_key).unwrap();
+        let (salt, stored_master_key) = match password {
+            Some(password) => {
+                let (salt, encrypted_master_key) = crypto::encrypt_master_key(master_key, password);
+                (salt, encrypted_master_key.to_vec())
+            }
+            None => ([0; crypto::SALT_LEN], master_key.to_vec()) //storing master_key in plaintext
+        };
+        let encrypted_use_padding = crypto::encrypt_data(&[bool_to_byte(true)], &master_key).unwrap();
+        let encrypted_padding_policy = crypto::encrypt_data(&PaddingPolicy::default().to_bytes(), &master_key).unwrap();
+        let encrypted_cover_traffic_rate = crypto::encrypt_data(&0u32.to_be_bytes(), &master_key).unwrap();
+        let encrypted_encrypt_database = crypto::encrypt_data(&[bool_to_byte(encrypt_database)], &master_key).unwrap();
+        //a crash must not leave a half-written identity
+        storage.transaction(|| {
+            storage.set_value(DBKeys::NAME, &encrypted_name)?;
+            storage.set_value(DBKeys::NAME_ENCRYPTED, &[bool_to_byte(true)])?;
+            if let Some(login_label) = login_label {
+                storage.set_value(DBKeys::LOGIN_LABEL, login_label.as_bytes())?;
+            }
+            storage.set_value(DBKeys::KEYPAIR, &encrypted_keypair)?;
+            storage.set_value(DBKeys::MASTER_KEY, &stored_master_key)?;
+            storage.set_value(DBKeys::SALT, &salt)?;
+            storage.set_value(DBKeys::USE_PADDING, &encrypted_use_padding)?;
+            storage.set_value(DBKeys::PADDING_POLICY, &encrypted_padding_policy)?;
+            storage.set_value(DBKeys::COVER_TRAFFIC_RATE, &encrypted_cover_traffic_rate)?;
+            storage.set_value(DBKeys::ENCRYPT_DATABASE, &encrypted_encrypt_database)?;
+            Identity::fill_duress_slot(&storage)
+        })?;
+        let mut database_key = crypto::derive_database_key(&master_key);
+        storage.unlock(Some(&database_key[..]).filter(|_| encrypt_database));
+        database_key.zeroize();
+        Ok(Identity {
+            name: name.to_owned(),
+            keypair,
+            master_key,
+            padding_policy: PaddingPolicy::default(),
+            cover_traffic_rate: 0,
+            encrypt_database,
+            storage,
+            decoy: false,
+            conversations: RefCell::new(None),
+        })
+    }
+
+    fn update_master_key(storage: &S, master_key: [u8; crypto::MASTER_KEY_LEN], new_password: Option<&[u8]>) -> Result<usize, Error> {
+        let (salt, stored_master_key) = match new_password {
+            Some(new_password) => {
+                let (salt, encrypted_master_key) = crypto::encrypt_master_key(master_key, new_password);
+                (salt, encrypted_master_key.to_vec())
+            }
+            None => ([0; crypto::SALT_LEN], master_key.to_vec())
+        };
+        storage.transaction(|| {
+            storage.set_value(DBKeys::MASTER_KEY, &stored_master_key)?;
+            storage.set_value(DBKeys::SALT, &salt)
+        })
+    }
+
+    pub fn change_storage_password(storage: &S, old_password: Option<&[u8]>, new_password: Option<&[u8]>) -> Result<bool, String> {
+        match Identity::load_encrypted_identity(storage, false) {
+            Ok(encrypted_identity) => {
+                let master_key: [u8; crypto::MASTER_KEY_LEN] = match old_password {
+                    Some(old_password) => match crypto::decrypt_master_key(&encrypted_identity.encrypted_master_key, old_password, &encrypted_identity.salt) {
+                        Ok(master_key) => master_key,
+                        Err(e) => return match e {
+                            CryptoError::DecryptionFailed => Ok(false),
+                            CryptoError::InvalidLength => Err(String::from(DATABASE_CORRUPED_ERROR))
+                        }
+                    }
+                    None => if encrypted_identity.encrypted_master_key.len() == crypto::MASTER_KEY_LEN {
+                        encrypted_identity.encrypted_master_key.try_into().unwrap()
+                    } else {
+                        return Err(String::from(DATABASE_CORRUPED_ERROR))
+                    }
+                };
+                match Identity::update_master_key(storage, master_key, new_password) {
+                    Ok(_) => Ok(true),
+                    Err(e) => Err(e.to_string())
+                }
+            }
+            Err(e) => Err(e.to_string())
+        }
+    }
+}
+
+
+//identities stored in a database folder
+impl Identity<SqliteStorage> {
+
+    pub fn set_encrypt_database(&mut self, encrypt_database: bool) -> Result<usize, String> {
+        if self.decoy { //the flag belongs to the real identity: only pretend to convert the database
+            let changed = encrypt_database != self.encrypt_database;
+            self.encrypt_database = encrypt_database;
+            return Ok(changed as usize);
+        }
+        self.convert_database(encrypt_database).map_err(|e| e.to_string())
+    }
+
+    fn convert_database(&mut self, encrypt_database: bool) -> Result<usize, Error> {
+        if encrypt_database == self.encrypt_database {
+            return Ok(0);
+        }
+        let encrypted_database_path = storage::get_encrypted_database_path(&self.storage.database_folder);
+        let mut key = storage::get_sqlcipher_key(&self.master_key);
+        if encrypt_database {
+            if Path::new(&encrypted_database_path).exists() { //leftover of an interrupted conversion
+                if let Err(e) = std::fs::remove_file(&encrypted_database_path) {
+                    print_error!(e);
+                }
+            }
+            self.storage.with_database(|db| {
+                let result = db.execute("ATTACH DATABASE ?1 AS encrypted KEY ?2", [&encrypted_database_path, &key]);
+                key.zeroize();
+                result?;
+                db.query_row("SELECT sqlcipher_export('encrypted')", [], |_| Ok(()))?;
+                db.execute(&format!("DROP TABLE encrypted.{}", MAIN_TABLE), [])?;
+                db.execute("DETACH DATABASE encrypted", [])
+            })?;
+        } else {
+            key.zeroize();
+            self.storage.with_database(|db| db.query_row("SELECT sqlcipher_export('plaintext')", [], |_| Ok(())))?; //already attached
+        }
+        let encrypted_encrypt_database = crypto::encrypt_data(&[bool_to_byte(encrypt_database)], &self.master_key).unwrap();
+        let result = self.storage.set_value(DBKeys::ENCRYPT_DATABASE, &encrypted_encrypt_database)?;
+        self.encrypt_database = encrypt_database;
+        let mut database_key = crypto::derive_database_key(&self.master_key);
+        self.storage.unlock(Some(&database_key[..]).filter(|_| encrypt_database));
+        database_key.zeroize();
+        //the data is now only readable from the new location: remove the old copy. The WAL was checkpointed when leaving WAL mode.
+        if encrypt_database {
+            self.storage.with_database(|db| {
+                for table in DATA_TABLES {
+                    db.execute(&format!("DROP TABLE IF EXISTS plaintext.{}", table.name), [])?;
+                }
+                db.execute("VACUUM plaintext", [])
+            })?;
+        } else if let Err(e) = std::fs::remove_file(&encrypted_database_path) {
+            print_error!(e);
+        }
+        Ok(result)
+    }
+
+    //rowid followed by the selected columns
+    fn query_rows(db: &Connection, sql: &str) -> Result<Vec<(i64, Row)>, Error> {
+        let mut stmt = db.prepare(sql)?;
+        let column_count = stmt.column_count();
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, (1..column_count).map(|i| row.get(i)).collect::<Result<Row, _>>()?)))?.collect();
+        rows
+    }
+
+    fn add_record_column(db: &Connection, table: &Table) -> Result<(), Error> {
+        if !storage::column_exists(db, table.name, "record")? {
+            db.execute(&format!("ALTER TABLE {} ADD COLUMN record BLOB", table.name), [])?;
+        }
+        Ok(())
+    }
+
+    //conversations used to be stored in one table per contact named by its plaintext UUID and file owners weren't encrypted.
+    //contacts and messages also used to have each of their values encrypted separately instead of a single record.
+    fn migrate_conversations(&self) -> Result<(), Error> {
+        self.storage.transaction(|| self.storage.with_database(|db| {
+            let mut merged = false;
+            if storage::column_exists(db, MESSAGES_TABLE.name, "outgoing")? {
+                Identity::add_record_column(db, &MESSAGES_TABLE)?;
+                let legacy_rows = Identity::query_rows(db, &format!("SELECT rowid, outgoing, timestamp, data FROM {} WHERE record IS NULL", MESSAGES_TABLE.name))?;
+                for (rowid, encrypted_record) in merge_legacy_rows(&self.master_key, legacy_rows)? {
+                    db.execute(&format!("UPDATE {} SET record=?1, outgoing=NULL, timestamp=NULL, data=NULL WHERE rowid=?2", MESSAGES_TABLE.name), params![encrypted_record, rowid])?;
+                    merged = true;
+                }
+            }
+            let legacy_tables: Vec<String> = {
+                let mut stmt = db.prepare("SELECT name FROM sqlite_master WHERE type='table'")?;
+                let table_names = stmt.query_map([], |row| row.get(0))?.collect::<Result<Vec<String>, _>>()?;
+                table_names.into_iter().filter(|table_name| Uuid::from_str(table_name).is_ok()).collect()
+            };
+            if !legacy_tables.is_empty() {
+                storage::create_table(db, &MESSAGES_TABLE)?;
+            }
+            for table_name in legacy_tables {
+                let contact_uuid = Uuid::from_str(&table_name).unwrap();
+                let legacy_rows = Identity::query_rows(db, &format!("SELECT rowid, outgoing, timestamp, data FROM \"{}\" ORDER BY rowid", table_name))?;
+                for (_, encrypted_record) in merge_legacy_rows(&self.master_key, legacy_rows)? {
+                    let encrypted_contact = crypto::encrypt_data(contact_uuid.as_bytes(), &self.master_key).unwrap();
+                    db.execute(&format!("INSERT INTO {} (contact, record) VALUES (?1, ?2)", MESSAGES_TABLE.name), params![encrypted_contact, encrypted_record])?;
+                }
+                db.execute(&format!("DROP TABLE \"{}\"", table_name), [])?;
+            }
+            if storage::table_exists(db, FILES_TABLE.name)? {
+                let legacy_files: Vec<(i64, Vec<u8>)> = {
+                    let mut stmt = db.prepare(&format!("SELECT rowid, contact_uuid FROM {} WHERE length(contact_uuid)=16", FILES_TABLE.name))?;
+                    let legacy_files = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?.collect::<Result<_, _>>()?;
+                    legacy_files
+                };
+                for (rowid, contact_uuid) in legacy_files {
+                    db.execute(&format!("UPDATE {} SET contact_uuid=?1 WHERE rowid=?2", FILES_TABLE.name), params![crypto::encrypt_data(&contact_uuid, &self.master_key).unwrap(), rowid])?;
+                }
+            }
+            if storage::column_exists(db, CONTACTS_TABLE.name, "key")? {
+                if !storage::column_exists(db, CONTACTS_TABLE.name, "settings")? {
+                    db.execute(&format!("ALTER TABLE {} ADD COLUMN settings BLOB", CONTACTS_TABLE.name), [])?;
+                }
+                Identity::add_record_column(db, &CONTACTS_TABLE)?;
+                let mut legacy_rows = Identity::query_rows(db, &format!("SELECT rowid, key, verified, seen, settings, name FROM {} WHERE record IS NULL", CONTACTS_TABLE.name))?;
+                //rows created before the settings column existed use the default settings
+                for (_, row) in &mut legacy_rows {
+                    if row[3].is_none() {
+                        row[3] = Some(crypto::encrypt_data(&ContactSettings::default().to_bytes(), &self.master_key).unwrap());
+                    }
+                }
+                for (rowid, encrypted_record) in merge_legacy_rows(&self.master_key, legacy_rows)? {
+                    db.execute(&format!("UPDATE {} SET record=?1, key=NULL, verified=NULL, seen=NULL, settings=NULL, name=NULL WHERE rowid=?2", CONTACTS_TABLE.name), params![encrypted_record, rowid])?;
+                    merged = true;
+                }
+            }
+            if storage::table_exists(db, CONTACTS_TABLE.name)? {
+                self.pad_contacts()?;
+            }
+            if merged {
+                self.storage.scrub()?;
+            }
+            Ok(())
+        }))
+    }
+
+    //files and blocked keys used to be found by decrypting the whole table. Rows of the other identity sharing
+    //the database can't be decrypted here: they are left without lookup key until it is opened.
+    fn add_lookup_keys(&self) -> Result<(), Error> {
+        self.storage.transaction(|| self.storage.with_database(|db| {
+            for (table, column) in [(&FILES_TABLE, "uuid"), (&BLOCKED_TABLE, "key")] {
+                if !storage::table_exists(db, table.name)? {
+                    continue;
+                }
+                if !storage::column_exists(db, table.name, "lookup")? {
+                    db.execute(&format!("ALTER TABLE {} ADD COLUMN lookup BLOB", table.name), [])?;
+                    storage::create_table(db, table)?; //adds the index
+                }
+                let legacy_rows = Identity::query_rows(db, &format!("SELECT rowid, {} FROM {} WHERE lookup IS NULL", column, table.name))?;
+                for (rowid, _, value) in decrypt_rows(&self.master_key, legacy_rows, 0) {
+                    db.execute(&format!("UPDATE {} SET lookup=?1 WHERE rowid=?2", table.name), params![crypto::compute_lookup_key(&self.master_key, &value), rowid])?;
+                }
+            }
+            Ok(())
+        }))
+    }
+
+    pub fn load_identity(database_folder: String, password: Option<&[u8]>) -> Result<Identity, String> {
+        let identity = Identity::load(SqliteStorage::new(database_folder), password)?;
+        //the decoy can't decrypt the rows of the real identity and would see them as corrupted
+        if !identity.decoy {
+            if let Err(e) = identity.migrate_conversations() {
+                print_error!(e);
+            }
+        }
+        if let Err(e) = identity.add_lookup_keys() {
+            print_error!(e);
+        }
+        //after the migrations, which need the legacy layout of the tables
+        if let Err(e) = identity.storage.create_tables(&DATA_TABLES) {
+            print_error!(e);
+        }
+        Ok(identity)
+    }
+
+    pub fn create_identidy(database_folder: String, name: &str, login_label: Option<&str>, encrypt_database: bool, password: Option<&[u8]>) -> Result<Identity, Error> {
+        let identity = Identity::create(SqliteStorage::new(database_folder), name, login_label, encrypt_database, password)?;
+        identity.storage.create_tables(&DATA_TABLES)?;
+        Ok(identity)
+    }
+
+    pub fn exists(database_folder: &str) -> Result<bool, Error> {
+        Ok(SqliteStorage::new(database_folder.to_owned()).get_value(DBKeys::KEYPAIR)?.is_some())
+    }
+
+    pub fn get_login_label(database_folder: &str) -> Result<Option<String>, Error> {
+        match SqliteStorage::new(database_folder.to_owned()).get_value(DBKeys::LOGIN_LABEL)? {
+            Some(label) => Ok(Some(String::from_utf8(label).map_err(|e| Error::Utf8Error(e.utf8_error()))?)),
+            None => Ok(None)
+        }
+    }
+
+    pub fn set_login_label(database_folder: &str, login_label: Option<&str>) -> Result<usize, Error> {
+        let storage = SqliteStorage::new(database_folder.to_owned());
+        match login_label {
+            Some(login_label) => storage.set_value(DBKeys::LOGIN_LABEL, login_label.as_bytes()), //the only plaintext string of the database
+            None => storage.remove_value(DBKeys::LOGIN_LABEL)
+        }
+    }
+
+    pub fn is_protected(database_folder: String) -> Result<bool, Error> {
+        Ok(get_required(&SqliteStorage::new(database_folder), DBKeys::MASTER_KEY)?.len() != crypto::MASTER_KEY_LEN)
+    }
+
+    pub fn change_password(database_folder: String, old_password: Option<&[u8]>, new_password: Option<&[u8]>) -> Result<bool, String> {
+        Identity::change_storage_password(&SqliteStorage::new(database_folder), old_password, new_password)
+    }
+
+    pub fn destroy(database_folder: &str) -> Result<(), std::io::Error> {
+        SqliteStorage::new(database_folder.to_owned()).destroy()
+    }
+
+    pub fn set_identity_avatar(database_folder: &str, avatar: &[u8]) -> Result<usize, Error> {
+        SqliteStorage::new(database_folder.to_owned()).set_value(DBKeys::AVATAR, avatar)
+    }
+
+    pub fn remove_identity_avatar(database_folder: &str) -> Result<usize, Error> {
+        SqliteStorage::new(database_folder.to_owned()).remove_value(DBKeys::AVATAR)
+    }
+
+    pub fn get_identity_avatar(database_folder: &str) -> Result<Vec<u8>, Error> {
+        get_required(&SqliteStorage::new(database_folder.to_owned()), DBKeys::AVATAR)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{sync::{Mutex, Once}, thread::{self, ThreadId}};
+    use crate::{discovery, storage::MemoryStorage};
+
+    fn create_test_identity() -> Identity {
+        let database_folder = std::env::temp_dir().join(format!("aira-test-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&database_folder).unwrap();
+        Identity::create_identidy(database_folder.to_str().unwrap().to_owned(), "Alice", None, false, None).unwrap()
+    }
+
+    //the tables are created with the identity, legacy layouts replace them
+    fn drop_data_tables(db: &Connection) -> Result<(), Error> {
+        for table in DATA_TABLES {
+            db.execute(&format!("DROP TABLE {}", table.name), [])?;
+        }
+        Ok(())
+    }
+
+    //errors logged by each test thread, to check what print_error! reveals
+    struct ErrorLog(Mutex<Vec<(ThreadId, String)>>);
+
+    impl log::Log for ErrorLog {
+        fn enabled(&self, metadata: &log::Metadata) -> bool {
+            metadata.level() <= log::Level::Error
+        }
+        fn log(&self, record: &log::Record) {
+            self.0.lock().unwrap().push((thread::current().id(), record.args().to_string()));
+        }
+        fn flush(&self) {}
+    }
+
+    static ERROR_LOG: ErrorLog = ErrorLog(Mutex::new(Vec::new()));
+
+    fn logged_errors() -> Vec<String> {
+        static INIT: Once = Once::new();
+        INIT.call_once(|| {
+            log::set_logger(&ERROR_LOG).unwrap();
+            log::set_max_level(log::LevelFilter::Error);
+        });
+        let current = thread::current().id();
+        ERROR_LOG.0.lock().unwrap().iter().filter(|(thread, _)| *thread == current).map(|(_, error)| error.clone()).collect()
+    }
+
+    fn read_database_files(database_folder: &str) -> Vec<u8> {
+        let mut content = Vec::new();
+        for entry in std::fs::read_dir(database_folder).unwrap() {
+            content.extend(std::fs::read(entry.unwrap().path()).unwrap());
+        }
+        content
+    }
+
+    fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+        haystack.windows(needle.len()).any(|window| window == needle)
+    }
+
+    fn get_raw_messages(identity: &Identity) -> Vec<Vec<u8>> {
+        identity.storage.with_database(|db| {
+            let mut stmt = db.prepare(&format!("SELECT record FROM {}", MESSAGES_TABLE.name))?;
+            let raw_messages = stmt.query_map([], |row| row.get(0))?.collect();
+            raw_messages
+        }).unwrap()
+    }
+
+    #[test]
+    fn delete_conversation_wipes_ciphertext() {
+        let identity = create_test_identity();
+        let contact = identity.add_contact("Bob".to_owned(), None, [1; PUBLIC_KEY_LENGTH]).unwrap();
+        for i in 0..10 {
+            identity.store_msg(&contact.uuid, Message { outgoing: true, timestamp: i, data: vec![i as u8; 200] }).unwrap();
+        }
+        let raw_messages = get_raw_messages(&identity);
+        assert_eq!(raw_messages.len(), 10);
+        assert!(contains(&read_database_files(&identity.storage.database_folder), &raw_messages[0]));
+
+        assert_eq!(identity.delete_conversation(&contact.uuid).unwrap(), 10);
+        let content = read_database_files(&identity.storage.database_folder);
+        for raw_message in raw_messages {
+            assert!(!contains(&content, &raw_message));
+        }
+    }
+
+    #[test]
+    fn remove_contact_wipes_ciphertext() {
+        let identity = create_test_identity();
+        let contact = identity.add_contact("Bob".to_owned(), None, [1; PUBLIC_KEY_LENGTH]).unwrap();
+        identity.store_msg(&contact.uuid, Message { outgoing: false, timestamp: 0, data: vec![42; 500] }).unwrap();
+        identity.store_file(Some(contact.uuid), &[24; 5000]).unwrap();
+        let raw_messages = get_raw_messages(&identity);
+        let raw_file: Vec<u8> = identity.storage.with_database(|db| db.query_row(&format!("SELECT data FROM {}", FILES_TABLE.name), [], |row| row.get(0))).unwrap();
+
+        identity.remove_contact(&contact.uuid).unwrap();
+        let content = read_database_files(&identity.storage.database_folder);
+        assert!(!contains(&content, &raw_messages[0]));
+        assert!(!contains(&content, &raw_file));
+        assert!(identity.load_contacts().unwrap().is_empty());
+    }
+
+    #[test]
+    fn change_contact_key_keeps_history() {
+        let identity = create_test_identity();
+        let contact = identity.add_contact("Bob".to_owned(), None, [1; PUBLIC_KEY_LENGTH]).unwrap();
+        identity.set_verified(&contact.uuid).unwrap();
+        assert_eq!(identity.change_contact_key(&contact.uuid, [1; PUBLIC_KEY_LENGTH], 10).unwrap(), None);
+        assert_eq!(identity.change_contact_key(&contact.uuid, [2; PUBLIC_KEY_LENGTH], 20).unwrap(), Some([1; PUBLIC_KEY_LENGTH]));
+        identity.change_contact_key(&contact.uuid, [3; PUBLIC_KEY_LENGTH], 30).unwrap();
+
+        let contact = identity.load_contacts().unwrap().pop().unwrap();
+        assert_eq!(contact.public_key, [3; PUBLIC_KEY_LENGTH]);
+        assert!(!contact.verified);
+        let history = identity.load_key_history(&contact.uuid).unwrap();
+        assert_eq!(history.iter().map(|previous_key| (previous_key.public_key, previous_key.timestamp)).collect::<Vec<_>>(), vec![([1; PUBLIC_KEY_LENGTH], 20), ([2; PUBLIC_KEY_LENGTH], 30)]);
+
+        identity.remove_contact(&contact.uuid).unwrap();
+        assert!(identity.load_key_history(&contact.uuid).unwrap().is_empty());
+    }
+
+    #[test]
+    fn rotated_keypair_is_followed_by_contacts() {
+        let mut alice = create_test_identity();
+        let bob = create_test_identity();
+        let contact = bob.add_contact("Alice".to_owned(), None, alice.get_public_key()).unwrap();
+        alice.rotate_keypair().unwrap();
+        alice.rotate_keypair().unwrap();
+        let key_rotations = alice.get_key_rotations().unwrap();
+        assert_eq!(key_rotations.len(), 2*KEY_ROTATION_LEN);
+        assert_eq!(bob.find_rotated_contact(&key_rotations, &alice.get_public_key()), Some(contact.uuid));
+        assert_eq!(bob.find_rotated_contact(&key_rotations[KEY_ROTATION_LEN..], &alice.get_public_key()), None);
+
+        let mut forged_key_rotations = key_rotations.clone();
+        forged_key_rotations[2*KEY_ROTATION_LEN-1] ^= 1;
+        assert_eq!(bob.find_rotated_contact(&forged_key_rotations, &alice.get_public_key()), None);
+
+        let alice = Identity::load_identity(alice.storage.database_folder.clone(), None).unwrap();
+        assert_eq!(alice.get_key_rotations().unwrap(), key_rotations);
+        assert_eq!(&key_rotations[KEY_ROTATION_LEN+PUBLIC_KEY_LENGTH..KEY_ROTATION_LEN+2*PUBLIC_KEY_LENGTH], alice.get_public_key());
+    }
+
+    #[test]
+    fn group_messages_are_stored_once() {
+        let identity = create_test_identity();
+        let bob = identity.add_contact("Bob".to_owned(), None, [1; PUBLIC_KEY_LENGTH]).unwrap();
+        let carol = identity.add_contact("Carol".to_owned(), None, [2; PUBLIC_KEY_LENGTH]).unwrap();
+        let group = identity.add_group(None, "Friends".to_owned(), vec![bob.uuid, carol.uuid]).unwrap();
+        identity.store_group_msg(&group.uuid, None, Message { outgoing: true, timestamp: 1, data: vec![0, 1] }).unwrap();
+        identity.store_group_msg(&group.uuid, Some(&bob.uuid), Message { outgoing: false, timestamp: 2, data: vec![0, 2] }).unwrap();
+        assert_eq!(get_raw_messages(&identity).len(), 2);
+        assert!(identity.load_msgs(&bob.uuid, 0, 10).unwrap_or_default().is_empty());
+
+        let msgs = identity.load_group_msgs(&group.uuid, 0, 10).unwrap();
+        assert_eq!(msgs.iter().map(|(sender, msg)| (*sender, msg.data.clone())).collect::<Vec<_>>(), vec![(None, vec![0, 1]), (Some(bob.uuid), vec![0, 2])]);
+
+        identity.remove_contact(&bob.uuid).unwrap();
+        let groups = identity.load_groups().unwrap();
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].name, "Friends");
+        assert_eq!(groups[0].members, vec![carol.uuid]);
+
+        identity.remove_group(&group.uuid).unwrap();
+        assert!(identity.load_groups().unwrap().is_empty());
+        assert!(get_raw_messages(&identity).is_empty());
+    }
+
+    #[test]
+    fn block_list() {
+        let identity = create_test_identity();
+        assert!(!identity.is_blocked(&[1; PUBLIC_KEY_LENGTH]).unwrap());
+        assert_eq!(identity.block(&[1; PUBLIC_KEY_LENGTH]).unwrap(), 1);
+        assert_eq!(identity.block(&[1; PUBLIC_KEY_LENGTH]).unwrap(), 0);
+        identity.block(&[2; PUBLIC_KEY_LENGTH]).unwrap();
+        assert!(identity.is_blocked(&[1; PUBLIC_KEY_LENGTH]).unwrap());
+        assert!(!contains(&read_database_files(&identity.storage.database_folder), &[1; PUBLIC_KEY_LENGTH]));
+        assert_eq!(identity.unblock(&[1; PUBLIC_KEY_LENGTH]).unwrap(), 1);
+        assert!(!identity.is_blocked(&[1; PUBLIC_KEY_LENGTH]).unwrap());
+        assert_eq!(identity.load_blocked().unwrap(), vec![vec![2; PUBLIC_KEY_LENGTH]]);
+    }
+
+    #[test]
+    fn contact_settings() {
+        let identity = create_test_identity();
+        let contact = identity.add_contact("Bob".to_owned(), None, [1; PUBLIC_KEY_LENGTH]).unwrap();
+        assert_eq!(identity.get_contact_settings(&contact.uuid).unwrap(), ContactSettings::default());
+        let settings = ContactSettings {
+            padding: PaddingPreference::Never,
+            notifications: NotificationMode::Muted,
+            auto_accept_size: 1_000_000,
+            retention: 100,
+        };
+        identity.set_contact_settings(&contact.uuid, &settings).unwrap();
+        assert_eq!(identity.get_contact_settings(&contact.uuid).unwrap(), settings);
+
+        identity.store_msg(&contact.uuid, Message { outgoing: true, timestamp: 50, data: vec![0] }).unwrap();
+        identity.store_msg(&contact.uuid, Message { outgoing: true, timestamp: 150, data: vec![0] }).unwrap();
+        assert_eq!(identity.delete_expired_msgs(&contact.uuid, 200).unwrap(), 1);
+        assert_eq!(identity.load_msgs(&contact.uuid, 0, 10).unwrap()[0].timestamp, 150);
+    }
+
+    #[test]
+    fn padding_policy_is_persisted() {
+        let mut identity = create_test_identity();
+        assert_eq!(identity.padding_policy, PaddingPolicy::PowerOfTwo);
+        let padding_policy = PaddingPolicy::Random { min: 10, max: 20 };
+        identity.set_padding_policy(padding_policy).unwrap();
+        let identity = Identity::load_identity(identity.storage.database_folder.clone(), None).unwrap();
+        assert_eq!(identity.padding_policy, padding_policy);
+
+        //older databases only have the use_padding flag
+        identity.storage.remove_value(DBKeys::PADDING_POLICY).unwrap();
+        identity.storage.set_value(DBKeys::USE_PADDING, &crypto::encrypt_data(&[bool_to_byte(false)], &identity.master_key).unwrap()).unwrap();
+        assert_eq!(Identity::load_identity(identity.storage.database_folder.clone(), None).unwrap().padding_policy, PaddingPolicy::None);
+    }
+
+    #[test]
+    fn discovery_tokens_are_recognized_by_contacts_only() {
+        let alice = create_test_identity();
+        let bob = create_test_identity();
+        let eve = create_test_identity();
+        let alice_as_contact = bob.add_contact("Alice".to_owned(), None, alice.get_public_key()).unwrap();
+        let bob_as_contact = alice.add_contact("Bob".to_owned(), None, bob.get_public_key()).unwrap();
+        eve.add_contact("Bob".to_owned(), None, bob.get_public_key()).unwrap();
+
+        let announcement = alice.get_discovery_announcement(7530, 1000);
+        assert_eq!(announcement.tokens.len(), discovery::TOKEN_BUCKET);
+        assert_eq!(bob.find_contact_by_discovery_tokens(&announcement.tokens, 1000), Some(alice_as_contact.uuid));
+        assert_eq!(bob.find_contact_by_discovery_tokens(&announcement.tokens, 1001), Some(alice_as_contact.uuid));
+        assert_eq!(bob.find_contact_by_discovery_tokens(&announcement.tokens, 1010), None);
+        assert_eq!(eve.find_contact_by_discovery_tokens(&announcement.tokens, 1000), None);
+        assert_ne!(alice.get_discovery_announcement(7530, 1001).tokens, announcement.tokens);
+        assert_ne!(eve.get_discovery_announcement(7530, 1000).tokens, announcement.tokens);
+
+        //both sides of a pair announce different tokens, and ours are not recognized as coming from the contact
+        let bob_announcement = bob.get_discovery_announcement(7530, 1000);
+        assert!(bob_announcement.tokens.iter().all(|token| !announcement.tokens.contains(token)));
+        assert_eq!(alice.find_contact_by_discovery_tokens(&bob_announcement.tokens, 1000), Some(bob_as_contact.uuid));
+        assert_eq!(bob.find_contact_by_discovery_tokens(&bob_announcement.tokens, 1000), None);
+        assert_eq!(alice.find_contact_by_discovery_tokens(&announcement.tokens, 1000), None);
+    }
+
+    fn check_store_msgs<S: Storage>(identity: &Identity<S>) {
+        let contact = identity.add_contact("Bob".to_owned(), None, [1; PUBLIC_KEY_LENGTH]).unwrap();
+        identity.store_msg(&contact.uuid, Message { outgoing: true, timestamp: 0, data: vec![0] }).unwrap();
+        let messages: Vec<Message> = (1..100).map(|i| Message { outgoing: i%3 == 0, timestamp: i, data: vec![i as u8; i as usize] }).collect();
+        let ids = identity.store_msgs(&contact.uuid, &messages).unwrap();
+        assert_eq!(ids.len(), messages.len());
+        assert!(ids.windows(2).all(|ids| ids[0] < ids[1]));
+        assert!(identity.store_msgs(&contact.uuid, &[]).unwrap().is_empty());
+
+        let loaded = identity.load_msgs(&contact.uuid, 0, messages.len()).unwrap();
+        assert_eq!(loaded.iter().map(|msg| (msg.outgoing, msg.timestamp, msg.data.clone())).collect::<Vec<_>>(), messages.iter().map(|msg| (msg.outgoing, msg.timestamp, msg.data.clone())).collect::<Vec<_>>());
+        assert_eq!(identity.load_msgs(&contact.uuid, messages.len(), 10).unwrap()[0].timestamp, 0);
+    }
+
+    #[test]
+    fn store_msgs() {
+        check_store_msgs(&create_test_identity());
+        check_store_msgs(&create_memory_identity());
+    }
+
+    #[test]
+    fn database_encryption_is_toggled() {
+        let mut identity = create_test_identity();
+        let contact = identity.add_contact("Bob".to_owned(), None, [1; PUBLIC_KEY_LENGTH]).unwrap();
+        identity.store_msg(&contact.uuid, Message { outgoing: true, timestamp: 0, data: vec![42] }).unwrap();
+        identity.set_encrypt_database(true).unwrap();
+        identity.store_msg(&contact.uuid, Message { outgoing: true, timestamp: 1, data: vec![43] }).unwrap();
+
+        let mut identity = Identity::load_identity(identity.storage.database_folder.clone(), None).unwrap();
+        assert!(identity.encrypt_database);
+        assert_eq!(identity.load_msgs(&contact.uuid, 0, 10).unwrap().len(), 2);
+        identity.set_encrypt_database(false).unwrap();
+        assert!(!Path::new(&storage::get_encrypted_database_path(&identity.storage.database_folder)).exists());
+        assert_eq!(identity.load_contacts().unwrap()[0].name, "Bob");
+        assert_eq!(identity.load_msgs(&contact.uuid, 0, 10).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn loading_keeps_order() {
+        let identity = create_test_identity();
+        let names: Vec<String> = (0..40).map(|i| format!("Contact {}", i)).collect();
+        for (i, name) in names.iter().enumerate() {
+            identity.add_contact(name.clone(), None, [i as u8+1; PUBLIC_KEY_LENGTH]).unwrap();
+        }
+        for _ in 0..3 {
+            let contacts = identity.load_contacts().unwrap();
+            assert_eq!(contacts.iter().map(|contact| contact.name.clone()).collect::<Vec<_>>(), names);
+            assert!(contacts.iter().enumerate().all(|(i, contact)| contact.public_key == [i as u8+1; PUBLIC_KEY_LENGTH]));
+        }
+        let contact = identity.load_contacts().unwrap().remove(7);
+        for i in 0..300 {
+            identity.store_msg(&contact.uuid, Message { outgoing: i%2 == 0, timestamp: i, data: i.to_be_bytes().to_vec() }).unwrap();
+        }
+        let msgs = identity.load_msgs(&contact.uuid, 50, 200).unwrap();
+        assert_eq!(msgs.iter().map(|msg| msg.timestamp).collect::<Vec<_>>(), (50..250).collect::<Vec<_>>());
+        assert!(msgs.iter().all(|msg| msg.outgoing == (msg.timestamp%2 == 0) && msg.data == msg.timestamp.to_be_bytes()));
+    }
+
+    #[test]
+    fn legacy_rows_are_merged_into_records() {
+        let identity = create_test_identity();
+        let contact_uuid = Uuid::new_v4();
+        let legacy_contact_uuid = Uuid::new_v4();
+        let encrypt = |value: &[u8]| crypto::encrypt_data(value, &identity.master_key).unwrap();
+        let settings = ContactSettings { retention: 100, ..ContactSettings::default() };
+        identity.storage.with_database(|db| {
+            drop_data_tables(db)?;
+            db.execute(&format!("CREATE TABLE {} (uuid BLOB PRIMARY KEY, name BLOB, avatar BLOB, key BLOB, verified BLOB, seen BLOB, settings BLOB)", CONTACTS_TABLE.name), [])?;
+            db.execute(&format!("INSERT INTO {} (uuid, name, key, verified, seen, settings) VALUES (?1, ?2, ?3, ?4, ?5, ?6)", CONTACTS_TABLE.name), params![contact_uuid.as_bytes(), encrypt(b"Bob"), encrypt(&[1; PUBLIC_KEY_LENGTH]), encrypt(&[bool_to_byte(true)]), encrypt(&[bool_to_byte(false)]), encrypt(&settings.to_bytes())])?;
+            db.execute(&format!("INSERT INTO {} (uuid, name, key, verified, seen) VALUES (?1, ?2, ?3, ?4, ?5)", CONTACTS_TABLE.name), params![legacy_contact_uuid.as_bytes(), encrypt(b"Carol"), encrypt(&[2; PUBLIC_KEY_LENGTH]), encrypt(&[bool_to_byte(false)]), encrypt(&[bool_to_byte(true)])])?;
+            db.execute(&format!("CREATE TABLE {} (id INTEGER PRIMARY KEY, contact BLOB, outgoing BLOB, timestamp BLOB, data BLOB)", MESSAGES_TABLE.name), [])?;
+            db.execute(&format!("CREATE TABLE \"{}\" (outgoing BLOB, timestamp BLOB, data BLOB)", legacy_contact_uuid), [])?;
+            for i in 0..5u64 {
+                db.execute(&format!("INSERT INTO {} (contact, outgoing, timestamp, data) VALUES (?1, ?2, ?3, ?4)", MESSAGES_TABLE.name), params![encrypt(contact_uuid.as_bytes()), encrypt(&[bool_to_byte(true)]), encrypt(&i.to_be_bytes()), encrypt(&[i as u8])])?;
+                db.execute(&format!("INSERT INTO \"{}\" (outgoing, timestamp, data) VALUES (?1, ?2, ?3)", legacy_contact_uuid), params![encrypt(&[bool_to_byte(false)]), encrypt(&i.to_be_bytes()), encrypt(&[i as u8; 2])])?;
+            }
+            Ok(())
+        }).unwrap();
+
+        let identity = Identity::load_identity(identity.storage.database_folder.clone(), None).unwrap();
+        let contacts = identity.load_contacts().unwrap();
+        assert_eq!(contacts.iter().map(|contact| (contact.name.as_str(), contact.public_key, contact.verified, contact.seen)).collect::<Vec<_>>(), vec![("Bob", [1; PUBLIC_KEY_LENGTH], true, false), ("Carol", [2; PUBLIC_KEY_LENGTH], false, true)]);
+        assert_eq!(identity.get_contact_settings(&contact_uuid).unwrap(), settings);
+        assert_eq!(identity.get_contact_settings(&legacy_contact_uuid).unwrap(), ContactSettings::default());
+        let msgs = identity.load_msgs(&contact_uuid, 0, 10).unwrap();
+        assert_eq!(msgs.iter().map(|msg| (msg.outgoing, msg.timestamp, msg.data.clone())).collect::<Vec<_>>(), (0..5).map(|i| (true, i, vec![i as u8])).collect::<Vec<_>>());
+        let msgs = identity.load_msgs(&legacy_contact_uuid, 0, 10).unwrap();
+        assert_eq!(msgs.iter().map(|msg| (msg.outgoing, msg.timestamp, msg.data.clone())).collect::<Vec<_>>(), (0..5).map(|i| (false, i, vec![i as u8; 2])).collect::<Vec<_>>());
+
+        let legacy_values: i64 = identity.storage.with_database(|db| {
+            let contact_values: i64 = db.query_row(&format!("SELECT count(*) FROM {} WHERE key IS NOT NULL OR name IS NOT NULL", CONTACTS_TABLE.name), [], |row| row.get(0))?;
+            let message_values: i64 = db.query_row(&format!("SELECT count(*) FROM {} WHERE data IS NOT NULL OR timestamp IS NOT NULL", MESSAGES_TABLE.name), [], |row| row.get(0))?;
+            Ok(contact_values+message_values)
+        }).unwrap();
+        assert_eq!(legacy_values, 0);
+        identity.change_contact_name(&contact_uuid, "Bobby").unwrap();
+        assert_eq!(identity.load_contacts().unwrap()[0].name, "Bobby");
+    }
+
+    #[test]
+    fn plaintext_name_is_encrypted() {
+        let identity = create_test_identity();
+        identity.storage.set_value(DBKeys::NAME, b"Alice").unwrap();
+        identity.storage.remove_value(DBKeys::NAME_ENCRYPTED).unwrap();
+        let database_folder = identity.storage.database_folder.clone();
+        let identity = Identity::load_identity(database_folder.clone(), None).unwrap();
+        assert_eq!(identity.name, "Alice");
+        let encrypted_name = identity.storage.get_value(DBKeys::NAME).unwrap().unwrap();
+        assert_eq!(crypto::decrypt_data(&encrypted_name, &identity.master_key).unwrap(), b"Alice");
+        assert!(identity.storage.get_value(DBKeys::NAME_ENCRYPTED).unwrap().is_some());
+        assert_eq!(Identity::load_identity(database_folder.clone(), None).unwrap().name, "Alice");
+
+        //a damaged name is reported instead of being taken for a plaintext one
+        identity.storage.set_value(DBKeys::NAME, b"Mallory").unwrap();
+        assert_eq!(Identity::load_identity(database_folder, None).err(), Some(String::from(DATABASE_CORRUPED_ERROR)));
+        assert_eq!(identity.storage.get_value(DBKeys::NAME).unwrap().unwrap(), b"Mallory");
+    }
+
+    #[test]
+    fn corrupted_encryption_flag() {
+        let identity = create_test_identity();
+        let database_folder = identity.storage.database_folder.clone();
+        for flag in [&[][..], &[0x42], &[bool_to_byte(true), bool_to_byte(true)]] {
+            identity.storage.set_value(DBKeys::ENCRYPT_DATABASE, &crypto::encrypt_data(flag, &identity.master_key).unwrap()).unwrap();
+            assert_eq!(Identity::load_identity(database_folder.clone(), None).err(), Some(String::from(DATABASE_CORRUPED_ERROR)));
+        }
+    }
+
+    #[test]
+    fn login_label() {
+        let database_folder = std::env::temp_dir().join(format!("aira-test-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&database_folder).unwrap();
+        let database_folder = database_folder.to_str().unwrap().to_owned();
+        Identity::create_identidy(database_folder.clone(), "Alice", Some("Work"), false, None).unwrap();
+        assert_eq!(Identity::get_login_label(&database_folder).unwrap().as_deref(), Some("Work"));
+        assert!(!contains(&read_database_files(&database_folder), b"Alice"));
+        Identity::set_login_label(&database_folder, Some("Personal")).unwrap();
+        assert_eq!(Identity::get_login_label(&database_folder).unwrap().as_deref(), Some("Personal"));
+        Identity::set_login_label(&database_folder, None).unwrap();
+        assert_eq!(Identity::get_login_label(&database_folder).unwrap(), None);
+        SqliteStorage::new(database_folder.clone()).set_value(DBKeys::LOGIN_LABEL, &[0xff, 0xfe]).unwrap();
+        assert!(Identity::get_login_label(&database_folder).is_err());
+    }
+
+    //contacts table without settings, one messages table per contact and plaintext file owners, as written by the first versions
+    fn create_baseline_tables(identity: &Identity, contacts: &[(Uuid, &str, Vec<Message>)]) -> Uuid {
+        let encrypt = |value: &[u8]| crypto::encrypt_data(value, &identity.master_key).unwrap();
+        let file_uuid = Uuid::new_v4();
+        identity.storage.with_database(|db| {
+            drop_data_tables(db)?;
+            db.execute(&format!("CREATE TABLE {} (uuid BLOB PRIMARY KEY, name BLOB, avatar BLOB, key BLOB, verified BLOB, seen BLOB)", CONTACTS_TABLE.name), [])?;
+            db.execute(&format!("CREATE TABLE {} (contact_uuid BLOB, uuid BLOB, data BLOB)", FILES_TABLE.name), [])?;
+            for (i, (uuid, name, messages)) in contacts.iter().enumerate() {
+                db.execute(&format!("INSERT INTO {} (uuid, name, key, verified, seen) VALUES (?1, ?2, ?3, ?4, ?5)", CONTACTS_TABLE.name), params![uuid.as_bytes(), encrypt(name.as_bytes()), encrypt(&[i as u8+1; PUBLIC_KEY_LENGTH]), encrypt(&[bool_to_byte(false)]), encrypt(&[bool_to_byte(true)])])?;
+                db.execute(&format!("CREATE TABLE \"{}\" (outgoing BLOB, timestamp BLOB, data BLOB)", uuid), [])?;
+                for message in messages {
+                    db.execute(&format!("INSERT INTO \"{}\" (outgoing, timestamp, data) VALUES (?1, ?2, ?3)", uuid), params![encrypt(&[bool_to_byte(message.outgoing)]), encrypt(&message.timestamp.to_be_bytes()), encrypt(&message.data)])?;
+                }
+            }
+            db.execute(&format!("INSERT INTO {} (contact_uuid, uuid, data) VALUES (?1, ?2, ?3)", FILES_TABLE.name), params![contacts[0].0.as_bytes(), encrypt(file_uuid.as_bytes()), encrypt(&[5; 100])])?;
+            Ok(())
+        }).unwrap();
+        file_uuid
+    }
+
+    fn get_table_names(identity: &Identity) -> Vec<String> {
+        identity.storage.with_database(|db| {
+            let mut stmt = db.prepare("SELECT name FROM sqlite_master WHERE type='table'")?;
+            let table_names = stmt.query_map([], |row| row.get(0))?.collect();
+            table_names
+        }).unwrap()
+    }
+
+    #[test]
+    fn baseline_database_is_migrated() {
+        let identity = create_test_identity();
+        let messages = |seed: u64, count: u64| -> Vec<Message> {
+            (0..count).map(|i| Message { outgoing: (seed+i)%3 == 1, timestamp: 1000-i, data: vec![seed as u8; i as usize+1] }).collect()
+        };
+        let contacts = [(Uuid::new_v4(), "Bob", messages(1, 30)), (Uuid::new_v4(), "Carol", messages(2, 7)), (Uuid::new_v4(), "Dave", Vec::new())];
+        let file_uuid = create_baseline_tables(&identity, &contacts);
+
+        let identity = Identity::load_identity(identity.storage.database_folder.clone(), None).unwrap();
+        assert!(!get_table_names(&identity).iter().any(|table_name| Uuid::from_str(table_name).is_ok()));
+        assert_eq!(identity.load_contacts().unwrap().iter().map(|contact| contact.name.as_str()).collect::<Vec<_>>(), vec!["Bob", "Carol", "Dave"]);
+        assert_eq!(get_raw_messages(&identity).len(), 37);
+        for (uuid, _, messages) in &contacts {
+            let loaded = identity.load_msgs(uuid, 0, 100).unwrap_or_default();
+            assert_eq!(loaded.iter().map(|msg| (msg.outgoing, msg.timestamp, msg.data.clone())).collect::<Vec<_>>(), messages.iter().map(|msg| (msg.outgoing, msg.timestamp, msg.data.clone())).collect::<Vec<_>>());
+            assert_eq!(identity.get_contact_settings(uuid).unwrap(), ContactSettings::default());
+        }
+        assert_eq!(identity.load_msgs(&contacts[0].0, 5, 3).unwrap().iter().map(|msg| msg.timestamp).collect::<Vec<_>>(), vec![978, 977, 976]);
+        assert_eq!(identity.load_file(file_uuid), Some(vec![5; 100]));
+        let plaintext_owners: i64 = identity.storage.with_database(|db| db.query_row(&format!("SELECT count(*) FROM {} WHERE length(contact_uuid)=16", FILES_TABLE.name), [], |row| row.get(0))).unwrap();
+        assert_eq!(plaintext_owners, 0);
+        identity.delete_conversation(&contacts[0].0).unwrap();
+        assert_eq!(identity.load_file(file_uuid), None);
+        assert_eq!(identity.load_msgs(&contacts[1].0, 0, 100).unwrap().len(), 7);
+    }
+
+    #[test]
+    fn undecryptable_legacy_rows_abort_migration() {
+        let identity = create_test_identity();
+        let contact_uuid = Uuid::new_v4();
+        create_baseline_tables(&identity, &[(contact_uuid, "Bob", vec![Message { outgoing: true, timestamp: 1, data: vec![1] }])]);
+        identity.storage.with_database(|db| db.execute(&format!("INSERT INTO \"{}\" (outgoing, timestamp, data) VALUES (?1, ?2, ?3)", contact_uuid), params![vec![0u8; 40], vec![0u8; 40], vec![0u8; 40]])).unwrap();
+
+        let identity = Identity::load_identity(identity.storage.database_folder.clone(), None).unwrap();
+        assert!(get_table_names(&identity).contains(&contact_uuid.to_string()));
+        let legacy_rows: i64 = identity.storage.with_database(|db| db.query_row(&format!("SELECT count(*) FROM \"{}\"", contact_uuid), [], |row| row.get(0))).unwrap();
+        assert_eq!(legacy_rows, 2);
+        //nothing of the aborted migration remains, the tables created afterwards are empty
+        identity.storage.with_database(|db| {
+            assert_eq!(db.query_row(&format!("SELECT count(*) FROM {}", MESSAGES_TABLE.name), [], |row| row.get::<_, i64>(0))?, 0);
+            assert!(!storage::column_exists(db, CONTACTS_TABLE.name, "record")?);
+            Ok(())
+        }).unwrap();
+    }
+
+    #[test]
+    fn encrypting_database_leaves_no_plaintext_copy() {
+        let mut identity = create_test_identity();
+        let contact = identity.add_contact("Bob".to_owned(), None, [1; PUBLIC_KEY_LENGTH]).unwrap();
+        for i in 0..20 {
+            identity.store_msg(&contact.uuid, Message { outgoing: true, timestamp: i, data: vec![i as u8; 300] }).unwrap();
+        }
+        let raw_messages = get_raw_messages(&identity);
+        let database_path = identity.storage.get_database_path();
+        let read_plaintext_files = || {
+            let mut content = std::fs::read(&database_path).unwrap();
+            content.extend(std::fs::read(database_path.clone()+"-wal").unwrap_or_default());
+            content
+        };
+        assert!(contains(&read_plaintext_files(), &raw_messages[0]));
+
+        identity.set_encrypt_database(true).unwrap();
+        let plaintext_files = read_plaintext_files();
+        let content = read_database_files(&identity.storage.database_folder);
+        for raw_message in &raw_messages {
+            assert!(!contains(&plaintext_files, raw_message));
+            assert!(!contains(&content, raw_message));
+        }
+        assert_eq!(identity.load_msgs(&contact.uuid, 0, 100).unwrap().len(), 20);
+    }
+
+    fn create_protected_identity(encrypt_database: bool) -> Identity {
+        let database_folder = std::env::temp_dir().join(format!("aira-test-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&database_folder).unwrap();
+        Identity::create_identidy(database_folder.to_str().unwrap().to_owned(), "Alice", None, encrypt_database, Some(b"password")).unwrap()
+    }
+
+    //each identity sharing the database fills the lookup keys of its own rows only
+    #[test]
+    fn lookup_keys_are_added_to_existing_rows() {
+        let identity = create_protected_identity(false);
+        identity.set_duress_password(b"duress", DuressAction::Decoy("Eve".to_owned())).unwrap();
+        let database_folder = identity.storage.database_folder.clone();
+        let decoy = Identity::load_identity(database_folder.clone(), Some(b"duress")).unwrap();
+        let file_uuids = [Uuid::new_v4(), Uuid::new_v4()];
+        identity.storage.with_database(|db| {
+            db.execute(&format!("DROP TABLE {}", FILES_TABLE.name), [])?;
+            db.execute(&format!("DROP TABLE {}", BLOCKED_TABLE.name), [])?;
+            db.execute(&format!("CREATE TABLE {} (contact_uuid BLOB, uuid BLOB, data BLOB)", FILES_TABLE.name), [])?;
+            db.execute(&format!("CREATE TABLE {} (id INTEGER PRIMARY KEY, key BLOB)", BLOCKED_TABLE.name), [])?;
+            for (i, master_key) in [identity.master_key, decoy.master_key].iter().enumerate() {
+                let encrypt = |value: &[u8]| crypto::encrypt_data(value, master_key).unwrap();
+                db.execute(&format!("INSERT INTO {} (uuid, data) VALUES (?1, ?2)", FILES_TABLE.name), params![encrypt(file_uuids[i].as_bytes()), encrypt(&[i as u8; 10])])?;
+                db.execute(&format!("INSERT INTO {} (key) VALUES (?1)", BLOCKED_TABLE.name), params![encrypt(&[i as u8; PUBLIC_KEY_LENGTH])])?;
+            }
+            Ok(())
+        }).unwrap();
+        drop(decoy);
+        let count_lookup_keys = |identity: &Identity| -> i64 {
+            identity.storage.with_database(|db| db.query_row(&format!("SELECT (SELECT count(lookup) FROM {}) + (SELECT count(lookup) FROM {})", FILES_TABLE.name, BLOCKED_TABLE.name), [], |row| row.get(0))).unwrap()
+        };
+
+        let identity = Identity::load_identity(database_folder.clone(), Some(b"password")).unwrap();
+        assert_eq!(count_lookup_keys(&identity), 2);
+        assert_eq!(identity.load_file(file_uuids[0]), Some(vec![0; 10]));
+        assert_eq!(identity.load_file(file_uuids[1]), None);
+        assert!(identity.is_blocked(&[0; PUBLIC_KEY_LENGTH]).unwrap());
+        assert!(!identity.is_blocked(&[1; PUBLIC_KEY_LENGTH]).unwrap());
+        drop(identity);
+        let decoy = Identity::load_identity(database_folder, Some(b"duress")).unwrap();
+        assert_eq!(count_lookup_keys(&decoy), 4);
+        assert_eq!(decoy.load_file(file_uuids[1]), Some(vec![1; 10]));
+        assert_eq!(decoy.load_file(file_uuids[0]), None);
+        assert!(decoy.is_blocked(&[1; PUBLIC_KEY_LENGTH]).unwrap());
+        assert!(!decoy.is_blocked(&[0; PUBLIC_KEY_LENGTH]).unwrap());
+    }
+
+    #[test]
+    fn duress_destroy_key_wipes_both_databases() {
+        let identity = create_protected_identity(true);
+        identity.add_contact("Bob".to_owned(), None, [1; PUBLIC_KEY_LENGTH]).unwrap();
+        identity.set_duress_password(b"duress", DuressAction::Destroy).unwrap();
+        let database_folder = identity.storage.database_folder.clone();
+        assert!(Path::new(&storage::get_database_path(&database_folder)).exists());
+        assert!(Path::new(&storage::get_encrypted_database_path(&database_folder)).exists());
+        drop(identity);
+
+        assert_eq!(Identity::load_identity(database_folder.clone(), Some(b"duress")).err(), Some(String::from(BAD_PASSWORD_ERROR)));
+        assert!(!Path::new(&storage::get_database_path(&database_folder)).exists());
+        assert!(!Path::new(&storage::get_encrypted_database_path(&database_folder)).exists());
+        assert!(!Identity::exists(&database_folder).unwrap());
+    }
+
+    #[test]
+    fn decoy_is_indistinguishable_and_isolated() {
+        let mut identity = create_protected_identity(true);
+        let bob = identity.add_contact("Bob".to_owned(), None, [1; PUBLIC_KEY_LENGTH]).unwrap();
+        identity.store_msg(&bob.uuid, Message { outgoing: true, timestamp: 0, data: vec![1] }).unwrap();
+        identity.set_duress_password(b"duress", DuressAction::Decoy("Eve".to_owned())).unwrap();
+        let database_folder = identity.storage.database_folder.clone();
+
+        let mut decoy = Identity::load_identity(database_folder.clone(), Some(b"duress")).unwrap();
+        assert_eq!(decoy.name, "Eve");
+        assert!(decoy.encrypt_database);
+        assert!(decoy.load_contacts().unwrap().is_empty());
+        assert!(decoy.load_msgs(&bob.uuid, 0, 10).is_none());
+        let carol = decoy.add_contact("Carol".to_owned(), None, [2; PUBLIC_KEY_LENGTH]).unwrap();
+        decoy.store_msg(&carol.uuid, Message { outgoing: false, timestamp: 1, data: vec![2] }).unwrap();
+        //settings calls succeed without touching what belongs to the real identity
+        assert_eq!(decoy.set_encrypt_database(false).unwrap(), 1);
+        assert!(decoy.set_duress_password(b"duress", DuressAction::Destroy).is_err());
+        decoy.set_duress_password(b"other", DuressAction::Destroy).unwrap();
+        decoy.remove_duress_password().unwrap();
+        drop(decoy);
+
+        //the decoy data survives conversions made by the real identity
+        identity.set_encrypt_database(false).unwrap();
+        assert_eq!(identity.load_contacts().unwrap().iter().map(|contact| contact.name.as_str()).collect::<Vec<_>>(), vec!["Bob"]);
+        let decoy = Identity::load_identity(database_folder.clone(), Some(b"duress")).unwrap();
+        assert!(!decoy.encrypt_database);
+        assert_eq!(decoy.load_contacts().unwrap().iter().map(|contact| contact.name.as_str()).collect::<Vec<_>>(), vec!["Carol"]);
+        drop(decoy);
+        identity.set_encrypt_database(true).unwrap();
+        drop(identity);
+
+        let decoy = Identity::load_identity(database_folder.clone(), Some(b"duress")).unwrap();
+        assert!(decoy.encrypt_database);
+        assert_eq!(decoy.load_msgs(&carol.uuid, 0, 10).unwrap()[0].data, vec![2]);
+        assert!(decoy.load_msgs(&bob.uuid, 0, 10).is_none());
+        let identity = Identity::load_identity(database_folder, Some(b"password")).unwrap();
+        assert_eq!(identity.load_contacts().unwrap().len(), 1);
+        assert_eq!(identity.load_msgs(&bob.uuid, 0, 10).unwrap()[0].data, vec![1]);
+        assert!(identity.load_msgs(&carol.uuid, 0, 10).is_none());
+    }
+
+    #[test]
+    fn decoy_rows_are_skipped_silently() {
+        let identity = create_protected_identity(true);
+        let bob = identity.add_contact("Bob".to_owned(), None, [1; PUBLIC_KEY_LENGTH]).unwrap();
+        identity.block(&[3; PUBLIC_KEY_LENGTH]).unwrap();
+        identity.add_group(None, "Friends".to_owned(), vec![bob.uuid]).unwrap();
+        let file = identity.store_file(None, b"real").unwrap();
+        let avatar = identity.store_avatar(b"avatar").unwrap();
+        identity.set_duress_password(b"duress", DuressAction::Decoy("Eve".to_owned())).unwrap();
+        let decoy = Identity::load_identity(identity.storage.database_folder.clone(), Some(b"duress")).unwrap();
+        let decoy_file = decoy.store_file(None, b"decoy").unwrap();
+
+        let errors = logged_errors().len();
+        assert!(decoy.load_contacts().unwrap().is_empty());
+        assert!(decoy.load_blocked().unwrap().is_empty());
+        assert!(decoy.load_groups().unwrap().is_empty());
+        assert_eq!(decoy.load_file(file), None);
+        assert_eq!(decoy.get_avatar(&avatar), None);
+        decoy.clear_cache().unwrap();
+        assert_eq!(identity.load_contacts().unwrap().len(), 1);
+        assert_eq!(identity.load_blocked().unwrap().len(), 1);
+        assert_eq!(identity.load_groups().unwrap().len(), 1);
+        assert_eq!(logged_errors().len(), errors);
+
+        //each identity only clears its own cache
+        assert_eq!(decoy.load_file(decoy_file), None);
+        assert_eq!(identity.load_file(file).unwrap(), b"real");
+        assert_eq!(identity.get_avatar(&avatar).unwrap(), b"avatar");
+    }
+
+    #[test]
+    fn destroy_removes_all_files() {
+        let identity = create_test_identity();
+        identity.add_contact("Bob".to_owned(), None, [1; PUBLIC_KEY_LENGTH]).unwrap();
+        Identity::destroy(&identity.storage.database_folder).unwrap();
+        assert_eq!(std::fs::read_dir(&identity.storage.database_folder).unwrap().count(), 0);
+        assert!(!Identity::exists(&identity.storage.database_folder).unwrap());
+    }
+
+    fn create_memory_identity() -> Identity<MemoryStorage> {
+        Identity::create(MemoryStorage::new(), "Alice", None, false, None).unwrap()
+    }
+
+    #[test]
+    fn memory_identity_is_reloaded() {
+        let mut identity = create_memory_identity();
+        identity.change_name("Alice2".to_owned()).unwrap();
+        identity.set_cover_traffic_rate(60).unwrap();
+        let public_key = identity.get_public_key();
+        let identity = Identity::load(identity.storage, None).unwrap();
+        assert_eq!(identity.name, "Alice2");
+        assert_eq!(identity.cover_traffic_rate, 60);
+        assert_eq!(identity.get_public_key(), public_key);
+    }
+
+    #[test]
+    fn memory_password() {
+        let identity = Identity::create(MemoryStorage::new(), "Alice", None, false, Some(b"password")).unwrap();
+        assert!(Identity::load(identity.storage, Some(b"wrong")).is_err());
+        let identity = Identity::create(MemoryStorage::new(), "Alice", None, false, Some(b"password")).unwrap();
+        assert!(Identity::change_storage_password(&identity.storage, Some(b"password"), Some(b"new")).unwrap());
+        assert!(!Identity::change_storage_password(&identity.storage, Some(b"password"), None).unwrap());
+        assert_eq!(Identity::load(identity.storage, Some(b"new")).unwrap().name, "Alice");
+    }
+
+    #[test]
+    fn memory_contacts() {
+        let identity = create_memory_identity();
+        let bob = identity.add_contact("Bob".to_owned(), None, [1; PUBLIC_KEY_LENGTH]).unwrap();
+        let carol = identity.add_contact("Carol".to_owned(), None, [2; PUBLIC_KEY_LENGTH]).unwrap();
+        assert_eq!(identity.storage.select(&CONTACTS_TABLE, &["uuid"]).unwrap().len(), CONTACTS_BUCKET_SIZE);
+        identity.change_contact_name(&bob.uuid, "Bobby").unwrap();
+        identity.set_contact_seen(&bob.uuid, false).unwrap();
+        identity.set_verified(&carol.uuid).unwrap();
+        let avatar = identity.store_avatar(&[7; 100]).unwrap();
+        identity.set_contact_avatar(&carol.uuid, Some(&avatar)).unwrap();
+
+        let contacts = identity.load_contacts().unwrap();
+        assert_eq!(contacts.len(), 2);
+        let (bob, carol) = (&contacts[0], &contacts[1]);
+        assert_eq!((bob.name.as_str(), bob.seen, bob.verified, bob.avatar), ("Bobby", false, false, None));
+        assert_eq!((carol.name.as_str(), carol.seen, carol.verified, carol.avatar), ("Carol", true, true, Some(avatar)));
+        assert_eq!(identity.get_avatar(&avatar), Some(vec![7; 100]));
+
+        identity.set_contact_avatar(&carol.uuid, None).unwrap();
+        assert_eq!(identity.get_avatar(&avatar), None);
+        assert_eq!(identity.load_contacts().unwrap()[1].avatar, None);
+
+        identity.remove_contact(&bob.uuid).unwrap();
+        assert_eq!(identity.load_contacts().unwrap().len(), 1);
+        assert_eq!(identity.storage.select(&CONTACTS_TABLE, &["uuid"]).unwrap().len(), CONTACTS_BUCKET_SIZE);
+    }
+
+    #[test]
+    fn memory_messages() {
+        let identity = create_memory_identity();
+        let bob = identity.add_contact("Bob".to_owned(), None, [1; PUBLIC_KEY_LENGTH]).unwrap();
+        let carol = identity.add_contact("Carol".to_owned(), None, [2; PUBLIC_KEY_LENGTH]).unwrap();
+        for i in 0..10 {
+            identity.store_msg(&bob.uuid, Message { outgoing: i%2 == 0, timestamp: i, data: vec![i as u8] }).unwrap();
+        }
+        identity.store_msg(&carol.uuid, Message { outgoing: true, timestamp: 0, data: vec![42] }).unwrap();
+
+        let msgs = identity.load_msgs(&bob.uuid, 0, 3).unwrap();
+        assert_eq!(msgs.iter().map(|msg| msg.timestamp).collect::<Vec<_>>(), vec![7, 8, 9]);
+        assert!(!msgs[0].outgoing && msgs[1].outgoing);
+        let msgs = identity.load_msgs(&bob.uuid, 8, 5).unwrap();
+        assert_eq!(msgs.iter().map(|msg| msg.data[0]).collect::<Vec<_>>(), vec![0, 1]);
+        assert!(identity.load_msgs(&bob.uuid, 10, 5).is_none());
+        identity.store_msg(&bob.uuid, Message { outgoing: true, timestamp: 10, data: vec![10] }).unwrap();
+        identity.store_msgs(&carol.uuid, &[Message { outgoing: true, timestamp: 1, data: vec![43] }]).unwrap();
+        assert_eq!(identity.load_msgs(&bob.uuid, 0, 1).unwrap()[0].timestamp, 10);
+
+        assert_eq!(identity.delete_conversation(&bob.uuid).unwrap(), 11);
+        assert!(identity.load_msgs(&bob.uuid, 0, 10).is_none());
+        assert_eq!(identity.load_msgs(&carol.uuid, 0, 10).unwrap().iter().map(|msg| msg.data[0]).collect::<Vec<_>>(), vec![42, 43]);
+    }
+
+    #[test]
+    fn memory_files() {
+        let identity = create_memory_identity();
+        let bob = identity.add_contact("Bob".to_owned(), None, [1; PUBLIC_KEY_LENGTH]).unwrap();
+        let file = identity.store_file(Some(bob.uuid), &[1; 1000]).unwrap();
+        let cached_file = identity.store_file(None, &[2; 1000]).unwrap();
+        let cached_avatar = identity.store_avatar(&[3; 10]).unwrap();
+        assert_eq!(identity.load_file(file), Some(vec![1; 1000]));
+        assert_eq!(identity.load_file(cached_file), Some(vec![2; 1000]));
+
+        identity.clear_cache().unwrap();
+        assert_eq!(identity.load_file(file), Some(vec![1; 1000]));
+        assert_eq!(identity.load_file(cached_file), None);
+        assert_eq!(identity.get_avatar(&cached_avatar), None);
+
+        identity.delete_conversation(&bob.uuid).unwrap();
+        assert_eq!(identity.load_file(file), None);
+    }
+
+    #[test]
+    fn memory_groups_blocked_and_key_history() {
+        let identity = create_memory_identity();
+        let bob = identity.add_contact("Bob".to_owned(), None, [1; PUBLIC_KEY_LENGTH]).unwrap();
+        let group = identity.add_group(None, "Friends".to_owned(), vec![bob.uuid]).unwrap();
+        identity.change_group_name(&group.uuid, "Family").unwrap();
+        identity.store_group_msg(&group.uuid, Some(&bob.uuid), Message { outgoing: false, timestamp: 1, data: vec![1] }).unwrap();
+        let groups = identity.load_groups().unwrap();
+        assert_eq!((groups[0].name.as_str(), groups[0].members.clone()), ("Family", vec![bob.uuid]));
+        assert_eq!(identity.load_group_msgs(&group.uuid, 0, 10).unwrap()[0].0, Some(bob.uuid));
+
+        identity.block(&[9; PUBLIC_KEY_LENGTH]).unwrap();
+        assert!(identity.is_blocked(&[9; PUBLIC_KEY_LENGTH]).unwrap());
+
+        identity.change_contact_key(&bob.uuid, [2; PUBLIC_KEY_LENGTH], 5).unwrap();
+        assert_eq!(identity.load_key_history(&bob.uuid).unwrap()[0].public_key, [1; PUBLIC_KEY_LENGTH]);
+
+        identity.remove_contact(&bob.uuid).unwrap();
+        assert!(identity.load_groups().unwrap()[0].members.is_empty());
+        assert!(identity.load_key_history(&bob.uuid).unwrap().is_empty());
+        identity.remove_group(&group.uuid).unwrap();
+        assert!(identity.load_group_msgs(&group.uuid, 0, 10).is_none());
+    }
+
+    #[test]
+    fn malformed_groups_are_skipped() {
+        let identity = create_memory_identity();
+        let group = identity.add_group(None, "Friends".to_owned(), Vec::new()).unwrap();
+        let encrypt = |data: &[u8]| crypto::encrypt_data(data, &identity.master_key).unwrap();
+        identity.storage.insert(&GROUPS_TABLE, &[("uuid", &[1; 5]), ("name", &encrypt(b"Short UUID")), ("members", &encrypt(&[]))]).unwrap();
+        identity.storage.insert(&GROUPS_TABLE, &[("uuid", &[2; 16]), ("name", &encrypt(&[0xff, 0xfe])), ("members", &encrypt(&[]))]).unwrap();
+        let groups = identity.load_groups().unwrap();
+        assert_eq!(groups.len(), 1);
+        assert_eq!((groups[0].uuid, groups[0].name.as_str()), (group.uuid, "Friends"));
+    }
+
+    #[test]
+    fn malformed_key_history_is_skipped() {
+        let identity = create_memory_identity();
+        let contact = identity.add_contact("Bob".to_owned(), None, [1; PUBLIC_KEY_LENGTH]).unwrap();
+        identity.change_contact_key(&contact.uuid, [2; PUBLIC_KEY_LENGTH], 42).unwrap();
+        let encrypt = |data: &[u8]| crypto::encrypt_data(data, &identity.master_key).unwrap();
+        let encrypted_contact = encrypt(contact.uuid.as_bytes());
+        identity.storage.insert(&KEY_HISTORY_TABLE, &[("contact", &encrypted_contact), ("key", &encrypt(&[3; 5])), ("timestamp", &encrypt(&43u64.to_be_bytes()))]).unwrap();
+        identity.storage.insert(&KEY_HISTORY_TABLE, &[("contact", &encrypted_contact), ("key", &encrypt(&[4; PUBLIC_KEY_LENGTH])), ("timestamp", &encrypt(&[44]))]).unwrap();
+        let previous_keys = identity.load_key_history(&contact.uuid).unwrap();
+        assert_eq!(previous_keys.len(), 1);
+        assert_eq!((previous_keys[0].public_key, previous_keys[0].timestamp), ([1; PUBLIC_KEY_LENGTH], 42));
+    }
+}
@@ -0,0 +1,14 @@
+mod key_value_table;
+mod utils;
+pub mod identity;
+pub mod storage;
+pub mod crypto;
+pub mod padding;
+pub mod cover_traffic;
+pub mod discovery;
+pub mod verification;
+pub mod protocol;
+pub mod net;
+
+pub use identity::{Identity, Contact, Group, Message};
+pub use net::{SessionEvents, SessionManager};
@@ -0,0 +1,553 @@
+use std::{collections::HashMap, convert::{TryFrom, TryInto}, io, net::{IpAddr, SocketAddr}, sync::{Arc, Mutex, atomic::{AtomicU64, Ordering}}, time::Duration};
+use aes_gcm::{Aes128Gcm, Key, NewAead, Nonce, aead::{Aead, Payload}};
+use curve25519_dalek::{constants::X25519_BASEPOINT, scalar::Scalar};
+use ed25519_dalek::{Keypair, PublicKey, Signature, Signer, Verifier, PUBLIC_KEY_LENGTH, SIGNATURE_LENGTH};
+use rand::{RngCore, rngs::OsRng};
+use sha2::{Digest, Sha384};
+use tokio::{io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt}, net::{TcpListener, TcpStream}, sync::{Notify, mpsc, watch}};
+use zeroize::Zeroize;
+use crate::{cover_traffic, crypto::{self, ApplicationKeys, HandshakeKeys, AES_TAG_LEN, HASH_OUTPUT_LEN, IV_LEN, KEY_LEN}, padding::{self, PaddingPolicy}, protocol};
+
+pub const PORT: u16 = 7530;
+const RANDOM_LEN: usize = 64;
+const EPHEMERAL_KEY_LEN: usize = 32;
+pub const MESSAGE_LEN_LEN: usize = 4;
+pub const MAX_RECV_SIZE: usize = padding::PADDED_MAX_SIZE + AES_TAG_LEN;
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+//callbacks are invoked from the runtime's worker threads
+pub trait SessionEvents: Send + Sync {
+    //called once the peer is authenticated. Returning false drops the connection (blocked keys...)
+    fn accept(&self, _peer_public_key: &[u8; PUBLIC_KEY_LENGTH]) -> bool {
+        true
+    }
+    fn on_session_opened(&self, peer_public_key: &[u8; PUBLIC_KEY_LENGTH], ip: IpAddr, outgoing: bool);
+    fn on_message(&self, peer_public_key: &[u8; PUBLIC_KEY_LENGTH], message: Vec<u8>);
+    fn on_session_closed(&self, peer_public_key: &[u8; PUBLIC_KEY_LENGTH]);
+}
+
+//AES-128-GCM with a per-direction counter XORed into the IV, as in PSEC
+//the AES key schedule can't be wiped so only the raw key is kept, the keys are zeroized on drop
+pub struct RecordCipher {
+    key: [u8; KEY_LEN],
+    iv: [u8; IV_LEN],
+    counter: u64,
+}
+
+impl RecordCipher {
+    pub fn new(key: &[u8], iv: [u8; IV_LEN]) -> RecordCipher {
+        RecordCipher {
+            key: key.try_into().unwrap(),
+            iv,
+            counter: 0,
+        }
+    }
+
+    fn cipher(&self) -> Aes128Gcm {
+        Aes128Gcm::new(Key::from_slice(&self.key))
+    }
+
+    fn next_nonce(&mut self) -> [u8; IV_LEN] {
+        let mut nonce = [0; IV_LEN];
+        nonce[IV_LEN-8..].copy_from_slice(&self.counter.to_be_bytes());
+        nonce.iter_mut().zip(self.iv.iter()).for_each(|(n, iv)| *n ^= iv);
+        self.counter += 1;
+        nonce
+    }
+
+    //record format: ciphertext length (u32 BE, also used as AAD) || AES-GCM(padded message)
+    pub fn encrypt(&mut self, padded: &[u8]) -> Vec<u8> {
+        let raw_len = ((padded.len()+AES_TAG_LEN) as u32).to_be_bytes();
+        let nonce = self.next_nonce();
+        let cipher_text = self.cipher().encrypt(Nonce::from_slice(&nonce), Payload { msg: padded, aad: &raw_len }).unwrap();
+        [&raw_len[..], &cipher_text].concat()
+    }
+
+    pub fn decrypt(&mut self, raw_len: &[u8; MESSAGE_LEN_LEN], cipher_text: &[u8]) -> Option<Vec<u8>> {
+        let nonce = self.next_nonce();
+        self.cipher().decrypt(Nonce::from_slice(&nonce), Payload { msg: cipher_text, aad: raw_len }).ok()
+    }
+}
+
+impl Drop for RecordCipher {
+    fn drop(&mut self) {
+        self.key.zeroize();
+        self.iv.zeroize();
+    }
+}
+
+pub struct SecureChannel {
+    pub peer_public_key: [u8; PUBLIC_KEY_LENGTH],
+    pub local_cipher: RecordCipher,
+    pub peer_cipher: RecordCipher,
+}
+
+struct Transcript {
+    sent: Vec<u8>,
+    received: Vec<u8>,
+}
+
+impl Transcript {
+    //mutual consensus for keys attribution
+    fn i_am_bob(&self) -> io::Result<bool> {
+        match self.sent.iter().zip(self.received.iter()).find(|(s, r)| s != r) {
+            Some((s, r)) => Ok(s < r),
+            None => Err(invalid_data("Handshake buffers are identical")),
+        }
+    }
+
+    fn hash(&self, i_am_bob: bool) -> [u8; HASH_OUTPUT_LEN] {
+        let mut hasher = Sha384::new();
+        if i_am_bob {
+            hasher.update(&self.sent);
+            hasher.update(&self.received);
+        } else {
+            hasher.update(&self.received);
+            hasher.update(&self.sent);
+        }
+        hasher.finalize().as_slice().try_into().unwrap()
+    }
+
+    async fn write<S: AsyncWrite + Unpin>(&mut self, stream: &mut S, buffer: &[u8]) -> io::Result<()> {
+        stream.write_all(buffer).await?;
+        self.sent.extend_from_slice(buffer);
+        Ok(())
+    }
+
+    async fn read<S: AsyncRead + Unpin>(&mut self, stream: &mut S, len: usize) -> io::Result<Vec<u8>> {
+        let mut buffer = vec![0; len];
+        stream.read_exact(&mut buffer).await?;
+        self.received.extend_from_slice(&buffer);
+        Ok(buffer)
+    }
+}
+
+//same handshake as Session.doHandshake: both sides run it symmetrically
+pub async fn handshake<S: AsyncRead + AsyncWrite + Unpin>(stream: &mut S, keypair: &Keypair) -> io::Result<SecureChannel> {
+    let mut transcript = Transcript {
+        sent: Vec::new(),
+        received: Vec::new(),
+    };
+
+    let mut random = [0; RANDOM_LEN];
+    OsRng.fill_bytes(&mut random);
+    let mut ephemeral_secret = [0; EPHEMERAL_KEY_LEN];
+    OsRng.fill_bytes(&mut ephemeral_secret);
+    ephemeral_secret[0] &= 248;
+    ephemeral_secret[31] &= 127;
+    ephemeral_secret[31] |= 64;
+    let ephemeral_scalar = Scalar::from_bits(ephemeral_secret);
+    ephemeral_secret.zeroize();
+    let ephemeral_public_key = (X25519_BASEPOINT * ephemeral_scalar).to_bytes();
+    transcript.write(stream, &[&random[..], &ephemeral_public_key].concat()).await?;
+
+    let peer_hello = transcript.read(stream, RANDOM_LEN+EPHEMERAL_KEY_LEN).await?;
+    let peer_ephemeral_public_key: [u8; EPHEMERAL_KEY_LEN] = peer_hello[RANDOM_LEN..].try_into().unwrap();
+    let mut shared_secret = (curve25519_dalek::montgomery::MontgomeryPoint(peer_ephemeral_public_key) * ephemeral_scalar).to_bytes();
+    if shared_secret == [0; 32] {
+        return Err(invalid_data("Low order ephemeral key"));
+    }
+    let i_am_bob = transcript.i_am_bob()?;
+    let handshake_keys = HandshakeKeys::derive_keys(shared_secret, transcript.hash(i_am_bob), i_am_bob);
+    shared_secret.zeroize();
+
+    OsRng.fill_bytes(&mut random);
+    let auth = [&random[..], keypair.public.as_bytes(), &keypair.sign(&ephemeral_public_key).to_bytes()].concat();
+    let encrypted_auth = Aes128Gcm::new(Key::from_slice(&handshake_keys.local_key)).encrypt(Nonce::from_slice(&handshake_keys.local_iv), auth.as_slice()).unwrap();
+    transcript.write(stream, &encrypted_auth).await?;
+
+    let peer_encrypted_auth = transcript.read(stream, RANDOM_LEN+PUBLIC_KEY_LENGTH+SIGNATURE_LENGTH+AES_TAG_LEN).await?;
+    let peer_auth = Aes128Gcm::new(Key::from_slice(&handshake_keys.peer_key)).decrypt(Nonce::from_slice(&handshake_keys.peer_iv), peer_encrypted_auth.as_slice())
+        .map_err(|_| invalid_data("Handshake decryption failed"))?;
+    let peer_public_key: [u8; PUBLIC_KEY_LENGTH] = peer_auth[RANDOM_LEN..RANDOM_LEN+PUBLIC_KEY_LENGTH].try_into().unwrap();
+    let signature = Signature::try_from(&peer_auth[RANDOM_LEN+PUBLIC_KEY_LENGTH..]).map_err(|_| invalid_data("Invalid signature"))?;
+    PublicKey::from_bytes(&peer_public_key)
+        .and_then(|public_key| public_key.verify(&peer_ephemeral_public_key, &signature))
+        .map_err(|_| invalid_data("Signature verification failed"))?;
+
+    let handshake_hash = transcript.hash(i_am_bob);
+    stream.write_all(&crypto::compute_handshake_finished(handshake_keys.local_handshake_traffic_secret, handshake_hash)).await?;
+    let mut peer_handshake_finished = [0; HASH_OUTPUT_LEN];
+    stream.read_exact(&mut peer_handshake_finished).await?;
+    if !crypto::verify_handshake_finished(peer_handshake_finished, handshake_keys.peer_handshake_traffic_secret, handshake_hash) {
+        return Err(invalid_data("Final verification failed"));
+    }
+    let application_keys = ApplicationKeys::derive_keys(handshake_keys.handshake_secret, handshake_hash, i_am_bob);
+    Ok(SecureChannel {
+        peer_public_key,
+        local_cipher: RecordCipher::new(&application_keys.local_key, application_keys.local_iv),
+        peer_cipher: RecordCipher::new(&application_keys.peer_key, application_keys.peer_iv),
+    })
+}
+
+//Ok(None) on a clean EOF before a new record. Cover traffic is dropped here and never reaches the application.
+pub async fn read_record<S: AsyncRead + Unpin>(stream: &mut S, cipher: &mut RecordCipher) -> io::Result<Option<Vec<u8>>> {
+    loop {
+        let mut raw_len = [0; MESSAGE_LEN_LEN];
+        match stream.read_exact(&mut raw_len).await {
+            Ok(_) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e),
+        }
+        let len = u32::from_be_bytes(raw_len) as usize;
+        if len <= AES_TAG_LEN || len > MAX_RECV_SIZE {
+            return Err(invalid_data("Invalid record length"));
+        }
+        let mut cipher_text = vec![0; len];
+        stream.read_exact(&mut cipher_text).await?;
+        let padded = cipher.decrypt(&raw_len, &cipher_text).ok_or_else(|| invalid_data("Record decryption failed"))?;
+        match padding::unpad(&padded) {
+            Some(message) if protocol::is_dummy(message) => {}
+            Some(message) => return Ok(Some(message.to_vec())),
+            None => return Err(invalid_data("Invalid padding")),
+        }
+    }
+}
+
+struct SessionHandle {
+    id: u64,
+    ip: IpAddr,
+    outgoing: bool,
+    sender: mpsc::UnboundedSender<Vec<u8>>,
+}
+
+pub struct SessionInfo {
+    pub peer_public_key: [u8; PUBLIC_KEY_LENGTH],
+    pub ip: IpAddr,
+    pub outgoing: bool,
+}
+
+//live sessions keyed by peer public key. Only one session per peer is kept: the first one wins.
+//used by aira-cli only: the Android service keeps driving its sockets from Kotlin, see Session.kt
+pub struct SessionManager {
+    keypair: Keypair,
+    events: Arc<dyn SessionEvents>,
+    padding_policy: Mutex<PaddingPolicy>,
+    cover_traffic_rate: watch::Sender<u32>,
+    sessions: Mutex<HashMap<[u8; PUBLIC_KEY_LENGTH], SessionHandle>>,
+    next_id: AtomicU64,
+}
+
+impl SessionManager {
+    pub fn new(keypair: Keypair, events: Arc<dyn SessionEvents>) -> Arc<SessionManager> {
+        Arc::new(SessionManager {
+            keypair,
+            events,
+            padding_policy: Mutex::new(PaddingPolicy::default()),
+            cover_traffic_rate: watch::channel(0).0,
+            sessions: Mutex::new(HashMap::new()),
+            next_id: AtomicU64::new(0),
+        })
+    }
+
+    pub fn public_key(&self) -> [u8; PUBLIC_KEY_LENGTH] {
+        self.keypair.public.to_bytes()
+    }
+
+    pub fn set_padding_policy(&self, padding_policy: PaddingPolicy) {
+        *self.padding_policy.lock().unwrap() = padding_policy;
+    }
+
+    //dummy records per hour sent on every session, 0 to disable. Running sessions pick up the new rate immediately.
+    pub fn set_cover_traffic_rate(&self, cover_traffic_rate: u32) {
+        self.cover_traffic_rate.send_replace(cover_traffic_rate);
+    }
+
+    //accept loop, meant to be spawned on the runtime
+    pub async fn serve(self: Arc<Self>, listener: TcpListener) -> io::Result<()> {
+        loop {
+            let (stream, addr) = listener.accept().await?;
+            let manager = self.clone();
+            tokio::spawn(async move {
+                if let Err(e) = manager.open_session(stream, addr.ip(), false).await {
+                    log::warn!("Incoming connection from {} failed: {}", addr, e);
+                }
+            });
+        }
+    }
+
+    pub async fn connect(self: &Arc<Self>, addr: SocketAddr) -> io::Result<[u8; PUBLIC_KEY_LENGTH]> {
+        let stream = TcpStream::connect(addr).await?;
+        self.clone().open_session(stream, addr.ip(), true).await
+    }
+
+    async fn open_session(self: Arc<Self>, mut stream: TcpStream, ip: IpAddr, outgoing: bool) -> io::Result<[u8; PUBLIC_KEY_LENGTH]> {
+        let channel = tokio::time::timeout(HANDSHAKE_TIMEOUT, handshake(&mut stream, &self.keypair)).await
+            .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "Handshake timed out"))??;
+        let peer_public_key = channel.peer_public_key;
+        if peer_public_key == self.public_key() {
+            return Err(invalid_data("Connected to ourself"));
+        }
+        if !self.events.accept(&peer_public_key) {
+            return Err(io::Error::new(io::ErrorKind::PermissionDenied, "Peer rejected"));
+        }
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let close = Arc::new(Notify::new());
+        {
+            let mut sessions = self.sessions.lock().unwrap();
+            if sessions.contains_key(&peer_public_key) {
+                return Err(io::Error::new(io::ErrorKind::AlreadyExists, "Session already opened"));
+            }
+            sessions.insert(peer_public_key, SessionHandle { id, ip, outgoing, sender });
+        }
+        self.events.on_session_opened(&peer_public_key, ip, outgoing);
+
+        let (mut reader, mut writer) = stream.into_split();
+        let SecureChannel { mut local_cipher, mut peer_cipher, .. } = channel;
+        let writer_close = close.clone();
+        let manager = self.clone();
+        tokio::spawn(async move {
+            let mut receiver = receiver;
+            let mut cover_traffic_rate = manager.cover_traffic_rate.subscribe();
+            loop {
+                //exponential delays are memoryless so drawing a new one after each record keeps the Poisson process
+                let delay = cover_traffic::next_delay(*cover_traffic_rate.borrow_and_update());
+                let padded = tokio::select! {
+                    padded = receiver.recv() => match padded {
+                        Some(padded) => padded,
+                        None => break,
+                    },
+                    _ = tokio::time::sleep(Duration::from_millis(delay.unwrap_or_default())), if delay.is_some() => {
+                        manager.padding_policy.lock().unwrap().pad(&protocol::dummy(&cover_traffic::generate_dummy_record()))
+                    }
+                    Ok(()) = cover_traffic_rate.changed() => continue,
+                };
+                if writer.write_all(&local_cipher.encrypt(&padded)).await.is_err() {
+                    break;
+                }
+            }
+            //queued records are flushed before the session ends
+            let _ = writer.shutdown().await;
+            writer_close.notify_one();
+        });
+        tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    record = read_record(&mut reader, &mut peer_cipher) => match record {
+                        Ok(Some(message)) => self.events.on_message(&peer_public_key, message),
+                        Ok(None) => break,
+                        Err(e) => {
+                            log::warn!("Closing session with {}: {}", ip, e);
+                            break;
+                        }
+                    },
+                    _ = close.notified() => break,
+                }
+            }
+            {
+                let mut sessions = self.sessions.lock().unwrap();
+                if sessions.get(&peer_public_key).map(|session| session.id) == Some(id) {
+                    sessions.remove(&peer_public_key);
+                }
+            }
+            self.events.on_session_closed(&peer_public_key);
+        });
+        Ok(peer_public_key)
+    }
+
+    pub fn send(&self, peer_public_key: &[u8; PUBLIC_KEY_LENGTH], message: &[u8]) -> bool {
+        let padded = self.padding_policy.lock().unwrap().pad(message);
+        match self.sessions.lock().unwrap().get(peer_public_key) {
+            Some(session) => session.sender.send(padded).is_ok(),
+            None => false,
+        }
+    }
+
+    //dropping the sender stops the writer, which then stops the reader
+    pub fn disconnect(&self, peer_public_key: &[u8; PUBLIC_KEY_LENGTH]) -> bool {
+        self.sessions.lock().unwrap().remove(peer_public_key).is_some()
+    }
+
+    pub fn is_online(&self, peer_public_key: &[u8; PUBLIC_KEY_LENGTH]) -> bool {
+        self.sessions.lock().unwrap().contains_key(peer_public_key)
+    }
+
+    pub fn sessions(&self) -> Vec<SessionInfo> {
+        self.sessions.lock().unwrap().iter().map(|(peer_public_key, session)| SessionInfo {
+            peer_public_key: *peer_public_key,
+            ip: session.ip,
+            outgoing: session.outgoing,
+        }).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+    use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+
+    #[derive(Debug, PartialEq)]
+    enum Event {
+        Opened([u8; PUBLIC_KEY_LENGTH], bool),
+        Message([u8; PUBLIC_KEY_LENGTH], Vec<u8>),
+        Closed([u8; PUBLIC_KEY_LENGTH]),
+    }
+
+    struct Recorder {
+        events: UnboundedSender<Event>,
+        rejected: Option<[u8; PUBLIC_KEY_LENGTH]>,
+    }
+
+    impl SessionEvents for Recorder {
+        fn accept(&self, peer_public_key: &[u8; PUBLIC_KEY_LENGTH]) -> bool {
+            self.rejected.as_ref() != Some(peer_public_key)
+        }
+        fn on_session_opened(&self, peer_public_key: &[u8; PUBLIC_KEY_LENGTH], _ip: IpAddr, outgoing: bool) {
+            self.events.send(Event::Opened(*peer_public_key, outgoing)).unwrap();
+        }
+        fn on_message(&self, peer_public_key: &[u8; PUBLIC_KEY_LENGTH], message: Vec<u8>) {
+            self.events.send(Event::Message(*peer_public_key, message)).unwrap();
+        }
+        fn on_session_closed(&self, peer_public_key: &[u8; PUBLIC_KEY_LENGTH]) {
+            self.events.send(Event::Closed(*peer_public_key)).unwrap();
+        }
+    }
+
+    fn new_node(rejected: Option<[u8; PUBLIC_KEY_LENGTH]>) -> (Arc<SessionManager>, UnboundedReceiver<Event>) {
+        let (events, receiver) = mpsc::unbounded_channel();
+        (SessionManager::new(Keypair::generate(&mut rand_7::rngs::OsRng), Arc::new(Recorder { events, rejected })), receiver)
+    }
+
+    async fn listen(manager: &Arc<SessionManager>) -> SocketAddr {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(manager.clone().serve(listener));
+        addr
+    }
+
+    async fn next_event(receiver: &mut UnboundedReceiver<Event>) -> Event {
+        tokio::time::timeout(Duration::from_secs(10), receiver.recv()).await.unwrap().unwrap()
+    }
+
+    //without dropping dummies
+    async fn read_raw_record(stream: &mut TcpStream, cipher: &mut RecordCipher) -> Vec<u8> {
+        let mut raw_len = [0; MESSAGE_LEN_LEN];
+        stream.read_exact(&mut raw_len).await.unwrap();
+        let mut cipher_text = vec![0; u32::from_be_bytes(raw_len) as usize];
+        stream.read_exact(&mut cipher_text).await.unwrap();
+        padding::unpad(&cipher.decrypt(&raw_len, &cipher_text).unwrap()).unwrap().to_vec()
+    }
+
+    #[test]
+    fn record_layer() {
+        let mut alice = RecordCipher::new(&[1; 16], [2; IV_LEN]);
+        let mut bob = RecordCipher::new(&[1; 16], [2; IV_LEN]);
+        for msg in [&b"first"[..], b"second"] {
+            let record = alice.encrypt(msg);
+            let raw_len: [u8; MESSAGE_LEN_LEN] = record[..MESSAGE_LEN_LEN].try_into().unwrap();
+            assert_eq!(u32::from_be_bytes(raw_len) as usize, record.len()-MESSAGE_LEN_LEN);
+            assert_eq!(bob.decrypt(&raw_len, &record[MESSAGE_LEN_LEN..]).unwrap(), msg);
+        }
+        //replayed or tampered records are rejected because counters moved on
+        let record = alice.encrypt(b"third");
+        let mut tampered = record.clone();
+        tampered[MESSAGE_LEN_LEN] ^= 1;
+        let raw_len: [u8; MESSAGE_LEN_LEN] = record[..MESSAGE_LEN_LEN].try_into().unwrap();
+        assert!(bob.decrypt(&raw_len, &tampered[MESSAGE_LEN_LEN..]).is_none());
+        assert!(bob.decrypt(&raw_len, &record[MESSAGE_LEN_LEN..]).is_none());
+    }
+
+    #[tokio::test]
+    async fn dummy_records_are_dropped() {
+        let mut alice = RecordCipher::new(&[1; 16], [2; IV_LEN]);
+        let mut bob = RecordCipher::new(&[1; 16], [2; IV_LEN]);
+        let padding_policy = PaddingPolicy::default();
+        let mut stream = Vec::new();
+        for message in [protocol::dummy(&cover_traffic::generate_dummy_record()), protocol::new_message("hello"), protocol::dummy(&[]), protocol::dummy(&[])] {
+            stream.extend(alice.encrypt(&padding_policy.pad(&message)));
+        }
+        let mut stream = stream.as_slice();
+        assert_eq!(read_record(&mut stream, &mut bob).await.unwrap().unwrap(), protocol::new_message("hello"));
+        assert_eq!(read_record(&mut stream, &mut bob).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn cover_traffic() {
+        let (alice, _alice_events) = new_node(None);
+        alice.set_cover_traffic_rate(36_000_000); //every 0.1 ms on average
+        let addr = listen(&alice).await;
+        //raw peer to see the dummy records
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let keypair = Keypair::generate(&mut rand_7::rngs::OsRng);
+        let mut channel = handshake(&mut stream, &keypair).await.unwrap();
+        for _ in 0..10 {
+            assert!(protocol::is_dummy(&read_raw_record(&mut stream, &mut channel.peer_cipher).await));
+        }
+
+        //a session manager peer only sees the real messages
+        let (bob, mut bob_events) = new_node(None);
+        bob.connect(addr).await.unwrap();
+        assert_eq!(next_event(&mut bob_events).await, Event::Opened(alice.public_key(), true));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(alice.send(&bob.public_key(), b"hello bob"));
+        assert_eq!(next_event(&mut bob_events).await, Event::Message(alice.public_key(), b"hello bob".to_vec()));
+
+        alice.set_cover_traffic_rate(0);
+        assert!(alice.send(keypair.public.as_bytes(), b"last"));
+        while read_raw_record(&mut stream, &mut channel.peer_cipher).await != b"last" {}
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(alice.send(keypair.public.as_bytes(), b"final"));
+        assert_eq!(read_raw_record(&mut stream, &mut channel.peer_cipher).await, b"final");
+    }
+
+    #[tokio::test]
+    async fn sessions_over_loopback() {
+        let (alice, mut alice_events) = new_node(None);
+        let (bob, mut bob_events) = new_node(None);
+        let addr = listen(&alice).await;
+
+        assert_eq!(bob.connect(addr).await.unwrap(), alice.public_key());
+        assert_eq!(next_event(&mut bob_events).await, Event::Opened(alice.public_key(), true));
+        assert_eq!(next_event(&mut alice_events).await, Event::Opened(bob.public_key(), false));
+        assert!(alice.is_online(&bob.public_key()));
+        assert_eq!(bob.sessions().len(), 1);
+
+        assert!(bob.send(&alice.public_key(), b"hello alice"));
+        assert_eq!(next_event(&mut alice_events).await, Event::Message(bob.public_key(), b"hello alice".to_vec()));
+        alice.set_padding_policy(PaddingPolicy::None);
+        let big = vec![0x42; 1_000_000];
+        assert!(alice.send(&bob.public_key(), &big));
+        assert!(alice.send(&bob.public_key(), b""));
+        assert_eq!(next_event(&mut bob_events).await, Event::Message(alice.public_key(), big));
+        assert_eq!(next_event(&mut bob_events).await, Event::Message(alice.public_key(), Vec::new()));
+
+        //a second session with the same peer is refused
+        assert_eq!(bob.connect(addr).await.unwrap_err().kind(), io::ErrorKind::AlreadyExists);
+
+        assert!(bob.disconnect(&alice.public_key()));
+        assert_eq!(next_event(&mut bob_events).await, Event::Closed(alice.public_key()));
+        assert_eq!(next_event(&mut alice_events).await, Event::Closed(bob.public_key()));
+        assert!(!alice.is_online(&bob.public_key()));
+        assert!(!bob.send(&alice.public_key(), b"too late"));
+    }
+
+    #[tokio::test]
+    async fn rejected_peers() {
+        let (bob, mut bob_events) = new_node(None);
+        let (alice, _alice_events) = new_node(Some(bob.public_key()));
+        let addr = listen(&alice).await;
+        //the handshake completes on bob's side before alice drops him
+        bob.connect(addr).await.unwrap();
+        assert_eq!(next_event(&mut bob_events).await, Event::Opened(alice.public_key(), true));
+        assert_eq!(next_event(&mut bob_events).await, Event::Closed(alice.public_key()));
+        assert!(!alice.is_online(&bob.public_key()));
+
+        //connecting to ourself
+        assert_eq!(alice.connect(addr).await.unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+        //garbage instead of a handshake
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(&[0; RANDOM_LEN+EPHEMERAL_KEY_LEN]).await.unwrap();
+        let mut buffer = Vec::new();
+        let _ = stream.write_all(&[0; RANDOM_LEN+PUBLIC_KEY_LENGTH+SIGNATURE_LENGTH+AES_TAG_LEN]).await;
+        let _ = stream.read_to_end(&mut buffer).await;
+        assert!(buffer.len() < RANDOM_LEN+EPHEMERAL_KEY_LEN+RANDOM_LEN+PUBLIC_KEY_LENGTH+SIGNATURE_LENGTH+AES_TAG_LEN+HASH_OUTPUT_LEN);
+        assert!(alice.sessions().is_empty());
+    }
+}
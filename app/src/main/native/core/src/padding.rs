@@ -0,0 +1,174 @@
+use std::convert::TryInto;
+use rand::{Rng, RngCore, rngs::OsRng};
+
+pub const MESSAGE_LEN_LEN: usize = 4;
+pub const PADDING_POLICY_LEN: usize = 9;
+pub const PADDED_MAX_SIZE: usize = 16384000; //largest record accepted by AIRA peers
+const MIN_BUCKET_SIZE: usize = 1000; //buckets of 1000*2^k like the desktop AIRA, the largest one being PADDED_MAX_SIZE
+pub const DEFAULT_CELL_SIZE: u32 = 4096;
+pub const DEFAULT_RANDOM_MAX: u32 = 4096;
+
+//padded format: message length (u32 BE) || message || random bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PaddingPolicy {
+    None,
+    #[default]
+    PowerOfTwo,
+    FixedCells(u32),
+    Random { min: u32, max: u32 },
+}
+
+impl PaddingPolicy {
+    //the policies selectable from the UI, identified by their tag
+    pub fn from_tag(tag: u8) -> Option<PaddingPolicy> {
+        match tag {
+            0 => Some(PaddingPolicy::None),
+            1 => Some(PaddingPolicy::PowerOfTwo),
+            2 => Some(PaddingPolicy::FixedCells(DEFAULT_CELL_SIZE)),
+            3 => Some(PaddingPolicy::Random { min: 0, max: DEFAULT_RANDOM_MAX }),
+            _ => None
+        }
+    }
+
+    pub fn tag(&self) -> u8 {
+        match self {
+            PaddingPolicy::None => 0,
+            PaddingPolicy::PowerOfTwo => 1,
+            PaddingPolicy::FixedCells(_) => 2,
+            PaddingPolicy::Random { .. } => 3,
+        }
+    }
+
+    pub fn to_bytes(&self) -> [u8; PADDING_POLICY_LEN] {
+        let (first, second) = match *self {
+            PaddingPolicy::None | PaddingPolicy::PowerOfTwo => (0, 0),
+            PaddingPolicy::FixedCells(cell_size) => (cell_size, 0),
+            PaddingPolicy::Random { min, max } => (min, max),
+        };
+        let mut bytes = [0; PADDING_POLICY_LEN];
+        bytes[0] = self.tag();
+        bytes[1..5].copy_from_slice(&first.to_be_bytes());
+        bytes[5..].copy_from_slice(&second.to_be_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<PaddingPolicy> {
+        if bytes.len() != PADDING_POLICY_LEN {
+            return None;
+        }
+        let first = u32::from_be_bytes(bytes[1..5].try_into().unwrap());
+        let second = u32::from_be_bytes(bytes[5..].try_into().unwrap());
+        match bytes[0] {
+            0 => Some(PaddingPolicy::None),
+            1 => Some(PaddingPolicy::PowerOfTwo),
+            2 if first > 0 => Some(PaddingPolicy::FixedCells(first)),
+            3 if first <= second => Some(PaddingPolicy::Random { min: first, max: second }),
+            _ => None
+        }
+    }
+
+    //never more than what peers accept, messages already larger than that are left unpadded
+    fn padded_len(&self, msg_len: usize) -> usize {
+        let padded_len = match *self {
+            PaddingPolicy::None => msg_len,
+            PaddingPolicy::PowerOfTwo => msg_len.div_ceil(MIN_BUCKET_SIZE).next_power_of_two() * MIN_BUCKET_SIZE,
+            PaddingPolicy::FixedCells(cell_size) => {
+                let cell_size = cell_size as usize;
+                msg_len.max(1).div_ceil(cell_size) * cell_size
+            }
+            PaddingPolicy::Random { min, max } => msg_len + OsRng.gen_range(min as usize..=max as usize),
+        };
+        padded_len.min(PADDED_MAX_SIZE).max(msg_len)
+    }
+
+    pub fn pad(&self, input: &[u8]) -> Vec<u8> {
+        let msg_len = MESSAGE_LEN_LEN + input.len();
+        let mut padded = vec![0; self.padded_len(msg_len)];
+        padded[..MESSAGE_LEN_LEN].copy_from_slice(&(input.len() as u32).to_be_bytes());
+        padded[MESSAGE_LEN_LEN..msg_len].copy_from_slice(input);
+        OsRng.fill_bytes(&mut padded[msg_len..]);
+        padded
+    }
+}
+
+pub fn unpad(input: &[u8]) -> Option<&[u8]> {
+    if input.len() < MESSAGE_LEN_LEN {
+        return None;
+    }
+    let msg_len = u32::from_be_bytes(input[..MESSAGE_LEN_LEN].try_into().unwrap()) as usize;
+    input.get(MESSAGE_LEN_LEN..MESSAGE_LEN_LEN+msg_len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{crypto, protocol::FILE_SIZE_LIMIT};
+
+    fn ciphertext_len(policy: PaddingPolicy, msg_len: usize) -> usize {
+        crypto::encrypt_data(&policy.pad(&vec![0x41; msg_len]), &[0; crypto::MASTER_KEY_LEN]).unwrap().len()
+    }
+
+    #[test]
+    fn ciphertext_lengths_within_a_bucket() {
+        let policy = PaddingPolicy::PowerOfTwo;
+        let len = ciphertext_len(policy, 0);
+        for msg_len in [1, 10, 500, 1000-MESSAGE_LEN_LEN] {
+            assert_eq!(ciphertext_len(policy, msg_len), len);
+        }
+        let len = ciphertext_len(policy, 1000-MESSAGE_LEN_LEN+1);
+        for msg_len in [1500, 1900, 2000-MESSAGE_LEN_LEN] {
+            assert_eq!(ciphertext_len(policy, msg_len), len);
+        }
+        assert_ne!(ciphertext_len(policy, 2000-MESSAGE_LEN_LEN+1), len);
+
+        let policy = PaddingPolicy::FixedCells(DEFAULT_CELL_SIZE);
+        let len = ciphertext_len(policy, 0);
+        for msg_len in [1, 100, 4000, DEFAULT_CELL_SIZE as usize-MESSAGE_LEN_LEN] {
+            assert_eq!(ciphertext_len(policy, msg_len), len);
+        }
+        assert_eq!(ciphertext_len(policy, 5000), ciphertext_len(policy, 8000));
+        assert_ne!(ciphertext_len(policy, 5000), len);
+    }
+
+    #[test]
+    fn largest_files_stay_within_the_receive_limit() {
+        let file = vec![0; FILE_SIZE_LIMIT as usize];
+        for policy in [
+            PaddingPolicy::None,
+            PaddingPolicy::PowerOfTwo,
+            PaddingPolicy::FixedCells(DEFAULT_CELL_SIZE),
+            PaddingPolicy::FixedCells(10_000_000),
+            PaddingPolicy::Random { min: 0, max: DEFAULT_RANDOM_MAX },
+            PaddingPolicy::Random { min: 1_000_000, max: u32::MAX },
+        ] {
+            let padded = policy.pad(&file);
+            assert!(padded.len() <= PADDED_MAX_SIZE);
+            assert_eq!(unpad(&padded).unwrap().len(), file.len());
+        }
+        assert_eq!(PaddingPolicy::PowerOfTwo.pad(&file).len(), PADDED_MAX_SIZE);
+        //too large to be received anyway
+        assert_eq!(PaddingPolicy::PowerOfTwo.pad(&vec![0; PADDED_MAX_SIZE]).len(), MESSAGE_LEN_LEN+PADDED_MAX_SIZE);
+    }
+
+    #[test]
+    fn random_padding_within_bounds() {
+        let policy = PaddingPolicy::Random { min: 16, max: 64 };
+        for _ in 0..100 {
+            let padded = policy.pad(b"hello");
+            let extra = padded.len() - MESSAGE_LEN_LEN - 5;
+            assert!((16..=64).contains(&extra));
+        }
+    }
+
+    #[test]
+    fn pad_unpad() {
+        let msg = b"some message";
+        for policy in [PaddingPolicy::None, PaddingPolicy::PowerOfTwo, PaddingPolicy::FixedCells(100), PaddingPolicy::Random { min: 0, max: 10 }] {
+            assert_eq!(unpad(&policy.pad(msg)).unwrap(), msg);
+            assert_eq!(PaddingPolicy::from_bytes(&policy.to_bytes()), Some(policy));
+        }
+        assert_eq!(PaddingPolicy::None.pad(msg).len(), MESSAGE_LEN_LEN+msg.len());
+        assert!(unpad(&[0, 0, 1, 0, 1]).is_none());
+        assert!(PaddingPolicy::from_bytes(&PaddingPolicy::FixedCells(0).to_bytes()).is_none());
+    }
+}
@@ -0,0 +1,107 @@
+use std::convert::TryInto;
+
+//application messages carried by PSEC records, see Protocol.kt
+pub const MESSAGE: u8 = 0x00;
+pub const FILE: u8 = 0x01;
+pub const ASK_PROFILE_INFO: u8 = 0x02;
+pub const NAME: u8 = 0x03;
+pub const AVATAR: u8 = 0x04;
+pub const REMOVE_AVATAR: u8 = 0x05;
+pub const ASK_LARGE_FILES: u8 = 0x06;
+pub const ACCEPT_LARGE_FILES: u8 = 0x07;
+pub const LARGE_FILE_CHUNK: u8 = 0x08;
+pub const ACK_CHUNK: u8 = 0x09;
+pub const ABORT_FILES_TRANSFER: u8 = 0x0a;
+pub const KEY_ROTATIONS: u8 = 0x0b;
+pub const GROUP_INFO: u8 = 0x0c;
+pub const GROUP_MESSAGE: u8 = 0x0d;
+pub const DUMMY: u8 = 0x0e;
+
+pub const FILE_SIZE_LIMIT: u64 = 16380000; //larger files use chunked transfers
+pub const FILE_CHUNK_SIZE: usize = 1023996; //including the LARGE_FILE_CHUNK byte
+
+pub fn new_message(msg: &str) -> Vec<u8> {
+    [&[MESSAGE], msg.as_bytes()].concat()
+}
+
+pub fn name(name: &str) -> Vec<u8> {
+    [&[NAME], name.as_bytes()].concat()
+}
+
+pub fn new_file(file_name: &str, content: &[u8]) -> Vec<u8> {
+    [&[FILE], &(file_name.len() as u16).to_be_bytes()[..], file_name.as_bytes(), content].concat()
+}
+
+pub fn ask_large_files(files: &[(String, u64)]) -> Vec<u8> {
+    let mut buffer = vec![ASK_LARGE_FILES];
+    for (file_name, file_size) in files {
+        buffer.extend_from_slice(&file_size.to_be_bytes());
+        buffer.extend_from_slice(&(file_name.len() as u16).to_be_bytes());
+        buffer.extend_from_slice(file_name.as_bytes());
+    }
+    buffer
+}
+
+pub fn large_file_chunk(chunk: &[u8]) -> Vec<u8> {
+    [&[LARGE_FILE_CHUNK], chunk].concat()
+}
+
+pub fn dummy(record: &[u8]) -> Vec<u8> {
+    [&[DUMMY], record].concat()
+}
+
+pub fn is_dummy(message: &[u8]) -> bool {
+    message.first() == Some(&DUMMY)
+}
+
+//(file name, file content)
+pub fn parse_small_file(buffer: &[u8]) -> Option<(&[u8], &[u8])> {
+    if buffer.len() > 3 {
+        let file_name_len = u16::from_be_bytes(buffer[1..3].try_into().unwrap()) as usize;
+        if buffer.len() > 3+file_name_len {
+            return Some((&buffer[3..3+file_name_len], &buffer[3+file_name_len..]));
+        }
+    }
+    None
+}
+
+//(file name, file size)
+pub fn parse_ask_files(buffer: &[u8]) -> Option<Vec<(String, u64)>> {
+    let mut files = Vec::new();
+    let mut n = 1;
+    while n < buffer.len() {
+        if buffer.len() <= n+10 {
+            return None;
+        }
+        let file_size = u64::from_be_bytes(buffer[n..n+8].try_into().unwrap());
+        let file_name_len = u16::from_be_bytes(buffer[n+8..n+10].try_into().unwrap()) as usize;
+        let file_name = buffer.get(n+10..n+10+file_name_len)?;
+        files.push((String::from_utf8_lossy(file_name).into_owned(), file_size));
+        n += 10+file_name_len;
+    }
+    Some(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn files_parsing() {
+        let files = vec![("a.txt".to_owned(), 20000000), ("b.bin".to_owned(), 1)];
+        let buffer = ask_large_files(&files);
+        assert_eq!(parse_ask_files(&buffer).unwrap(), files);
+        assert!(parse_ask_files(&buffer[..buffer.len()-1]).is_none());
+        let buffer = new_file("c.txt", b"content");
+        assert_eq!(parse_small_file(&buffer).unwrap(), (&b"c.txt"[..], &b"content"[..]));
+        assert!(parse_small_file(&buffer[..8]).is_none());
+    }
+
+    #[test]
+    fn dummies() {
+        assert!(is_dummy(&dummy(b"random")));
+        assert!(is_dummy(&[DUMMY]));
+        assert!(!is_dummy(&new_message("random")));
+        assert!(!is_dummy(&[]));
+    }
+}
@@ -0,0 +1,620 @@
+use std::{cell::{Cell, RefCell}, collections::{BTreeMap, HashMap}, fs::OpenOptions, io::Write, path::Path};
+use rand::{RngCore, rngs::OsRng};
+use rusqlite::{Connection, Error, params_from_iter};
+use zeroize::Zeroize;
+use crate::{crypto, key_value_table::KeyValueTable, print_error};
+
+const DB_NAME: &str = "AIRA.db";
+const MAX_VARIABLES: usize = 999; //lowest SQLITE_MAX_VARIABLE_NUMBER
+const ENCRYPTED_DB_NAME: &str = "AIRA-encrypted.db";
+pub const MAIN_TABLE: &str = "main";
+
+pub struct Table {
+    pub name: &'static str,
+    pub schema: &'static str, //SQL column definitions
+    pub index: Option<&'static str>, //column looked up with find
+}
+
+//values of the requested columns, NULL columns being None
+pub type Row = Vec<Option<Vec<u8>>>;
+
+pub trait Storage {
+    //called once the master key is known, before any table is accessed. None to keep the data in plaintext.
+    fn unlock(&mut self, _database_key: Option<&[u8]>) {}
+    fn has_encrypted_database(&self) -> bool {
+        false
+    }
+    //the main table holds the identity and its settings
+    fn get_value(&self, key: &str) -> Result<Option<Vec<u8>>, Error>;
+    fn set_value(&self, key: &str, value: &[u8]) -> Result<usize, Error>;
+    fn remove_value(&self, key: &str) -> Result<usize, Error>;
+    //rows get an id increasing with insertion order. Columns not given are NULL.
+    fn insert(&self, table: &Table, values: &[(&str, &[u8])]) -> Result<i64, Error>;
+    //all the rows have the given columns and are inserted in a single transaction
+    fn insert_many(&self, table: &Table, columns: &[&str], rows: &[Vec<&[u8]>]) -> Result<Vec<i64>, Error> {
+        self.transaction(|| rows.iter().map(|row| {
+            self.insert(table, &columns.iter().copied().zip(row.iter().copied()).collect::<Vec<_>>())
+        }).collect())
+    }
+    //all rows ordered by id
+    fn select(&self, table: &Table, columns: &[&str]) -> Result<Vec<(i64, Row)>, Error>;
+    fn get(&self, table: &Table, id: i64, columns: &[&str]) -> Result<Option<Row>, Error>;
+    //rows that exist among ids, in the order of ids
+    fn get_many(&self, table: &Table, ids: &[i64], columns: &[&str]) -> Result<Vec<(i64, Row)>, Error>;
+    //ids of the rows whose column holds exactly value, ordered by id
+    fn find(&self, table: &Table, column: &str, value: &[u8]) -> Result<Vec<i64>, Error>;
+    fn update(&self, table: &Table, id: i64, column: &str, value: Option<&[u8]>) -> Result<usize, Error>;
+    fn delete(&self, table: &Table, id: i64) -> Result<usize, Error>;
+    //either all the operations made by f are applied or none of them
+    fn transaction<T, F: FnOnce() -> Result<T, Error>>(&self, f: F) -> Result<T, Error>;
+    //make deleted data unrecoverable. Rewrites the whole database, so it is kept for explicit removals: routine deletes rely on secure_delete
+    fn scrub(&self) -> Result<(), Error>;
+    fn destroy(&self) -> Result<(), std::io::Error>;
+}
+
+pub fn get_database_path(database_folder: &str) -> String {
+    Path::new(database_folder).join(DB_NAME).to_str().unwrap().to_owned()
+}
+
+pub fn get_encrypted_database_path(database_folder: &str) -> String {
+    Path::new(database_folder).join(ENCRYPTED_DB_NAME).to_str().unwrap().to_owned()
+}
+
+//SQLCipher raw key syntax
+fn to_sqlcipher_key(database_key: &[u8]) -> String {
+    format!("x'{}'", hex::encode(database_key))
+}
+
+pub fn get_sqlcipher_key(master_key: &[u8]) -> String {
+    let mut database_key = crypto::derive_database_key(master_key);
+    let key = to_sqlcipher_key(&database_key);
+    database_key.zeroize();
+    key
+}
+
+//deleted content is overwritten with zeros instead of being left in free pages
+fn configure_database(db: &Connection, journal_mode: &str) -> Result<(), Error> {
+    db.pragma_update(None, "secure_delete", true)?;
+    db.pragma_update(None, "journal_mode", journal_mode)
+}
+
+fn open_database(db_path: &str) -> Result<Connection, Error> {
+    let db = Connection::open(db_path)?;
+    configure_database(&db, "WAL")?;
+    Ok(db)
+}
+
+//the plaintext database is attached to the encrypted one so that a single COMMIT covers both files.
+//SQLite only commits attached databases atomically with a rollback journal, not in WAL mode.
+fn open_encrypted_database(database_folder: &str, sqlcipher_key: &str) -> Result<Connection, Error> {
+    let db = Connection::open(get_encrypted_database_path(database_folder))?;
+    db.execute_batch(&format!("PRAGMA key = \"{}\";", sqlcipher_key))?; //must come before any other statement
+    db.execute("ATTACH DATABASE ?1 AS plaintext KEY ''", [get_database_path(database_folder)])?;
+    configure_database(&db, "DELETE")?; //applies to both databases
+    Ok(db)
+}
+
+pub fn table_exists(db: &Connection, table_name: &str) -> Result<bool, Error> {
+    let mut stmt = db.prepare("SELECT name FROM sqlite_master WHERE type='table' AND name=?")?;
+    let mut rows = stmt.query([table_name])?;
+    Ok(rows.next()?.is_some())
+}
+
+pub fn column_exists(db: &Connection, table_name: &str, column_name: &str) -> Result<bool, Error> {
+    let mut stmt = db.prepare(&format!("PRAGMA table_info({})", table_name))?;
+    let column_names = stmt.query_map([], |row| row.get::<_, String>(1))?.collect::<Result<Vec<String>, _>>()?;
+    Ok(column_names.iter().any(|name| name == column_name))
+}
+
+pub fn create_table(db: &Connection, table: &Table) -> Result<usize, Error> {
+    let result = db.execute(&format!("CREATE TABLE IF NOT EXISTS \"{}\" ({})", table.name, table.schema), [])?;
+    if let Some(column) = table.index {
+        db.execute(&format!("CREATE INDEX IF NOT EXISTS \"{}_{}\" ON \"{}\" ({})", table.name, column, table.name, column), [])?;
+    }
+    Ok(result)
+}
+
+//the main table always stays in the plaintext database. When the database is encrypted, the other tables are in a separate SQLCipher database.
+//the connection is opened on first use and kept until the storage is unlocked again or dropped
+pub struct SqliteStorage {
+    pub database_folder: String,
+    sqlcipher_key: Option<String>,
+    db: RefCell<Option<Connection>>,
+    in_transaction: Cell<bool>,
+    scrub_pending: Cell<bool>,
+}
+
+impl SqliteStorage {
+    pub fn new(database_folder: String) -> SqliteStorage {
+        SqliteStorage {
+            database_folder,
+            sqlcipher_key: None,
+            db: RefCell::new(None),
+            in_transaction: Cell::new(false),
+            scrub_pending: Cell::new(false),
+        }
+    }
+
+    pub fn get_database_path(&self) -> String {
+        get_database_path(&self.database_folder)
+    }
+
+    //data tables are in the main schema. When encrypted, the plaintext database is attached as "plaintext".
+    pub fn with_database<T, F: FnOnce(&Connection) -> Result<T, Error>>(&self, f: F) -> Result<T, Error> {
+        if self.db.borrow().is_none() {
+            *self.db.borrow_mut() = Some(match &self.sqlcipher_key {
+                Some(key) => open_encrypted_database(&self.database_folder, key)?,
+                None => open_database(&self.get_database_path())?
+            });
+        }
+        f(self.db.borrow().as_ref().unwrap())
+    }
+
+    fn main_table(&self) -> &'static str {
+        if self.sqlcipher_key.is_some() {
+            "plaintext.main"
+        } else {
+            MAIN_TABLE
+        }
+    }
+
+    fn close(&self) {
+        self.db.borrow_mut().take();
+    }
+
+    //done once when the identity is opened or created, after the database is unlocked: the other operations expect the tables to exist
+    pub fn create_tables(&self, tables: &[&Table]) -> Result<(), Error> {
+        self.transaction(|| self.with_database(|db| {
+            for table in tables {
+                create_table(db, table)?;
+            }
+            Ok(())
+        }))
+    }
+
+    fn rollback(&self) {
+        self.scrub_pending.set(false);
+        if let Err(e) = self.with_database(|db| db.execute_batch("ROLLBACK")) {
+            print_error!(e);
+        }
+    }
+}
+
+//resets the transaction state even if f panics, rolling back what it did
+struct TransactionGuard<'a>(&'a SqliteStorage);
+
+impl<'a> Drop for TransactionGuard<'a> {
+    fn drop(&mut self) {
+        self.0.in_transaction.set(false);
+        if std::thread::panicking() {
+            self.0.rollback();
+        }
+    }
+}
+
+impl Drop for SqliteStorage {
+    fn drop(&mut self) {
+        self.sqlcipher_key.zeroize();
+    }
+}
+
+impl Storage for SqliteStorage {
+    fn unlock(&mut self, database_key: Option<&[u8]>) {
+        self.close();
+        self.sqlcipher_key.zeroize();
+        self.sqlcipher_key = database_key.map(to_sqlcipher_key);
+    }
+
+    fn has_encrypted_database(&self) -> bool {
+        Path::new(&get_encrypted_database_path(&self.database_folder)).exists()
+    }
+
+    fn get_value(&self, key: &str) -> Result<Option<Vec<u8>>, Error> {
+        self.with_database(|db| match KeyValueTable::new(db, self.main_table())?.get(key) {
+            Ok(value) => Ok(Some(value)),
+            Err(Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(e)
+        })
+    }
+
+    fn set_value(&self, key: &str, value: &[u8]) -> Result<usize, Error> {
+        self.with_database(|db| KeyValueTable::new(db, self.main_table())?.upsert(key, value))
+    }
+
+    fn remove_value(&self, key: &str) -> Result<usize, Error> {
+        self.with_database(|db| KeyValueTable::new(db, self.main_table())?.del(key))
+    }
+
+    fn insert(&self, table: &Table, values: &[(&str, &[u8])]) -> Result<i64, Error> {
+        self.with_database(|db| {
+            let columns = values.iter().map(|(column, _)| *column).collect::<Vec<&str>>().join(", ");
+            let placeholders = vec!["?"; values.len()].join(", ");
+            db.execute(&format!("INSERT INTO \"{}\" ({}) VALUES ({})", table.name, columns, placeholders), params_from_iter(values.iter().map(|(_, value)| value)))?;
+            Ok(db.last_insert_rowid())
+        })
+    }
+
+    fn insert_many(&self, table: &Table, columns: &[&str], rows: &[Vec<&[u8]>]) -> Result<Vec<i64>, Error> {
+        self.transaction(|| self.with_database(|db| {
+            let placeholders = vec!["?"; columns.len()].join(", ");
+            let mut stmt = db.prepare_cached(&format!("INSERT INTO \"{}\" ({}) VALUES ({})", table.name, columns.join(", "), placeholders))?;
+            rows.iter().map(|row| stmt.insert(params_from_iter(row.iter()))).collect()
+        }))
+    }
+
+    fn select(&self, table: &Table, columns: &[&str]) -> Result<Vec<(i64, Row)>, Error> {
+        self.with_database(|db| {
+            let mut stmt = db.prepare_cached(&format!("SELECT rowid, {} FROM \"{}\" ORDER BY rowid", columns.join(", "), table.name))?;
+            let rows = stmt.query_map([], |row| {
+                Ok((row.get(0)?, (1..=columns.len()).map(|i| row.get(i)).collect::<Result<Row, _>>()?))
+            })?.collect();
+            rows
+        })
+    }
+
+    fn get(&self, table: &Table, id: i64, columns: &[&str]) -> Result<Option<Row>, Error> {
+        self.with_database(|db| {
+            let mut stmt = db.prepare_cached(&format!("SELECT {} FROM \"{}\" WHERE rowid=?", columns.join(", "), table.name))?;
+            let mut rows = stmt.query([id])?;
+            match rows.next()? {
+                Some(row) => Ok(Some((0..columns.len()).map(|i| row.get(i)).collect::<Result<Row, _>>()?)),
+                None => Ok(None)
+            }
+        })
+    }
+
+    fn get_many(&self, table: &Table, ids: &[i64], columns: &[&str]) -> Result<Vec<(i64, Row)>, Error> {
+        self.with_database(|db| {
+            let mut rows = HashMap::with_capacity(ids.len());
+            for chunk in ids.chunks(MAX_VARIABLES) {
+                let placeholders = vec!["?"; chunk.len()].join(", ");
+                let mut stmt = db.prepare_cached(&format!("SELECT rowid, {} FROM \"{}\" WHERE rowid IN ({})", columns.join(", "), table.name, placeholders))?;
+                for row in stmt.query_map(params_from_iter(chunk), |row| {
+                    Ok((row.get(0)?, (1..=columns.len()).map(|i| row.get(i)).collect::<Result<Row, _>>()?))
+                })? {
+                    let (id, row): (i64, Row) = row?;
+                    rows.insert(id, row);
+                }
+            }
+            Ok(ids.iter().filter_map(|id| Some((*id, rows.remove(id)?))).collect())
+        })
+    }
+
+    fn find(&self, table: &Table, column: &str, value: &[u8]) -> Result<Vec<i64>, Error> {
+        self.with_database(|db| {
+            let mut stmt = db.prepare_cached(&format!("SELECT rowid FROM \"{}\" WHERE {}=? ORDER BY rowid", table.name, column))?;
+            let ids = stmt.query_map([value], |row| row.get(0))?.collect();
+            ids
+        })
+    }
+
+    fn update(&self, table: &Table, id: i64, column: &str, value: Option<&[u8]>) -> Result<usize, Error> {
+        self.with_database(|db| {
+            db.execute(&format!("UPDATE \"{}\" SET {}=?1 WHERE rowid=?2", table.name, column), rusqlite::params![value, id])
+        })
+    }
+
+    fn delete(&self, table: &Table, id: i64) -> Result<usize, Error> {
+        self.with_database(|db| {
+            db.execute(&format!("DELETE FROM \"{}\" WHERE rowid=?", table.name), [id])
+        })
+    }
+
+    fn transaction<T, F: FnOnce() -> Result<T, Error>>(&self, f: F) -> Result<T, Error> {
+        if self.in_transaction.get() { //nested
+            return f();
+        }
+        self.with_database(|db| db.execute_batch("BEGIN IMMEDIATE"))?;
+        self.in_transaction.set(true);
+        let guard = TransactionGuard(self);
+        let result = f();
+        drop(guard);
+        //a failed COMMIT (busy database, full disk...) leaves the transaction open: it must be rolled back too
+        match result.and_then(|value| self.with_database(|db| db.execute_batch("COMMIT")).map(|_| value)) {
+            Ok(value) => {
+                if self.scrub_pending.replace(false) {
+                    self.scrub()?;
+                }
+                Ok(value)
+            }
+            Err(e) => {
+                self.rollback();
+                Err(e)
+            }
+        }
+    }
+
+    //secure_delete doesn't cover the WAL nor the free pages already on disk. VACUUM can't run inside a transaction so it is delayed until the commit.
+    fn scrub(&self) -> Result<(), Error> {
+        if self.in_transaction.get() {
+            self.scrub_pending.set(true);
+            return Ok(());
+        }
+        self.with_database(|db| {
+            db.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))?;
+            db.execute("VACUUM", [])?;
+            Ok(())
+        })
+    }
+
+    //overwrite every database file before unlinking it
+    fn destroy(&self) -> Result<(), std::io::Error> {
+        self.close();
+        for db_name in [DB_NAME, ENCRYPTED_DB_NAME] {
+            for suffix in ["", "-journal", "-wal", "-shm"] {
+                let path = Path::new(&self.database_folder).join(db_name.to_owned()+suffix);
+                if path.exists() {
+                    let mut file = OpenOptions::new().write(true).open(&path)?;
+                    let mut random = vec![0; file.metadata()?.len() as usize];
+                    OsRng.fill_bytes(&mut random);
+                    file.write_all(&random)?;
+                    file.sync_all()?;
+                    std::fs::remove_file(&path)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+type MemoryTable = BTreeMap<i64, HashMap<String, Vec<u8>>>;
+
+//keeps everything in RAM. Used to test the identity logic without touching the disk.
+#[derive(Default)]
+pub struct MemoryStorage {
+    values: RefCell<HashMap<String, Vec<u8>>>,
+    tables: RefCell<HashMap<&'static str, MemoryTable>>,
+    last_id: Cell<i64>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> MemoryStorage {
+        MemoryStorage::default()
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn get_value(&self, key: &str) -> Result<Option<Vec<u8>>, Error> {
+        Ok(self.values.borrow().get(key).cloned())
+    }
+
+    fn set_value(&self, key: &str, value: &[u8]) -> Result<usize, Error> {
+        self.values.borrow_mut().insert(key.to_owned(), value.to_vec());
+        Ok(1)
+    }
+
+    fn remove_value(&self, key: &str) -> Result<usize, Error> {
+        Ok(self.values.borrow_mut().remove(key).map_or(0, |_| 1))
+    }
+
+    fn insert(&self, table: &Table, values: &[(&str, &[u8])]) -> Result<i64, Error> {
+        let id = self.last_id.get()+1;
+        self.last_id.set(id);
+        let row = values.iter().map(|(column, value)| (column.to_string(), value.to_vec())).collect();
+        self.tables.borrow_mut().entry(table.name).or_default().insert(id, row);
+        Ok(id)
+    }
+
+    fn select(&self, table: &Table, columns: &[&str]) -> Result<Vec<(i64, Row)>, Error> {
+        Ok(self.tables.borrow().get(table.name).map(|rows| rows.iter().map(|(id, row)| {
+            (*id, columns.iter().map(|column| row.get(*column).cloned()).collect())
+        }).collect()).unwrap_or_default())
+    }
+
+    fn get(&self, table: &Table, id: i64, columns: &[&str]) -> Result<Option<Row>, Error> {
+        Ok(self.tables.borrow().get(table.name).and_then(|rows| rows.get(&id)).map(|row| {
+            columns.iter().map(|column| row.get(*column).cloned()).collect()
+        }))
+    }
+
+    fn get_many(&self, table: &Table, ids: &[i64], columns: &[&str]) -> Result<Vec<(i64, Row)>, Error> {
+        let tables = self.tables.borrow();
+        Ok(ids.iter().filter_map(|id| {
+            let row = tables.get(table.name)?.get(id)?;
+            Some((*id, columns.iter().map(|column| row.get(*column).cloned()).collect()))
+        }).collect())
+    }
+
+    fn find(&self, table: &Table, column: &str, value: &[u8]) -> Result<Vec<i64>, Error> {
+        Ok(self.tables.borrow().get(table.name).map(|rows| rows.iter().filter(|(_, row)| {
+            row.get(column).map(Vec::as_slice) == Some(value)
+        }).map(|(id, _)| *id).collect()).unwrap_or_default())
+    }
+
+    fn update(&self, table: &Table, id: i64, column: &str, value: Option<&[u8]>) -> Result<usize, Error> {
+        match self.tables.borrow_mut().get_mut(table.name).and_then(|rows| rows.get_mut(&id)) {
+            Some(row) => {
+                match value {
+                    Some(value) => row.insert(column.to_owned(), value.to_vec()),
+                    None => row.remove(column)
+                };
+                Ok(1)
+            }
+            None => Ok(0)
+        }
+    }
+
+    fn delete(&self, table: &Table, id: i64) -> Result<usize, Error> {
+        Ok(self.tables.borrow_mut().get_mut(table.name).and_then(|rows| rows.remove(&id)).map_or(0, |_| 1))
+    }
+
+    fn transaction<T, F: FnOnce() -> Result<T, Error>>(&self, f: F) -> Result<T, Error> {
+        let values = self.values.borrow().clone();
+        let tables = self.tables.borrow().clone();
+        let result = f();
+        if result.is_err() {
+            *self.values.borrow_mut() = values;
+            *self.tables.borrow_mut() = tables;
+        }
+        result
+    }
+
+    fn scrub(&self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn destroy(&self) -> Result<(), std::io::Error> {
+        self.values.borrow_mut().clear();
+        self.tables.borrow_mut().clear();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEST_TABLE: Table = Table { name: "test", schema: "id INTEGER PRIMARY KEY, data BLOB", index: Some("data") };
+
+    fn create_test_storage(encrypted: bool) -> SqliteStorage {
+        let database_folder = std::env::temp_dir().join(format!("aira-test-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&database_folder).unwrap();
+        let mut storage = SqliteStorage::new(database_folder.to_str().unwrap().to_owned());
+        if encrypted {
+            storage.unlock(Some(&crypto::derive_database_key(&[1; crypto::MASTER_KEY_LEN])));
+        }
+        storage.create_tables(&[&TEST_TABLE]).unwrap();
+        storage
+    }
+
+    fn check_transactions<S: Storage>(storage: &S) {
+        let result: Result<(), Error> = storage.transaction(|| {
+            storage.set_value("key", b"value")?;
+            storage.insert(&TEST_TABLE, &[("data", b"row")])?;
+            storage.scrub()?;
+            Err(Error::QueryReturnedNoRows)
+        });
+        assert!(result.is_err());
+        assert_eq!(storage.get_value("key").unwrap(), None);
+        assert!(storage.select(&TEST_TABLE, &["data"]).unwrap().is_empty());
+
+        let id = storage.transaction(|| {
+            storage.set_value("key", b"value")?;
+            let id = storage.transaction(|| storage.insert(&TEST_TABLE, &[("data", b"row")]))?;
+            storage.delete(&TEST_TABLE, storage.insert(&TEST_TABLE, &[("data", b"deleted")])?)?;
+            storage.scrub()?;
+            Ok(id)
+        }).unwrap();
+        assert_eq!(storage.get_value("key").unwrap(), Some(b"value".to_vec()));
+        assert_eq!(storage.select(&TEST_TABLE, &["data"]).unwrap(), vec![(id, vec![Some(b"row".to_vec())])]);
+    }
+
+    #[test]
+    fn sqlite_transactions() {
+        check_transactions(&create_test_storage(false));
+    }
+
+    #[test]
+    fn encrypted_sqlite_transactions() {
+        let storage = create_test_storage(true);
+        check_transactions(&storage);
+        assert!(Path::new(&get_encrypted_database_path(&storage.database_folder)).exists());
+    }
+
+    fn check_panicking_transaction(storage: &SqliteStorage) {
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| storage.transaction::<(), _>(|| {
+            storage.set_value("key", b"value")?;
+            storage.insert(&TEST_TABLE, &[("data", b"row")])?;
+            storage.scrub()?;
+            panic!("Transaction interrupted");
+        })));
+        assert!(result.is_err());
+        assert!(!storage.in_transaction.get());
+        assert!(!storage.scrub_pending.get());
+        assert_eq!(storage.get_value("key").unwrap(), None);
+        assert!(storage.select(&TEST_TABLE, &["data"]).unwrap().is_empty());
+        check_transactions(storage);
+    }
+
+    #[test]
+    fn panicking_transactions() {
+        check_panicking_transaction(&create_test_storage(false));
+        let storage = create_test_storage(true);
+        check_panicking_transaction(&storage);
+    }
+
+    //deferred foreign keys are only checked by COMMIT
+    fn check_failed_commit(storage: &SqliteStorage) {
+        storage.with_database(|db| db.execute_batch("PRAGMA foreign_keys = ON; CREATE TABLE child (parent INTEGER REFERENCES test(id) DEFERRABLE INITIALLY DEFERRED)")).unwrap();
+        let result = storage.transaction(|| {
+            storage.set_value("key", b"value")?;
+            storage.scrub()?;
+            storage.with_database(|db| db.execute("INSERT INTO child (parent) VALUES (42)", []))
+        });
+        assert!(matches!(result, Err(Error::SqliteFailure(e, _)) if e.code == rusqlite::ErrorCode::ConstraintViolation));
+        assert!(!storage.scrub_pending.get());
+        assert!(storage.with_database(|db| Ok(db.is_autocommit())).unwrap());
+        assert_eq!(storage.get_value("key").unwrap(), None);
+        check_transactions(storage);
+    }
+
+    #[test]
+    fn failed_commits_are_rolled_back() {
+        check_failed_commit(&create_test_storage(false));
+        let storage = create_test_storage(true);
+        check_failed_commit(&storage);
+    }
+
+    #[test]
+    fn encrypted_sqlite_commits_once() {
+        let storage = create_test_storage(true);
+        storage.transaction(|| {
+            storage.set_value("key", b"value")?;
+            storage.insert(&TEST_TABLE, &[("data", b"row")])
+        }).unwrap();
+        storage.with_database(|db| {
+            let schemas = db.prepare("PRAGMA database_list")?.query_map([], |row| row.get::<_, String>(1))?.collect::<Result<Vec<_>, _>>()?;
+            assert_eq!(schemas, ["main", "plaintext"]);
+            for schema in schemas {
+                let journal_mode: String = db.query_row(&format!("PRAGMA {}.journal_mode", schema), [], |row| row.get(0))?;
+                assert_eq!(journal_mode, "delete");
+            }
+            Ok(())
+        }).unwrap();
+        storage.close();
+        let plaintext = open_database(&storage.get_database_path()).unwrap();
+        assert_eq!(KeyValueTable::new(&plaintext, MAIN_TABLE).unwrap().get("key").unwrap(), b"value");
+        assert!(!table_exists(&plaintext, TEST_TABLE.name).unwrap());
+    }
+
+    fn check_lookups<S: Storage>(storage: &S) {
+        assert!(storage.find(&TEST_TABLE, "data", b"row").unwrap().is_empty());
+        let ids = storage.insert_many(&TEST_TABLE, &["data"], &[vec![b"row"], vec![b"other"], vec![b"row"]]).unwrap();
+        assert_eq!(storage.find(&TEST_TABLE, "data", b"row").unwrap(), [ids[0], ids[2]]);
+        assert_eq!(storage.find(&TEST_TABLE, "data", b"other").unwrap(), [ids[1]]);
+        assert!(storage.find(&TEST_TABLE, "data", b"ro").unwrap().is_empty());
+
+        let row = |data: &[u8]| vec![Some(data.to_vec())];
+        assert_eq!(storage.get_many(&TEST_TABLE, &[ids[2], ids[2]+1, ids[0]], &["data"]).unwrap(), [(ids[2], row(b"row")), (ids[0], row(b"row"))]);
+        let many_ids = storage.insert_many(&TEST_TABLE, &["data"], &vec![vec![&b"many"[..]]; MAX_VARIABLES+1]).unwrap();
+        let rows = storage.get_many(&TEST_TABLE, &many_ids.iter().rev().copied().collect::<Vec<_>>(), &["data"]).unwrap();
+        assert_eq!(rows.iter().map(|(id, _)| *id).collect::<Vec<_>>(), many_ids.into_iter().rev().collect::<Vec<_>>());
+        assert!(storage.get_many(&TEST_TABLE, &[], &["data"]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn lookups() {
+        check_lookups(&MemoryStorage::new());
+        let storage = create_test_storage(false);
+        check_lookups(&storage);
+        let plan: String = storage.with_database(|db| {
+            db.query_row(&format!("EXPLAIN QUERY PLAN SELECT rowid FROM {} WHERE data=?", TEST_TABLE.name), [b"row"], |row| row.get(3))
+        }).unwrap();
+        assert!(plan.contains("USING COVERING INDEX") || plan.contains("USING INDEX"), "{}", plan);
+    }
+
+    #[test]
+    fn memory_transactions() {
+        check_transactions(&MemoryStorage::new());
+    }
+
+    #[test]
+    fn sqlite_uses_wal() {
+        let storage = create_test_storage(false);
+        storage.set_value("key", b"value").unwrap();
+        let journal_mode: String = storage.with_database(|db| db.query_row("PRAGMA journal_mode", [], |row| row.get(0))).unwrap();
+        assert_eq!(journal_mode, "wal");
+        storage.destroy().unwrap();
+        assert_eq!(std::fs::read_dir(&storage.database_folder).unwrap().count(), 0);
+    }
+}
@@ -0,0 +1,213 @@
+use std::convert::{TryFrom, TryInto};
+use ed25519_dalek::{Keypair, PublicKey, Signature, Signer, Verifier, PUBLIC_KEY_LENGTH, SIGNATURE_LENGTH};
+use crate::crypto;
+
+const QR_PREFIX: &str = "aira";
+const QR_VERSION: u8 = 1;
+const KEY_ROTATION_CONTEXT: &[u8] = b"aira key rotation";
+pub const KEY_ROTATION_LEN: usize = 2*PUBLIC_KEY_LENGTH+2*SIGNATURE_LENGTH;
+
+//QR payload format: "aira:<version>:<hex public key>:<hex fingerprint>"
+//the fingerprint is redundant with the key but lets scanners detect truncated or altered payloads
+pub struct QrPayload {
+    pub version: u8,
+    pub public_key: [u8; PUBLIC_KEY_LENGTH],
+}
+
+impl QrPayload {
+    pub fn new(public_key: [u8; PUBLIC_KEY_LENGTH]) -> QrPayload {
+        QrPayload {
+            version: QR_VERSION,
+            public_key,
+        }
+    }
+
+    pub fn encode(&self) -> String {
+        format!("{}:{}:{}:{}", QR_PREFIX, self.version, hex::encode(self.public_key), hex::encode(crypto::compute_fingerprint(&self.public_key)))
+    }
+
+    pub fn parse(payload: &str) -> Option<QrPayload> {
+        let fields: Vec<&str> = payload.trim().split(':').collect();
+        if fields.len() != 4 || fields[0] != QR_PREFIX {
+            return None;
+        }
+        let version: u8 = fields[1].parse().ok()?;
+        if version != QR_VERSION {
+            return None;
+        }
+        let public_key: [u8; PUBLIC_KEY_LENGTH] = hex::decode(fields[2]).ok()?.try_into().ok()?;
+        let fingerprint = hex::decode(fields[3]).ok()?;
+        if fingerprint != crypto::compute_fingerprint(&public_key) {
+            return None;
+        }
+        Some(QrPayload {
+            version,
+            public_key,
+        })
+    }
+
+    pub fn verify(payload: &str, expected_public_key: &[u8]) -> bool {
+        match QrPayload::parse(payload) {
+            Some(qr_payload) => qr_payload.public_key == expected_public_key,
+            None => false
+        }
+    }
+}
+
+//"new key = K2" statement signed by the old key. The new key signs it too to prove that it is owned by the same person.
+pub struct KeyRotation {
+    pub old_public_key: [u8; PUBLIC_KEY_LENGTH],
+    pub new_public_key: [u8; PUBLIC_KEY_LENGTH],
+    old_signature: [u8; SIGNATURE_LENGTH],
+    new_signature: [u8; SIGNATURE_LENGTH],
+}
+
+impl KeyRotation {
+    fn get_statement(old_public_key: &[u8], new_public_key: &[u8]) -> Vec<u8> {
+        [KEY_ROTATION_CONTEXT, old_public_key, new_public_key].concat()
+    }
+
+    pub fn new(old_keypair: &Keypair, new_keypair: &Keypair) -> KeyRotation {
+        let statement = KeyRotation::get_statement(old_keypair.public.as_bytes(), new_keypair.public.as_bytes());
+        KeyRotation {
+            old_public_key: old_keypair.public.to_bytes(),
+            new_public_key: new_keypair.public.to_bytes(),
+            old_signature: old_keypair.sign(&statement).to_bytes(),
+            new_signature: new_keypair.sign(&statement).to_bytes(),
+        }
+    }
+
+    pub fn to_bytes(&self) -> [u8; KEY_ROTATION_LEN] {
+        [&self.old_public_key[..], &self.new_public_key, &self.old_signature, &self.new_signature].concat().try_into().unwrap()
+    }
+
+    //only returns rotations whose both signatures are valid
+    pub fn from_bytes(bytes: &[u8]) -> Option<KeyRotation> {
+        if bytes.len() != KEY_ROTATION_LEN {
+            return None;
+        }
+        let key_rotation = KeyRotation {
+            old_public_key: bytes[..PUBLIC_KEY_LENGTH].try_into().unwrap(),
+            new_public_key: bytes[PUBLIC_KEY_LENGTH..2*PUBLIC_KEY_LENGTH].try_into().unwrap(),
+            old_signature: bytes[2*PUBLIC_KEY_LENGTH..2*PUBLIC_KEY_LENGTH+SIGNATURE_LENGTH].try_into().unwrap(),
+            new_signature: bytes[2*PUBLIC_KEY_LENGTH+SIGNATURE_LENGTH..].try_into().unwrap(),
+        };
+        if key_rotation.verify() {
+            Some(key_rotation)
+        } else {
+            None
+        }
+    }
+
+    fn verify(&self) -> bool {
+        let statement = KeyRotation::get_statement(&self.old_public_key, &self.new_public_key);
+        let verify_signature = |public_key: &[u8], signature: &[u8]| -> bool {
+            match (PublicKey::from_bytes(public_key), Signature::try_from(signature)) {
+                (Ok(public_key), Ok(signature)) => public_key.verify(&statement, &signature).is_ok(),
+                _ => false
+            }
+        };
+        verify_signature(&self.old_public_key, &self.old_signature) && verify_signature(&self.new_public_key, &self.new_signature)
+    }
+
+    //follow the chain of rotations starting from a known key. Returns None if the chain doesn't lead to the expected key.
+    pub fn follow_chain(key_rotations: &[KeyRotation], known_public_key: &[u8; PUBLIC_KEY_LENGTH], expected_public_key: &[u8]) -> Option<usize> {
+        let mut current_key = *known_public_key;
+        let mut hops = 0;
+        while current_key != expected_public_key {
+            current_key = key_rotations.iter().find(|key_rotation| key_rotation.old_public_key == current_key)?.new_public_key;
+            hops += 1;
+            if hops > key_rotations.len() { //loop in the chain
+                return None;
+            }
+        }
+        Some(hops)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PUBLIC_KEY: [u8; PUBLIC_KEY_LENGTH] = [1; PUBLIC_KEY_LENGTH];
+    const PAYLOAD: &str = "aira:1:0101010101010101010101010101010101010101010101010101010101010101:2975f687467be981c4ff7964fbea3d05";
+
+    #[test]
+    fn qr_payload_round_trip() {
+        assert_eq!(QrPayload::new(PUBLIC_KEY).encode(), PAYLOAD);
+        let qr_payload = QrPayload::parse(PAYLOAD).unwrap();
+        assert_eq!((qr_payload.version, qr_payload.public_key), (QR_VERSION, PUBLIC_KEY));
+        assert!(QrPayload::parse(&format!(" {}\n", PAYLOAD)).is_some());
+        assert!(QrPayload::verify(PAYLOAD, &PUBLIC_KEY));
+        assert!(!QrPayload::verify(PAYLOAD, &[2; PUBLIC_KEY_LENGTH]));
+    }
+
+    #[test]
+    fn invalid_qr_payloads() {
+        let wrong_fingerprint = PAYLOAD.replace(":2975f687", ":2975f688");
+        let wrong_version = PAYLOAD.replace("aira:1:", "aira:2:");
+        let wrong_prefix = PAYLOAD.replace("aira:", "aria:");
+        let malformed_key = PAYLOAD.replace(":0101", ":01zz");
+        let short_key = PAYLOAD.replace(":0101", ":");
+        let malformed_fingerprint = PAYLOAD.replace("fbea3d05", "fbea3d0");
+        for payload in [wrong_fingerprint.as_str(), &wrong_version, &wrong_prefix, &malformed_key, &short_key, &malformed_fingerprint, "aira:x:01:01", &PAYLOAD[..40], &format!("{}:00", PAYLOAD), ""] {
+            assert!(QrPayload::parse(payload).is_none(), "{}", payload);
+            assert!(!QrPayload::verify(payload, &PUBLIC_KEY));
+        }
+    }
+
+    fn generate_keypairs<const N: usize>() -> [Keypair; N] {
+        [(); N].map(|_| Keypair::generate(&mut rand_7::rngs::OsRng))
+    }
+
+    #[test]
+    fn key_rotation_signatures() {
+        let [old_keypair, new_keypair, attacker_keypair] = generate_keypairs();
+        let bytes = KeyRotation::new(&old_keypair, &new_keypair).to_bytes();
+        let key_rotation = KeyRotation::from_bytes(&bytes).unwrap();
+        assert_eq!(key_rotation.old_public_key, old_keypair.public.to_bytes());
+        assert_eq!(key_rotation.new_public_key, new_keypair.public.to_bytes());
+        assert!(KeyRotation::from_bytes(&bytes[..KEY_ROTATION_LEN-1]).is_none());
+        assert!(KeyRotation::from_bytes(&[&bytes[..], &[0]].concat()).is_none());
+
+        //the new key didn't sign the statement
+        let statement = KeyRotation::get_statement(old_keypair.public.as_bytes(), new_keypair.public.as_bytes());
+        let mut forged = bytes;
+        forged[KEY_ROTATION_LEN-SIGNATURE_LENGTH..].copy_from_slice(&attacker_keypair.sign(&statement).to_bytes());
+        assert!(KeyRotation::from_bytes(&forged).is_none());
+        //the old key signed a rotation to another key
+        let mut forged = KeyRotation::new(&old_keypair, &attacker_keypair).to_bytes();
+        forged[PUBLIC_KEY_LENGTH..2*PUBLIC_KEY_LENGTH].copy_from_slice(new_keypair.public.as_bytes());
+        assert!(KeyRotation::from_bytes(&forged).is_none());
+        for i in [0, PUBLIC_KEY_LENGTH, 2*PUBLIC_KEY_LENGTH, KEY_ROTATION_LEN-1] {
+            let mut tampered = bytes;
+            tampered[i] ^= 1;
+            assert!(KeyRotation::from_bytes(&tampered).is_none());
+        }
+    }
+
+    #[test]
+    fn key_rotation_chain() {
+        let keypairs: [Keypair; 4] = generate_keypairs();
+        let public_keys = keypairs.each_ref().map(|keypair| keypair.public.to_bytes());
+        let chain: Vec<KeyRotation> = keypairs.windows(2).rev().map(|keypairs| KeyRotation::new(&keypairs[0], &keypairs[1])).collect();
+        assert_eq!(KeyRotation::follow_chain(&chain, &public_keys[0], &public_keys[0]), Some(0));
+        assert_eq!(KeyRotation::follow_chain(&chain, &public_keys[0], &public_keys[3]), Some(3));
+        assert_eq!(KeyRotation::follow_chain(&chain, &public_keys[1], &public_keys[3]), Some(2));
+        //rotations only go forward
+        assert_eq!(KeyRotation::follow_chain(&chain, &public_keys[3], &public_keys[0]), None);
+        let [stranger] = generate_keypairs();
+        assert_eq!(KeyRotation::follow_chain(&chain, &public_keys[0], stranger.public.as_bytes()), None);
+
+        //broken link
+        let broken_chain = [KeyRotation::new(&keypairs[0], &keypairs[1]), KeyRotation::new(&keypairs[2], &keypairs[3])];
+        assert_eq!(KeyRotation::follow_chain(&broken_chain, &public_keys[0], &public_keys[3]), None);
+        assert_eq!(KeyRotation::follow_chain(&broken_chain, &public_keys[2], &public_keys[3]), Some(1));
+
+        //a loop must stop once the chain length is reached
+        let looping_chain = [KeyRotation::new(&keypairs[0], &keypairs[1]), KeyRotation::new(&keypairs[1], &keypairs[2]), KeyRotation::new(&keypairs[2], &keypairs[0])];
+        assert_eq!(KeyRotation::follow_chain(&looping_chain, &public_keys[0], &public_keys[3]), None);
+        assert_eq!(KeyRotation::follow_chain(&looping_chain, &public_keys[1], &public_keys[0]), Some(2));
+        assert_eq!(KeyRotation::follow_chain(&[], &public_keys[0], &public_keys[1]), None);
+    }
+}
@@ -0,0 +1,330 @@
+//checks that the Rust core speaks the PSEC protocol shared with the desktop AIRA:
+//known-answer vectors generated independently (see vectors/gen_psec_vectors.py),
+//sessions recorded against async-psec (see psec-interop/tests/record_vectors.rs) and
+//loopback sessions with a reference peer written from the protocol description.
+use std::{collections::HashMap, convert::{TryFrom, TryInto}, net::{IpAddr, Ipv4Addr, TcpStream}, sync::Arc, thread};
+use aes_gcm::{Aes128Gcm, Key, NewAead, Nonce, aead::Aead};
+use ed25519_dalek::{Keypair, PublicKey, Signature, Verifier};
+use sha2::{Digest, Sha384};
+use tokio::sync::mpsc::{self, UnboundedSender};
+use aira_core::{crypto::{self, ApplicationKeys, HandshakeKeys}, net::{self, RecordCipher, SessionEvents, SessionManager}, padding::PaddingPolicy};
+
+fn load_vectors(vectors: &str) -> Vec<HashMap<String, Vec<u8>>> {
+    vectors.split("\n\n").map(|block| {
+        block.lines().filter(|line| !line.is_empty() && !line.starts_with('#')).map(|line| {
+            let (name, value) = line.split_once(" = ").unwrap();
+            (name.to_owned(), hex::decode(value).unwrap())
+        }).collect::<HashMap<_, _>>()
+    }).filter(|vector| !vector.is_empty()).collect()
+}
+
+fn array<const N: usize>(input: &[u8]) -> [u8; N] {
+    input.try_into().unwrap()
+}
+
+#[test]
+fn key_schedule_matches_vectors() {
+    let vectors = load_vectors(include_str!("vectors/psec_kat.txt"));
+    assert_eq!(vectors.len(), 6);
+    for vector in vectors {
+        let handshake_hash = array(&vector["handshake_hash"]);
+        let i_am_bob = vector["i_am_bob"] == [1];
+        let handshake_keys = HandshakeKeys::derive_keys(array(&vector["shared_secret"]), handshake_hash, i_am_bob);
+        assert_eq!(handshake_keys.handshake_secret[..], vector["handshake_secret"][..]);
+        assert_eq!(handshake_keys.local_handshake_traffic_secret[..], vector["local_handshake_traffic_secret"][..]);
+        assert_eq!(handshake_keys.peer_handshake_traffic_secret[..], vector["peer_handshake_traffic_secret"][..]);
+        assert_eq!(handshake_keys.local_key[..], vector["local_handshake_key"][..]);
+        assert_eq!(handshake_keys.local_iv[..], vector["local_handshake_iv"][..]);
+        assert_eq!(handshake_keys.peer_key[..], vector["peer_handshake_key"][..]);
+        assert_eq!(handshake_keys.peer_iv[..], vector["peer_handshake_iv"][..]);
+
+        let finished = crypto::compute_handshake_finished(handshake_keys.local_handshake_traffic_secret, handshake_hash);
+        assert_eq!(finished[..], vector["local_handshake_finished"][..]);
+        //the peer checks our finished message against its peer traffic secret, which is our local one
+        assert!(crypto::verify_handshake_finished(finished, handshake_keys.local_handshake_traffic_secret, handshake_hash));
+        assert!(!crypto::verify_handshake_finished(finished, handshake_keys.peer_handshake_traffic_secret, handshake_hash));
+
+        let application_keys = ApplicationKeys::derive_keys(handshake_keys.handshake_secret, handshake_hash, i_am_bob);
+        assert_eq!(application_keys.local_key[..], vector["local_application_key"][..]);
+        assert_eq!(application_keys.local_iv[..], vector["local_application_iv"][..]);
+        assert_eq!(application_keys.peer_key[..], vector["peer_application_key"][..]);
+        assert_eq!(application_keys.peer_iv[..], vector["peer_application_iv"][..]);
+    }
+}
+
+//replays both sides of the recorded sessions: what async-psec sent must authenticate and decrypt,
+//and our side must be reproduced byte for byte since async-psec accepted it
+#[tokio::test]
+async fn async_psec_sessions_replay() {
+    let vectors = load_vectors(include_str!("vectors/async_psec_session.txt"));
+    assert_eq!(vectors.len(), 2);
+    for (vector, expected_bob) in vectors.into_iter().zip([false, true]) {
+        let ephemeral_secret = x25519_dalek::StaticSecret::from(array::<32>(&vector["ephemeral_secret"]));
+        let (hello, peer_hello) = (&vector["hello"], &vector["async_psec_hello"]);
+        assert_eq!(hello[64..], x25519_dalek::PublicKey::from(&ephemeral_secret).as_bytes()[..]);
+        let peer_ephemeral_public_key = x25519_dalek::PublicKey::from(array::<32>(&peer_hello[64..]));
+        let shared_secret = ephemeral_secret.diffie_hellman(&peer_ephemeral_public_key);
+        let i_am_bob = hello < peer_hello;
+        assert_eq!(i_am_bob, expected_bob);
+        let transcript_hash = |sent: &[&[u8]], received: &[&[u8]]| -> [u8; crypto::HASH_OUTPUT_LEN] {
+            let (first, second) = if i_am_bob { (sent, received) } else { (received, sent) };
+            array(&Sha384::new().chain_update(first.concat()).chain_update(second.concat()).finalize())
+        };
+        let handshake_keys = HandshakeKeys::derive_keys(shared_secret.to_bytes(), transcript_hash(&[hello], &[peer_hello]), i_am_bob);
+
+        let peer_auth = Aes128Gcm::new(Key::from_slice(&handshake_keys.peer_key))
+            .decrypt(Nonce::from_slice(&handshake_keys.peer_iv), vector["async_psec_auth"].as_slice()).unwrap();
+        assert_eq!(peer_auth[64..96], vector["async_psec_public_key"][..]);
+        let signature = Signature::try_from(&peer_auth[96..]).unwrap();
+        PublicKey::from_bytes(&peer_auth[64..96]).unwrap().verify(peer_ephemeral_public_key.as_bytes(), &signature).unwrap();
+        let auth = Aes128Gcm::new(Key::from_slice(&handshake_keys.local_key))
+            .decrypt(Nonce::from_slice(&handshake_keys.local_iv), vector["auth"].as_slice()).unwrap();
+        assert_eq!(auth[64..96], vector["public_key"][..]);
+
+        let handshake_hash = transcript_hash(&[hello, &vector["auth"]], &[peer_hello, &vector["async_psec_auth"]]);
+        assert_eq!(crypto::compute_handshake_finished(handshake_keys.local_handshake_traffic_secret, handshake_hash)[..], vector["finished"][..]);
+        assert!(crypto::verify_handshake_finished(array(&vector["async_psec_finished"]), handshake_keys.peer_handshake_traffic_secret, handshake_hash));
+
+        let application_keys = ApplicationKeys::derive_keys(handshake_keys.handshake_secret, handshake_hash, i_am_bob);
+        let mut peer_cipher = RecordCipher::new(&application_keys.peer_key, application_keys.peer_iv);
+        for record in ["async_psec_padded_record", "async_psec_record"] {
+            assert_eq!(net::read_record(&mut vector[record].as_slice(), &mut peer_cipher).await.unwrap().unwrap(), vector["async_psec_message"]);
+        }
+        let mut local_cipher = RecordCipher::new(&application_keys.local_key, application_keys.local_iv);
+        assert_eq!(local_cipher.encrypt(&PaddingPolicy::None.pad(&vector["message"])), vector["record"]);
+    }
+}
+
+//minimal PSEC peer using blocking sockets. It deliberately shares no code with aira_core.
+mod reference {
+    use std::{convert::{TryFrom, TryInto}, io::{Read, Write}, net::TcpStream};
+    use aes_gcm::{Aes128Gcm, Key, NewAead, Nonce, aead::{Aead, Payload}};
+    use ed25519_dalek::{Keypair, PublicKey, Signature, Signer, Verifier};
+    use hmac::{Hmac, Mac};
+    use rand_7::{RngCore, rngs::OsRng};
+    use sha2::{Digest, Sha384};
+    use x25519_dalek::{PublicKey as EphemeralPublicKey, StaticSecret};
+
+    const RANDOM_LEN: usize = 64;
+
+    fn hmac(key: &[u8], data: &[&[u8]]) -> Vec<u8> {
+        let mut hmac = Hmac::<Sha384>::new_from_slice(key).unwrap();
+        for part in data {
+            hmac.update(part);
+        }
+        hmac.finalize().into_bytes().to_vec()
+    }
+
+    //RFC 5869
+    fn extract(salt: Option<&[u8]>, ikm: &[u8]) -> Vec<u8> {
+        hmac(salt.unwrap_or(&[0; 48]), &[ikm])
+    }
+
+    fn expand_label(secret: &[u8], label: &str, context: Option<&[u8]>, len: usize) -> Vec<u8> {
+        let mut info = [&(label.len() as u32).to_be_bytes()[..], label.as_bytes()].concat();
+        if let Some(context) = context {
+            info.extend_from_slice(&(context.len() as u32).to_be_bytes());
+            info.extend_from_slice(context);
+        }
+        let (mut okm, mut block) = (Vec::new(), Vec::new());
+        let mut counter = 1u8;
+        while okm.len() < len {
+            block = hmac(secret, &[&block, &info, &[counter]]);
+            okm.extend_from_slice(&block);
+            counter += 1;
+        }
+        okm.truncate(len);
+        okm
+    }
+
+    struct Direction {
+        cipher: Aes128Gcm,
+        iv: Vec<u8>,
+        counter: u64,
+    }
+
+    impl Direction {
+        fn new(traffic_secret: &[u8]) -> Direction {
+            Direction {
+                cipher: Aes128Gcm::new(Key::from_slice(&expand_label(traffic_secret, "key", None, 16))),
+                iv: expand_label(traffic_secret, "iv", None, 12),
+                counter: 0,
+            }
+        }
+
+        fn nonce(&mut self) -> Vec<u8> {
+            let counter = [&[0; 4][..], &self.counter.to_be_bytes()].concat();
+            self.counter += 1;
+            self.iv.iter().zip(counter).map(|(iv, counter)| iv ^ counter).collect()
+        }
+    }
+
+    pub struct Channel {
+        stream: TcpStream,
+        local: Direction,
+        peer: Direction,
+        pub peer_public_key: Vec<u8>,
+        pub i_am_bob: bool,
+    }
+
+    pub fn handshake(mut stream: TcpStream, keypair: &Keypair) -> Channel {
+        let (mut sent, mut received) = (Vec::new(), Vec::new());
+        let mut random = [0; RANDOM_LEN];
+        OsRng.fill_bytes(&mut random);
+        let ephemeral_secret = StaticSecret::new(OsRng);
+        let ephemeral_public_key = EphemeralPublicKey::from(&ephemeral_secret);
+        sent.extend_from_slice(&random);
+        sent.extend_from_slice(ephemeral_public_key.as_bytes());
+        stream.write_all(&sent).unwrap();
+
+        received.resize(RANDOM_LEN+32, 0);
+        stream.read_exact(&mut received).unwrap();
+        let peer_ephemeral_public_key: [u8; 32] = received[RANDOM_LEN..].try_into().unwrap();
+        let shared_secret = ephemeral_secret.diffie_hellman(&EphemeralPublicKey::from(peer_ephemeral_public_key));
+        //bob is the peer whose hello is lower at the first differing byte
+        let i_am_bob = sent < received;
+        let transcript_hash = |sent: &[u8], received: &[u8]| -> Vec<u8> {
+            let (first, second) = if i_am_bob { (sent, received) } else { (received, sent) };
+            Sha384::new().chain_update(first).chain_update(second).finalize().to_vec()
+        };
+        let (local_role, peer_role) = if i_am_bob { ("bob", "alice") } else { ("alice", "bob") };
+
+        let handshake_hash = transcript_hash(&sent, &received);
+        let handshake_secret = extract(None, shared_secret.as_bytes());
+        let local_traffic_secret = expand_label(&handshake_secret, &format!("handshake_i_am_{}", local_role), Some(&handshake_hash), 48);
+        let peer_traffic_secret = expand_label(&handshake_secret, &format!("handshake_i_am_{}", peer_role), Some(&handshake_hash), 48);
+        let local = Direction::new(&local_traffic_secret);
+        let peer = Direction::new(&peer_traffic_secret);
+
+        OsRng.fill_bytes(&mut random);
+        let auth = [&random[..], keypair.public.as_bytes(), &keypair.sign(ephemeral_public_key.as_bytes()).to_bytes()].concat();
+        let encrypted_auth = local.cipher.encrypt(Nonce::from_slice(&local.iv), auth.as_slice()).unwrap();
+        stream.write_all(&encrypted_auth).unwrap();
+        sent.extend_from_slice(&encrypted_auth);
+
+        let mut peer_encrypted_auth = vec![0; RANDOM_LEN+32+64+16];
+        stream.read_exact(&mut peer_encrypted_auth).unwrap();
+        received.extend_from_slice(&peer_encrypted_auth);
+        let peer_auth = peer.cipher.decrypt(Nonce::from_slice(&peer.iv), peer_encrypted_auth.as_slice()).unwrap();
+        let peer_public_key = peer_auth[RANDOM_LEN..RANDOM_LEN+32].to_vec();
+        let signature = Signature::try_from(&peer_auth[RANDOM_LEN+32..]).unwrap();
+        PublicKey::from_bytes(&peer_public_key).unwrap().verify(&peer_ephemeral_public_key, &signature).unwrap();
+
+        let handshake_hash = transcript_hash(&sent, &received);
+        stream.write_all(&hmac(&expand_label(&local_traffic_secret, "finished", None, 48), &[&handshake_hash])).unwrap();
+        let mut peer_finished = [0; 48];
+        stream.read_exact(&mut peer_finished).unwrap();
+        assert_eq!(peer_finished[..], hmac(&expand_label(&peer_traffic_secret, "finished", None, 48), &[&handshake_hash])[..]);
+
+        let master_secret = extract(Some(&expand_label(&handshake_secret, "derived", None, 48)), b"");
+        Channel {
+            stream,
+            local: Direction::new(&expand_label(&master_secret, &format!("application_i_am_{}", local_role), Some(&handshake_hash), 48)),
+            peer: Direction::new(&expand_label(&master_secret, &format!("application_i_am_{}", peer_role), Some(&handshake_hash), 48)),
+            peer_public_key,
+            i_am_bob,
+        }
+    }
+
+    impl Channel {
+        //padded with the message length prefix only
+        pub fn send(&mut self, message: &[u8]) {
+            let padded = [&(message.len() as u32).to_be_bytes()[..], message].concat();
+            let raw_len = ((padded.len()+16) as u32).to_be_bytes();
+            let nonce = self.local.nonce();
+            let cipher_text = self.local.cipher.encrypt(Nonce::from_slice(&nonce), Payload { msg: &padded, aad: &raw_len }).unwrap();
+            self.stream.write_all(&[&raw_len[..], &cipher_text].concat()).unwrap();
+        }
+
+        pub fn recv(&mut self) -> Vec<u8> {
+            let mut raw_len = [0; 4];
+            self.stream.read_exact(&mut raw_len).unwrap();
+            let mut cipher_text = vec![0; u32::from_be_bytes(raw_len) as usize];
+            self.stream.read_exact(&mut cipher_text).unwrap();
+            let nonce = self.peer.nonce();
+            let padded = self.peer.cipher.decrypt(Nonce::from_slice(&nonce), Payload { msg: &cipher_text, aad: &raw_len }).unwrap();
+            let len = u32::from_be_bytes(padded[..4].try_into().unwrap()) as usize;
+            padded[4..4+len].to_vec()
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Event {
+    Opened([u8; 32]),
+    Message([u8; 32], Vec<u8>),
+}
+
+struct Forwarder(UnboundedSender<Event>);
+
+impl SessionEvents for Forwarder {
+    fn on_session_opened(&self, peer_public_key: &[u8; 32], _ip: IpAddr, _outgoing: bool) {
+        self.0.send(Event::Opened(*peer_public_key)).unwrap();
+    }
+    fn on_message(&self, peer_public_key: &[u8; 32], message: Vec<u8>) {
+        self.0.send(Event::Message(*peer_public_key, message)).unwrap();
+    }
+    fn on_session_closed(&self, _peer_public_key: &[u8; 32]) {}
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn session_with_reference_peer() {
+    let (sender, mut events) = mpsc::unbounded_channel();
+    let manager = SessionManager::new(Keypair::generate(&mut rand_7::rngs::OsRng), Arc::new(Forwarder(sender)));
+    let listener = tokio::net::TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(manager.clone().serve(listener));
+
+    let reference_keypair = Keypair::generate(&mut rand_7::rngs::OsRng);
+    let reference_public_key = reference_keypair.public.to_bytes();
+    let reference = thread::spawn(move || {
+        let mut channel = reference::handshake(TcpStream::connect(addr).unwrap(), &reference_keypair);
+        channel.send(b"\x00hello from the reference peer");
+        (channel.peer_public_key.clone(), channel.recv(), channel.recv())
+    });
+
+    match events.recv().await.unwrap() {
+        Event::Opened(public_key) => assert_eq!(public_key, reference_public_key),
+        _ => panic!("Session not opened"),
+    }
+    assert!(manager.send(&reference_public_key, b"\x00hello from aira-core"));
+    let big = vec![0x42; 3_000_000];
+    assert!(manager.send(&reference_public_key, &big));
+    match events.recv().await.unwrap() {
+        Event::Message(public_key, message) => {
+            assert_eq!(public_key, reference_public_key);
+            assert_eq!(message, b"\x00hello from the reference peer");
+        }
+        _ => panic!("Message not received"),
+    }
+    let (peer_public_key, first, second) = tokio::task::spawn_blocking(move || reference.join().unwrap()).await.unwrap();
+    assert_eq!(peer_public_key, manager.public_key());
+    assert_eq!(first, b"\x00hello from aira-core");
+    assert_eq!(second, big);
+}
+
+//roles are chosen from random hellos, so repeated handshakes cover both of them
+#[tokio::test(flavor = "multi_thread")]
+async fn handshake_roles_with_reference_peer() {
+    let mut roles = [false, false];
+    for _ in 0..32 {
+        let listener = std::net::TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let reference_keypair = Keypair::generate(&mut rand_7::rngs::OsRng);
+        let reference = thread::spawn(move || {
+            let mut channel = reference::handshake(listener.accept().unwrap().0, &reference_keypair);
+            let message = channel.recv();
+            channel.send(&message);
+            channel.i_am_bob
+        });
+        let keypair = Keypair::generate(&mut rand_7::rngs::OsRng);
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let mut channel = net::handshake(&mut stream, &keypair).await.unwrap();
+        let padded = PaddingPolicy::PowerOfTwo.pad(b"echo");
+        tokio::io::AsyncWriteExt::write_all(&mut stream, &channel.local_cipher.encrypt(&padded)).await.unwrap();
+        assert_eq!(net::read_record(&mut stream, &mut channel.peer_cipher).await.unwrap().unwrap(), b"echo");
+        let reference_was_bob = tokio::task::spawn_blocking(move || reference.join().unwrap()).await.unwrap();
+        roles[reference_was_bob as usize] = true;
+    }
+    assert_eq!(roles, [true, true]);
+}
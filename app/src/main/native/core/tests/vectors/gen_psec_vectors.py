@@ -0,0 +1,81 @@
+#!/usr/bin/env python3
+# Generates psec_kat.txt from an independent implementation of the PSEC key schedule,
+# written against the protocol description with the Python standard library only:
+#   handshake_secret = HKDF-Extract(salt=0, shared_secret)
+#   <role>_handshake_traffic_secret = ExpandLabel(handshake_secret, "handshake_i_am_<role>", handshake_hash)
+#   derived_secret = ExpandLabel(handshake_secret, "derived")
+#   master_secret = HKDF-Extract(salt=derived_secret, "")
+#   <role>_application_traffic_secret = ExpandLabel(master_secret, "application_i_am_<role>", handshake_hash)
+#   key = ExpandLabel(traffic_secret, "key", 16 bytes), iv = ExpandLabel(traffic_secret, "iv", 12 bytes)
+#   finished = HMAC(ExpandLabel(handshake_traffic_secret, "finished"), handshake_hash)
+# ExpandLabel info: u32 BE label length || label || [u32 BE context length || context]
+# All hashes are SHA-384. Usage: python3 gen_psec_vectors.py > psec_kat.txt
+import hashlib
+import hmac
+
+HASH_LEN = 48
+
+
+def hkdf_extract(salt, ikm):
+    return hmac.new(salt or bytes(HASH_LEN), ikm, hashlib.sha384).digest()
+
+
+def hkdf_expand(prk, info, length):
+    okm, block, counter = b"", b"", 1
+    while len(okm) < length:
+        block = hmac.new(prk, block + info + bytes([counter]), hashlib.sha384).digest()
+        okm += block
+        counter += 1
+    return okm[:length]
+
+
+def expand_label(secret, label, context=None, length=HASH_LEN):
+    info = len(label).to_bytes(4, "big") + label.encode()
+    if context is not None:
+        info += len(context).to_bytes(4, "big") + context
+    return hkdf_expand(secret, info, length)
+
+
+def traffic_keys(traffic_secret):
+    return expand_label(traffic_secret, "key", length=16), expand_label(traffic_secret, "iv", length=12)
+
+
+def case(shared_secret, handshake_hash, i_am_bob):
+    local_role, peer_role = ("bob", "alice") if i_am_bob else ("alice", "bob")
+    handshake_secret = hkdf_extract(None, shared_secret)
+    local_hts = expand_label(handshake_secret, "handshake_i_am_" + local_role, handshake_hash)
+    peer_hts = expand_label(handshake_secret, "handshake_i_am_" + peer_role, handshake_hash)
+    master_secret = hkdf_extract(expand_label(handshake_secret, "derived"), b"")
+    local_ats = expand_label(master_secret, "application_i_am_" + local_role, handshake_hash)
+    peer_ats = expand_label(master_secret, "application_i_am_" + peer_role, handshake_hash)
+    values = [
+        ("shared_secret", shared_secret),
+        ("handshake_hash", handshake_hash),
+        ("i_am_bob", bytes([i_am_bob])),
+        ("handshake_secret", handshake_secret),
+        ("local_handshake_traffic_secret", local_hts),
+        ("peer_handshake_traffic_secret", peer_hts),
+    ]
+    for prefix, keys in [("local_handshake", traffic_keys(local_hts)), ("peer_handshake", traffic_keys(peer_hts)), ("local_application", traffic_keys(local_ats)), ("peer_application", traffic_keys(peer_ats))]:
+        values += [(prefix + "_key", keys[0]), (prefix + "_iv", keys[1])]
+    finished_key = expand_label(local_hts, "finished")
+    values.append(("local_handshake_finished", hmac.new(finished_key, handshake_hash, hashlib.sha384).digest()))
+    return values
+
+
+def main():
+    print("# PSEC known-answer vectors, generated by gen_psec_vectors.py. Do not edit.")
+    inputs = [
+        (bytes(range(32)), hashlib.sha384(b"transcript").digest()),
+        (bytes([0xff] * 32), bytes(HASH_LEN)),
+        (hashlib.sha256(b"shared secret").digest(), hashlib.sha384(b"another transcript").digest()),
+    ]
+    for shared_secret, handshake_hash in inputs:
+        for i_am_bob in [False, True]:
+            print()
+            for name, value in case(shared_secret, handshake_hash, i_am_bob):
+                print("{} = {}".format(name, value.hex()))
+
+
+if __name__ == "__main__":
+    main()
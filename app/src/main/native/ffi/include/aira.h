@@ -0,0 +1,101 @@
+/*
+ * C interface to the AIRA core (libaira_ffi.so).
+ *
+ * Ownership rules:
+ *  - Objects returned by the create, load and handshake functions and lists returned by load functions
+ *    belong to the caller and must be released with the matching *_free function.
+ *  - Strings returned as `char *` belong to the caller: release them with aira_string_free.
+ *  - AiraBuffer values returned by the library belong to the caller: release them with
+ *    aira_buffer_free. Buffers and strings embedded in a list are released with the list.
+ *  - Pointers passed as arguments are only borrowed for the duration of the call.
+ *  - Sockets passed as file descriptors stay owned by the caller and are never closed.
+ *
+ * Errors: functions returning a pointer return NULL on failure, functions returning int
+ * return 0 on success and -1 on failure. aira_last_error() then describes the failure.
+ * NULL keys, UUIDs and strings are reported as errors. Identity and channel handles must not
+ * be NULL, except in the *_free functions.
+ *
+ * An identity must not be used from several threads at the same time. Different channels
+ * can be used concurrently.
+ */
+#ifndef AIRA_H
+#define AIRA_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#define AIRA_PUBLIC_KEY_LEN 32
+#define AIRA_UUID_LEN 16
+
+typedef struct AiraIdentity AiraIdentity;
+typedef struct AiraChannel AiraChannel;
+
+typedef struct {
+    uint8_t *data;
+    size_t len;
+} AiraBuffer;
+
+typedef struct {
+    uint8_t uuid[AIRA_UUID_LEN];
+    uint8_t public_key[AIRA_PUBLIC_KEY_LEN];
+    char *name; /* owned by the list */
+    bool verified;
+    bool seen;
+} AiraContact;
+
+typedef struct {
+    AiraContact *contacts;
+    size_t len;
+} AiraContactList;
+
+typedef struct {
+    bool outgoing;
+    uint64_t timestamp; /* seconds since the epoch */
+    AiraBuffer data; /* owned by the list */
+} AiraMessage;
+
+typedef struct {
+    AiraMessage *messages;
+    size_t len;
+} AiraMessageList;
+
+/* Borrowed: valid until the next failing call on the same thread. NULL if no error occurred. */
+const char *aira_last_error(void);
+
+void aira_buffer_free(AiraBuffer buffer);
+void aira_string_free(char *string);
+
+/* Identity. A NULL password means no password. The database is encrypted when a password is set. */
+AiraIdentity *aira_identity_create(const char *database_folder, const char *name, const uint8_t *password, size_t password_len);
+AiraIdentity *aira_identity_load(const char *database_folder, const uint8_t *password, size_t password_len);
+void aira_identity_free(AiraIdentity *identity); /* also wipes the keys from memory */
+char *aira_identity_name(const AiraIdentity *identity);
+void aira_identity_public_key(const AiraIdentity *identity, uint8_t public_key[AIRA_PUBLIC_KEY_LEN]);
+char *aira_fingerprint(const uint8_t public_key[AIRA_PUBLIC_KEY_LEN]);
+
+/* Contacts. uuid may be NULL in aira_contact_add. */
+int aira_contact_add(const AiraIdentity *identity, const char *name, const uint8_t public_key[AIRA_PUBLIC_KEY_LEN], uint8_t uuid[AIRA_UUID_LEN]);
+int aira_contact_remove(const AiraIdentity *identity, const uint8_t uuid[AIRA_UUID_LEN]);
+int aira_contact_set_verified(const AiraIdentity *identity, const uint8_t uuid[AIRA_UUID_LEN]);
+AiraContactList *aira_contacts_load(const AiraIdentity *identity);
+void aira_contact_list_free(AiraContactList *list);
+
+/* Messages. data is stored as-is, usually a protocol message (MESSAGE byte || UTF-8 text). */
+int aira_message_store(const AiraIdentity *identity, const uint8_t contact_uuid[AIRA_UUID_LEN], bool outgoing, uint64_t timestamp, const uint8_t *data, size_t data_len);
+/* Up to count messages, skipping the offset most recent ones, in chronological order. */
+AiraMessageList *aira_messages_load(const AiraIdentity *identity, const uint8_t contact_uuid[AIRA_UUID_LEN], size_t offset, size_t count);
+void aira_message_list_free(AiraMessageList *list);
+
+/* PSEC handshake on a connected, blocking TCP socket. Both peers run it symmetrically. */
+AiraChannel *aira_handshake(const AiraIdentity *identity, int socket);
+void aira_channel_free(AiraChannel *channel); /* also wipes the record keys from memory */
+void aira_channel_peer_public_key(const AiraChannel *channel, uint8_t public_key[AIRA_PUBLIC_KEY_LEN]);
+
+/* Records. Messages are padded with the padding policy of the identity. */
+AiraBuffer aira_channel_encrypt(AiraChannel *channel, const uint8_t *data, size_t data_len);
+int aira_channel_decrypt(AiraChannel *channel, const uint8_t *record, size_t record_len, AiraBuffer *message); /* 1 and an empty message for cover traffic */
+int aira_channel_send(AiraChannel *channel, int socket, const uint8_t *data, size_t data_len);
+int aira_channel_recv(AiraChannel *channel, int socket, AiraBuffer *message); /* blocks until a whole record is received, cover traffic is skipped */
+
+#endif
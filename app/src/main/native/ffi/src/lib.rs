@@ -0,0 +1,483 @@
+//C ABI over aira-core, see include/aira.h for the ownership rules
+#![allow(clippy::missing_safety_doc)]
+use std::{cell::RefCell, convert::TryInto, ffi::{CStr, CString}, fmt::Display, io::{self, Read, Write}, mem::ManuallyDrop, net::TcpStream, os::{raw::{c_char, c_int}, unix::io::{FromRawFd, RawFd}}, ptr, slice};
+use uuid::Uuid;
+use aira_core::{crypto, padding::{self, PaddingPolicy}, net::{self, SecureChannel}, protocol, Identity, Message};
+
+const PUBLIC_KEY_LEN: usize = 32;
+const UUID_LEN: usize = 16;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_error<E: Display>(e: E) {
+    LAST_ERROR.with(|last_error| *last_error.borrow_mut() = CString::new(e.to_string().replace('\0', "")).ok());
+}
+
+fn result_to_int<T, E: Display>(result: Result<T, E>) -> c_int {
+    match result {
+        Ok(_) => 0,
+        Err(e) => {
+            set_error(e);
+            -1
+        }
+    }
+}
+
+fn null_argument() -> c_int {
+    set_error("NULL argument");
+    -1
+}
+
+fn string_to_c(input: &str) -> *mut c_char {
+    CString::new(input.replace('\0', "")).unwrap().into_raw()
+}
+
+unsafe fn c_to_string(input: *const c_char) -> Option<String> {
+    if input.is_null() {
+        None
+    } else {
+        Some(CStr::from_ptr(input).to_string_lossy().into_owned())
+    }
+}
+
+unsafe fn c_to_slice<'a>(data: *const u8, len: usize) -> &'a [u8] {
+    if data.is_null() || len == 0 {
+        &[]
+    } else {
+        slice::from_raw_parts(data, len)
+    }
+}
+
+//fixed size arguments (keys, UUIDs), None if NULL
+unsafe fn c_to_array<const N: usize>(data: *const u8) -> Option<[u8; N]> {
+    if data.is_null() {
+        None
+    } else {
+        Some(*(data as *const [u8; N]))
+    }
+}
+
+unsafe fn c_to_uuid(uuid: *const u8) -> Option<Uuid> {
+    c_to_array(uuid).map(Uuid::from_bytes)
+}
+
+//a NULL password means no password
+unsafe fn c_to_password<'a>(password: *const u8, password_len: usize) -> Option<&'a [u8]> {
+    if password.is_null() {
+        None
+    } else {
+        Some(c_to_slice(password, password_len))
+    }
+}
+
+//borrows the socket without closing it on drop
+unsafe fn borrow_socket(fd: RawFd) -> ManuallyDrop<TcpStream> {
+    ManuallyDrop::new(TcpStream::from_raw_fd(fd))
+}
+
+//owned by the library: release with aira_buffer_free
+#[repr(C)]
+pub struct AiraBuffer {
+    pub data: *mut u8,
+    pub len: usize,
+}
+
+impl AiraBuffer {
+    fn from_vec(input: Vec<u8>) -> AiraBuffer {
+        let mut boxed = ManuallyDrop::new(input.into_boxed_slice());
+        AiraBuffer {
+            data: boxed.as_mut_ptr(),
+            len: boxed.len(),
+        }
+    }
+
+    fn empty() -> AiraBuffer {
+        AiraBuffer {
+            data: ptr::null_mut(),
+            len: 0,
+        }
+    }
+
+    unsafe fn free(&mut self) {
+        if !self.data.is_null() {
+            drop(Box::from_raw(ptr::slice_from_raw_parts_mut(self.data, self.len)));
+            self.data = ptr::null_mut();
+            self.len = 0;
+        }
+    }
+}
+
+#[repr(C)]
+pub struct AiraContact {
+    pub uuid: [u8; UUID_LEN],
+    pub public_key: [u8; PUBLIC_KEY_LEN],
+    pub name: *mut c_char, //owned by the list
+    pub verified: bool,
+    pub seen: bool,
+}
+
+//owned by the caller: release with aira_contact_list_free
+#[repr(C)]
+pub struct AiraContactList {
+    pub contacts: *mut AiraContact,
+    pub len: usize,
+}
+
+#[repr(C)]
+pub struct AiraMessage {
+    pub outgoing: bool,
+    pub timestamp: u64,
+    pub data: AiraBuffer, //owned by the list
+}
+
+//owned by the caller: release with aira_message_list_free
+#[repr(C)]
+pub struct AiraMessageList {
+    pub messages: *mut AiraMessage,
+    pub len: usize,
+}
+
+//opaque handle to an established PSEC session
+pub struct AiraChannel {
+    channel: SecureChannel,
+    padding_policy: PaddingPolicy,
+}
+
+fn vec_into_raw<T>(input: Vec<T>) -> (*mut T, usize) {
+    let mut boxed = ManuallyDrop::new(input.into_boxed_slice());
+    (boxed.as_mut_ptr(), boxed.len())
+}
+
+unsafe fn vec_from_raw<T>(data: *mut T, len: usize) -> Vec<T> {
+    Box::from_raw(ptr::slice_from_raw_parts_mut(data, len)).into_vec()
+}
+
+//borrowed, valid until the next failing call on the same thread
+#[no_mangle]
+pub extern "C" fn aira_last_error() -> *const c_char {
+    LAST_ERROR.with(|last_error| match last_error.borrow().as_ref() {
+        Some(error) => error.as_ptr(),
+        None => ptr::null(),
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn aira_buffer_free(buffer: AiraBuffer) {
+    let mut buffer = buffer;
+    buffer.free();
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn aira_string_free(string: *mut c_char) {
+    if !string.is_null() {
+        drop(CString::from_raw(string));
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn aira_identity_create(database_folder: *const c_char, name: *const c_char, password: *const u8, password_len: usize) -> *mut Identity {
+    let (database_folder, name) = match (c_to_string(database_folder), c_to_string(name)) {
+        (Some(database_folder), Some(name)) => (database_folder, name),
+        _ => {
+            set_error("NULL argument");
+            return ptr::null_mut();
+        }
+    };
+    let password = c_to_password(password, password_len);
+    match Identity::create_identidy(database_folder, &name, None, password.is_some(), password) {
+        Ok(identity) => Box::into_raw(Box::new(identity)),
+        Err(e) => {
+            set_error(e);
+            ptr::null_mut()
+        }
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn aira_identity_load(database_folder: *const c_char, password: *const u8, password_len: usize) -> *mut Identity {
+    let database_folder = match c_to_string(database_folder) {
+        Some(database_folder) => database_folder,
+        None => {
+            set_error("NULL argument");
+            return ptr::null_mut();
+        }
+    };
+    match Identity::load_identity(database_folder, c_to_password(password, password_len)) {
+        Ok(identity) => Box::into_raw(Box::new(identity)),
+        Err(e) => {
+            set_error(e);
+            ptr::null_mut()
+        }
+    }
+}
+
+//wipes the keys from memory
+#[no_mangle]
+pub unsafe extern "C" fn aira_identity_free(identity: *mut Identity) {
+    if !identity.is_null() {
+        let mut identity = Box::from_raw(identity);
+        identity.zeroize();
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn aira_identity_name(identity: *const Identity) -> *mut c_char {
+    string_to_c(&(*identity).name)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn aira_identity_public_key(identity: *const Identity, public_key: *mut u8) {
+    if !public_key.is_null() {
+        ptr::copy_nonoverlapping((*identity).get_public_key().as_ptr(), public_key, PUBLIC_KEY_LEN);
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn aira_fingerprint(public_key: *const u8) -> *mut c_char {
+    match c_to_array::<PUBLIC_KEY_LEN>(public_key) {
+        Some(public_key) => string_to_c(&crypto::generate_fingerprint(&public_key)),
+        None => {
+            set_error("NULL argument");
+            ptr::null_mut()
+        }
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn aira_contact_add(identity: *const Identity, name: *const c_char, public_key: *const u8, uuid: *mut u8) -> c_int {
+    let (name, public_key) = match (c_to_string(name), c_to_array(public_key)) {
+        (Some(name), Some(public_key)) => (name, public_key),
+        _ => {
+            set_error("NULL argument");
+            return -1;
+        }
+    };
+    match (*identity).add_contact(name, None, public_key) {
+        Ok(contact) => {
+            if !uuid.is_null() {
+                ptr::copy_nonoverlapping(contact.uuid.as_bytes().as_ptr(), uuid, UUID_LEN);
+            }
+            0
+        }
+        Err(e) => {
+            set_error(e);
+            -1
+        }
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn aira_contact_remove(identity: *const Identity, uuid: *const u8) -> c_int {
+    match c_to_uuid(uuid) {
+        Some(uuid) => result_to_int((*identity).remove_contact(&uuid)),
+        None => null_argument(),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn aira_contact_set_verified(identity: *const Identity, uuid: *const u8) -> c_int {
+    match c_to_uuid(uuid) {
+        Some(uuid) => result_to_int((*identity).set_verified(&uuid)),
+        None => null_argument(),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn aira_contacts_load(identity: *const Identity) -> *mut AiraContactList {
+    match (*identity).load_contacts() {
+        Some(contacts) => {
+            let contacts: Vec<AiraContact> = contacts.into_iter().map(|contact| AiraContact {
+                uuid: *contact.uuid.as_bytes(),
+                public_key: contact.public_key,
+                name: string_to_c(&contact.name),
+                verified: contact.verified,
+                seen: contact.seen,
+            }).collect();
+            let (contacts, len) = vec_into_raw(contacts);
+            Box::into_raw(Box::new(AiraContactList { contacts, len }))
+        }
+        None => {
+            set_error("Failed to load contacts");
+            ptr::null_mut()
+        }
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn aira_contact_list_free(list: *mut AiraContactList) {
+    if !list.is_null() {
+        let list = Box::from_raw(list);
+        for contact in vec_from_raw(list.contacts, list.len) {
+            aira_string_free(contact.name);
+        }
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn aira_message_store(identity: *const Identity, contact_uuid: *const u8, outgoing: bool, timestamp: u64, data: *const u8, data_len: usize) -> c_int {
+    match c_to_uuid(contact_uuid) {
+        Some(contact_uuid) => result_to_int((*identity).store_msg(&contact_uuid, Message {
+            outgoing,
+            timestamp,
+            data: c_to_slice(data, data_len).to_vec(),
+        })),
+        None => null_argument(),
+    }
+}
+
+//loads up to count messages, from the most recent minus offset, in chronological order
+#[no_mangle]
+pub unsafe extern "C" fn aira_messages_load(identity: *const Identity, contact_uuid: *const u8, offset: usize, count: usize) -> *mut AiraMessageList {
+    let contact_uuid = match c_to_uuid(contact_uuid) {
+        Some(contact_uuid) => contact_uuid,
+        None => {
+            set_error("NULL argument");
+            return ptr::null_mut();
+        }
+    };
+    let messages = (*identity).load_msgs(&contact_uuid, offset, count).unwrap_or_default();
+    let messages: Vec<AiraMessage> = messages.into_iter().map(|message| AiraMessage {
+        outgoing: message.outgoing,
+        timestamp: message.timestamp,
+        data: AiraBuffer::from_vec(message.data),
+    }).collect();
+    let (messages, len) = vec_into_raw(messages);
+    Box::into_raw(Box::new(AiraMessageList { messages, len }))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn aira_message_list_free(list: *mut AiraMessageList) {
+    if !list.is_null() {
+        let list = Box::from_raw(list);
+        for mut message in vec_from_raw(list.messages, list.len) {
+            message.data.free();
+        }
+    }
+}
+
+//runs the PSEC handshake on a connected TCP socket. The socket stays owned by the caller and must be in blocking mode.
+#[no_mangle]
+pub unsafe extern "C" fn aira_handshake(identity: *const Identity, socket: RawFd) -> *mut AiraChannel {
+    let keypair = (*identity).get_keypair();
+    let result = borrow_socket(socket).try_clone().and_then(|stream| {
+        stream.set_nonblocking(true)?;
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_io().build()?;
+        runtime.block_on(async {
+            let mut stream = tokio::net::TcpStream::from_std(stream)?;
+            let channel = net::handshake(&mut stream, &keypair).await;
+            stream.into_std()?.set_nonblocking(false)?; //the flag is shared with the caller's socket
+            channel
+        })
+    });
+    match result {
+        Ok(channel) => Box::into_raw(Box::new(AiraChannel {
+            channel,
+            padding_policy: (*identity).padding_policy,
+        })),
+        Err(e) => {
+            set_error(e);
+            ptr::null_mut()
+        }
+    }
+}
+
+//the record keys are wiped when the channel is dropped
+#[no_mangle]
+pub unsafe extern "C" fn aira_channel_free(channel: *mut AiraChannel) {
+    if !channel.is_null() {
+        drop(Box::from_raw(channel));
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn aira_channel_peer_public_key(channel: *const AiraChannel, public_key: *mut u8) {
+    if !public_key.is_null() {
+        ptr::copy_nonoverlapping((*channel).channel.peer_public_key.as_ptr(), public_key, PUBLIC_KEY_LEN);
+    }
+}
+
+//pads and encrypts a message into a record ready to be written to the socket
+#[no_mangle]
+pub unsafe extern "C" fn aira_channel_encrypt(channel: *mut AiraChannel, data: *const u8, data_len: usize) -> AiraBuffer {
+    let channel = &mut *channel;
+    AiraBuffer::from_vec(channel.channel.local_cipher.encrypt(&channel.padding_policy.pad(c_to_slice(data, data_len))))
+}
+
+//None for cover traffic
+fn decrypt_record(channel: &mut AiraChannel, raw_len: &[u8; net::MESSAGE_LEN_LEN], cipher_text: &[u8]) -> Result<Option<Vec<u8>>, &'static str> {
+    let padded = channel.channel.peer_cipher.decrypt(raw_len, cipher_text).ok_or("Record decryption failed")?;
+    let message = padding::unpad(&padded).ok_or("Invalid padding")?;
+    Ok(if protocol::is_dummy(message) {
+        None
+    } else {
+        Some(message.to_vec())
+    })
+}
+
+//decrypts a whole record (length included), as returned by aira_channel_encrypt on the peer side. Returns 1 with an empty message for dummy records.
+#[no_mangle]
+pub unsafe extern "C" fn aira_channel_decrypt(channel: *mut AiraChannel, record: *const u8, record_len: usize, message: *mut AiraBuffer) -> c_int {
+    let record = c_to_slice(record, record_len);
+    if record.len() < net::MESSAGE_LEN_LEN {
+        set_error("Record too short");
+        return -1;
+    }
+    match decrypt_record(&mut *channel, record[..net::MESSAGE_LEN_LEN].try_into().unwrap(), &record[net::MESSAGE_LEN_LEN..]) {
+        Ok(Some(plain_text)) => {
+            *message = AiraBuffer::from_vec(plain_text);
+            0
+        }
+        Ok(None) => {
+            *message = AiraBuffer::empty();
+            1
+        }
+        Err(e) => {
+            set_error(e);
+            -1
+        }
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn aira_channel_send(channel: *mut AiraChannel, socket: RawFd, data: *const u8, data_len: usize) -> c_int {
+    let mut record = aira_channel_encrypt(channel, data, data_len);
+    let result = borrow_socket(socket).write_all(c_to_slice(record.data, record.len));
+    record.free();
+    result_to_int(result)
+}
+
+//blocks until a whole record is received, skipping dummy records
+#[no_mangle]
+pub unsafe extern "C" fn aira_channel_recv(channel: *mut AiraChannel, socket: RawFd, message: *mut AiraBuffer) -> c_int {
+    let mut socket = borrow_socket(socket);
+    let mut raw_len = [0; net::MESSAGE_LEN_LEN];
+    let result = loop {
+        let record = socket.read_exact(&mut raw_len).and_then(|_| {
+            let len = u32::from_be_bytes(raw_len) as usize;
+            if len > net::MAX_RECV_SIZE {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "Record too large"));
+            }
+            let mut cipher_text = vec![0; len];
+            socket.read_exact(&mut cipher_text)?;
+            decrypt_record(&mut *channel, &raw_len, &cipher_text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+        });
+        match record {
+            Ok(None) => {}
+            Ok(Some(plain_text)) => break Ok(plain_text),
+            Err(e) => break Err(e),
+        }
+    };
+    match result {
+        Ok(plain_text) => {
+            *message = AiraBuffer::from_vec(plain_text);
+            0
+        }
+        Err(e) => {
+            *message = AiraBuffer::empty();
+            set_error(e);
+            -1
+        }
+    }
+}
@@ -0,0 +1,204 @@
+#!/usr/bin/env python3
+# Smoke test of libaira_ffi.so through ctypes. Build it first with: cargo build -p aira-ffi
+# The library path can be overridden with $AIRA_FFI_LIB.
+import ctypes as c
+import os
+import socket
+import sys
+import tempfile
+import threading
+
+LIB_PATH = os.environ.get("AIRA_FFI_LIB", os.path.join(os.path.dirname(__file__), "..", "..", "target", "debug", "libaira_ffi.so"))
+PUBLIC_KEY_LEN = 32
+UUID_LEN = 16
+
+
+class AiraBuffer(c.Structure):
+    _fields_ = [("data", c.POINTER(c.c_uint8)), ("len", c.c_size_t)]
+
+    def bytes(self):
+        return c.string_at(self.data, self.len) if self.len else b""
+
+
+class AiraContact(c.Structure):
+    _fields_ = [("uuid", c.c_uint8 * UUID_LEN), ("public_key", c.c_uint8 * PUBLIC_KEY_LEN), ("name", c.c_char_p), ("verified", c.c_bool), ("seen", c.c_bool)]
+
+
+class AiraContactList(c.Structure):
+    _fields_ = [("contacts", c.POINTER(AiraContact)), ("len", c.c_size_t)]
+
+
+class AiraMessage(c.Structure):
+    _fields_ = [("outgoing", c.c_bool), ("timestamp", c.c_uint64), ("data", AiraBuffer)]
+
+
+class AiraMessageList(c.Structure):
+    _fields_ = [("messages", c.POINTER(AiraMessage)), ("len", c.c_size_t)]
+
+
+def load_lib():
+    lib = c.CDLL(LIB_PATH)
+    ptr = c.c_void_p
+    signatures = {
+        "aira_last_error": (c.c_char_p, []),
+        "aira_buffer_free": (None, [AiraBuffer]),
+        "aira_string_free": (None, [ptr]),
+        "aira_identity_create": (ptr, [c.c_char_p, c.c_char_p, c.c_char_p, c.c_size_t]),
+        "aira_identity_load": (ptr, [c.c_char_p, c.c_char_p, c.c_size_t]),
+        "aira_identity_free": (None, [ptr]),
+        "aira_identity_name": (ptr, [ptr]),
+        "aira_identity_public_key": (None, [ptr, c.c_char_p]),
+        "aira_fingerprint": (ptr, [c.c_char_p]),
+        "aira_contact_add": (c.c_int, [ptr, c.c_char_p, c.c_char_p, c.c_char_p]),
+        "aira_contact_remove": (c.c_int, [ptr, c.c_char_p]),
+        "aira_contact_set_verified": (c.c_int, [ptr, c.c_char_p]),
+        "aira_contacts_load": (c.POINTER(AiraContactList), [ptr]),
+        "aira_contact_list_free": (None, [c.POINTER(AiraContactList)]),
+        "aira_message_store": (c.c_int, [ptr, c.c_char_p, c.c_bool, c.c_uint64, c.c_char_p, c.c_size_t]),
+        "aira_messages_load": (c.POINTER(AiraMessageList), [ptr, c.c_char_p, c.c_size_t, c.c_size_t]),
+        "aira_message_list_free": (None, [c.POINTER(AiraMessageList)]),
+        "aira_handshake": (ptr, [ptr, c.c_int]),
+        "aira_channel_free": (None, [ptr]),
+        "aira_channel_peer_public_key": (None, [ptr, c.c_char_p]),
+        "aira_channel_encrypt": (AiraBuffer, [ptr, c.c_char_p, c.c_size_t]),
+        "aira_channel_decrypt": (c.c_int, [ptr, c.c_char_p, c.c_size_t, c.POINTER(AiraBuffer)]),
+        "aira_channel_send": (c.c_int, [ptr, c.c_int, c.c_char_p, c.c_size_t]),
+        "aira_channel_recv": (c.c_int, [ptr, c.c_int, c.POINTER(AiraBuffer)]),
+    }
+    for name, (restype, argtypes) in signatures.items():
+        function = getattr(lib, name)
+        function.restype = restype
+        function.argtypes = argtypes
+    return lib
+
+
+lib = load_lib()
+
+
+def check(condition, what):
+    if not condition:
+        error = lib.aira_last_error()
+        raise AssertionError("{}: {}".format(what, error.decode() if error else "no error"))
+
+
+def take_string(ptr):
+    value = c.string_at(ptr).decode()
+    lib.aira_string_free(ptr)
+    return value
+
+
+def public_key(identity):
+    key = c.create_string_buffer(PUBLIC_KEY_LEN)
+    lib.aira_identity_public_key(identity, key)
+    return key.raw
+
+
+def test_identity_contacts_messages(folder):
+    password = b"password"
+    identity = lib.aira_identity_create(folder.encode(), b"alice", password, len(password))
+    check(identity, "create")
+    alice_key = public_key(identity)
+    lib.aira_identity_free(identity)
+
+    check(not lib.aira_identity_load(folder.encode(), b"wrong", 5), "wrong password rejected")
+    check(lib.aira_last_error(), "error message set")
+    identity = lib.aira_identity_load(folder.encode(), password, len(password))
+    check(identity, "load")
+    check(take_string(lib.aira_identity_name(identity)) == "alice", "name")
+    check(public_key(identity) == alice_key, "public key")
+    check(len(take_string(lib.aira_fingerprint(alice_key))) == 32, "fingerprint")
+
+    bob_key = os.urandom(PUBLIC_KEY_LEN)
+    uuid = c.create_string_buffer(UUID_LEN)
+    check(lib.aira_contact_add(identity, b"bob", bob_key, uuid) == 0, "add contact")
+    check(lib.aira_contact_set_verified(identity, uuid.raw) == 0, "set verified")
+    contacts = lib.aira_contacts_load(identity)
+    check(contacts, "load contacts")
+    check(contacts.contents.len == 1, "one contact")
+    contact = contacts.contents.contacts[0]
+    check(contact.name == b"bob" and bytes(contact.public_key) == bob_key and contact.verified, "contact fields")
+    check(bytes(contact.uuid) == uuid.raw, "contact uuid")
+    lib.aira_contact_list_free(contacts)
+
+    for i, text in enumerate([b"\x00hello", b"\x00how are you?", b"\x00bye"]):
+        check(lib.aira_message_store(identity, uuid.raw, i % 2 == 0, 1000+i, text, len(text)) == 0, "store message")
+    messages = lib.aira_messages_load(identity, uuid.raw, 0, 2)
+    check(messages.contents.len == 2, "two most recent messages")
+    check(messages.contents.messages[0].data.bytes() == b"\x00how are you?", "message order")
+    check(messages.contents.messages[1].outgoing and messages.contents.messages[1].timestamp == 1002, "message fields")
+    lib.aira_message_list_free(messages)
+
+    #NULL keys and UUIDs are errors, not crashes
+    check(lib.aira_contact_add(identity, b"eve", None, None) == -1, "NULL public key rejected")
+    check(lib.aira_last_error() == b"NULL argument", "NULL argument error")
+    check(lib.aira_contact_set_verified(identity, None) == -1, "NULL uuid rejected")
+    check(lib.aira_message_store(identity, None, True, 0, b"\x00", 1) == -1, "NULL contact uuid rejected")
+    check(not lib.aira_messages_load(identity, None, 0, 1), "NULL contact uuid rejected on load")
+    check(not lib.aira_fingerprint(None), "NULL fingerprint key rejected")
+    check(lib.aira_contact_remove(identity, None) == -1, "NULL uuid rejected on removal")
+
+    check(lib.aira_contact_remove(identity, uuid.raw) == 0, "remove contact")
+    contacts = lib.aira_contacts_load(identity)
+    check(contacts.contents.len == 0, "no contact left")
+    lib.aira_contact_list_free(contacts)
+    lib.aira_identity_free(identity)
+
+
+def test_handshake_and_records(folder):
+    identities = []
+    for name in [b"alice", b"bob"]:
+        path = os.path.join(folder, name.decode())
+        os.mkdir(path)
+        identity = lib.aira_identity_create(path.encode(), name, None, 0)
+        check(identity, "create")
+        identities.append(identity)
+
+    listener = socket.create_server(("127.0.0.1", 0))
+    client = socket.create_connection(listener.getsockname())
+    server, _ = listener.accept()
+    channels = [None, None]
+
+    def handshake(i, sock):
+        channels[i] = lib.aira_handshake(identities[i], sock.fileno())
+
+    threads = [threading.Thread(target=handshake, args=(0, server)), threading.Thread(target=handshake, args=(1, client))]
+    for thread in threads:
+        thread.start()
+    for thread in threads:
+        thread.join()
+    check(channels[0] and channels[1], "handshake")
+    peer_key = c.create_string_buffer(PUBLIC_KEY_LEN)
+    lib.aira_channel_peer_public_key(channels[0], peer_key)
+    check(peer_key.raw == public_key(identities[1]), "peer public key")
+
+    msg = b"\x00hello over PSEC"
+    check(lib.aira_channel_send(channels[1], client.fileno(), msg, len(msg)) == 0, "send")
+    received = AiraBuffer()
+    check(lib.aira_channel_recv(channels[0], server.fileno(), c.byref(received)) == 0, "recv")
+    check(received.bytes() == msg, "received message")
+    lib.aira_buffer_free(received)
+
+    record = lib.aira_channel_encrypt(channels[0], msg, len(msg))
+    record_bytes = record.bytes()
+    lib.aira_buffer_free(record)
+    check(len(record_bytes) >= 1000, "records are padded")
+    tampered = record_bytes[:-1] + bytes([record_bytes[-1] ^ 1])
+    check(lib.aira_channel_decrypt(channels[1], tampered, len(tampered), c.byref(received)) == -1, "tampered record rejected")
+
+    for sock in [client, server, listener]:
+        sock.close()
+    for channel in channels:
+        lib.aira_channel_free(channel)
+    for identity in identities:
+        lib.aira_identity_free(identity)
+
+
+def main():
+    for test in [test_identity_contacts_messages, test_handshake_and_records]:
+        with tempfile.TemporaryDirectory() as folder:
+            test(folder)
+        print("{} ... ok".format(test.__name__))
+
+
+if __name__ == "__main__":
+    sys.exit(main())
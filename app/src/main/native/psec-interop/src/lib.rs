@@ -0,0 +1 @@
+//test-only package, see tests/async_psec.rs
@@ -0,0 +1,66 @@
+//both handshake directions against async-psec, with and without padding on each side
+use std::{net::{IpAddr, Ipv4Addr}, sync::Arc};
+use async_psec::{Identity, PsecReader, PsecWriter, Session};
+use ed25519_dalek::Keypair;
+use rand::rngs::OsRng;
+use tokio::{net::{TcpListener, TcpStream}, sync::mpsc::{self, UnboundedSender}};
+use aira_core::{net::{SessionEvents, SessionManager}, padding::PaddingPolicy};
+
+struct Forwarder(UnboundedSender<Vec<u8>>);
+
+impl SessionEvents for Forwarder {
+    fn on_session_opened(&self, _peer_public_key: &[u8; 32], _ip: IpAddr, _outgoing: bool) {}
+    fn on_message(&self, _peer_public_key: &[u8; 32], message: Vec<u8>) {
+        self.0.send(message).unwrap();
+    }
+    fn on_session_closed(&self, _peer_public_key: &[u8; 32]) {}
+}
+
+async fn exchange(manager: &SessionManager, messages: &mut mpsc::UnboundedReceiver<Vec<u8>>, session: &mut Session) {
+    assert_eq!(session.peer_public_key, Some(manager.public_key()));
+    for use_padding in [true, false] {
+        session.encrypt_and_send(b"\x00hello from async-psec", use_padding).await.unwrap();
+        assert_eq!(messages.recv().await.unwrap(), b"\x00hello from async-psec");
+    }
+    //registered once its first message arrived
+    let sessions = manager.sessions();
+    assert_eq!(sessions.len(), 1);
+    let peer_public_key = sessions[0].peer_public_key;
+    for padding_policy in [PaddingPolicy::PowerOfTwo, PaddingPolicy::None] {
+        manager.set_padding_policy(padding_policy);
+        assert!(manager.send(&peer_public_key, b"\x00hello from aira-core"));
+        assert_eq!(session.receive_and_decrypt().await.unwrap(), b"\x00hello from aira-core");
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn aira_core_accepts_async_psec() {
+    let (sender, mut messages) = mpsc::unbounded_channel();
+    let manager = SessionManager::new(Keypair::generate(&mut OsRng), Arc::new(Forwarder(sender)));
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(manager.clone().serve(listener));
+
+    let identity = Identity::generate(&mut OsRng);
+    let mut session = Session::from(TcpStream::connect(addr).await.unwrap());
+    session.do_handshake(&identity).await.unwrap();
+    exchange(&manager, &mut messages, &mut session).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn async_psec_accepts_aira_core() {
+    let (sender, mut messages) = mpsc::unbounded_channel();
+    let manager = SessionManager::new(Keypair::generate(&mut OsRng), Arc::new(Forwarder(sender)));
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let identity = Identity::generate(&mut OsRng);
+    let server = tokio::spawn(async move {
+        let mut session = Session::from(listener.accept().await.unwrap().0);
+        session.do_handshake(&identity).await.unwrap();
+        session
+    });
+    manager.connect(addr).await.unwrap();
+    let mut session = server.await.unwrap();
+    exchange(&manager, &mut messages, &mut session).await;
+}
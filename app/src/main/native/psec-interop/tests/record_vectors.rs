@@ -0,0 +1,125 @@
+//records core/tests/vectors/async_psec_session.txt from live async-psec sessions:
+//cargo test --manifest-path psec-interop/Cargo.toml -- --ignored
+//Our side uses fixed secrets so the transcript can be replayed offline, async-psec's side is whatever it generated.
+use std::{convert::TryInto, fmt::Write};
+use aes_gcm::{Aes128Gcm, Key, NewAead, Nonce, aead::Aead};
+use async_psec::{Identity, PsecReader, PsecWriter, Session};
+use ed25519_dalek::{Keypair, PublicKey, SecretKey, Signer};
+use sha2::{Digest, Sha384};
+use tokio::{io::{AsyncReadExt, AsyncWriteExt}, net::{TcpListener, TcpStream}};
+use x25519_dalek::{PublicKey as EphemeralPublicKey, StaticSecret};
+use aira_core::{crypto::{self, ApplicationKeys, HandshakeKeys}, net::{self, RecordCipher}, padding::PaddingPolicy};
+
+const RANDOM_LEN: usize = 64;
+const AUTH_LEN: usize = RANDOM_LEN+32+64+16;
+const ASYNC_PSEC_MESSAGE: &[u8] = b"\x00hello from async-psec";
+const AIRA_CORE_MESSAGE: &[u8] = b"\x00hello from aira-core";
+
+fn keypair(seed: u8) -> Keypair {
+    let secret = SecretKey::from_bytes(&[seed; 32]).unwrap();
+    Keypair { public: PublicKey::from(&secret), secret }
+}
+
+async fn read(stream: &mut TcpStream, len: usize) -> Vec<u8> {
+    let mut buffer = vec![0; len];
+    stream.read_exact(&mut buffer).await.unwrap();
+    buffer
+}
+
+async fn read_record(stream: &mut TcpStream) -> Vec<u8> {
+    let raw_len = read(stream, 4).await;
+    let cipher_text = read(stream, u32::from_be_bytes(raw_len[..].try_into().unwrap()) as usize).await;
+    [raw_len, cipher_text].concat()
+}
+
+//one session against async-psec, returned as a vector block
+async fn record_session(ephemeral_seed: u8) -> (bool, String) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let async_psec_identity: Identity = keypair(2);
+    let async_psec = tokio::spawn(async move {
+        let mut session = Session::from(listener.accept().await.unwrap().0);
+        session.do_handshake(&async_psec_identity).await.unwrap();
+        session.encrypt_and_send(ASYNC_PSEC_MESSAGE, true).await.unwrap();
+        session.encrypt_and_send(ASYNC_PSEC_MESSAGE, false).await.unwrap();
+        assert_eq!(session.receive_and_decrypt().await.unwrap(), AIRA_CORE_MESSAGE);
+    });
+
+    let identity = keypair(1);
+    let ephemeral_secret = StaticSecret::from([ephemeral_seed; 32]);
+    let ephemeral_public_key = EphemeralPublicKey::from(&ephemeral_secret);
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+
+    let hello = [&[0x11; RANDOM_LEN][..], ephemeral_public_key.as_bytes()].concat();
+    stream.write_all(&hello).await.unwrap();
+    let peer_hello = read(&mut stream, RANDOM_LEN+32).await;
+    let peer_ephemeral_public_key: [u8; 32] = peer_hello[RANDOM_LEN..].try_into().unwrap();
+    let shared_secret = ephemeral_secret.diffie_hellman(&EphemeralPublicKey::from(peer_ephemeral_public_key));
+    let i_am_bob = hello < peer_hello;
+    let transcript_hash = |sent: &[&[u8]], received: &[&[u8]]| -> [u8; 48] {
+        let (first, second) = if i_am_bob { (sent, received) } else { (received, sent) };
+        Sha384::new().chain_update(first.concat()).chain_update(second.concat()).finalize().as_slice().try_into().unwrap()
+    };
+    let handshake_keys = HandshakeKeys::derive_keys(shared_secret.to_bytes(), transcript_hash(&[&hello], &[&peer_hello]), i_am_bob);
+
+    let auth = [&[0x22; RANDOM_LEN][..], identity.public.as_bytes(), &identity.sign(ephemeral_public_key.as_bytes()).to_bytes()].concat();
+    let encrypted_auth = Aes128Gcm::new(Key::from_slice(&handshake_keys.local_key)).encrypt(Nonce::from_slice(&handshake_keys.local_iv), auth.as_slice()).unwrap();
+    stream.write_all(&encrypted_auth).await.unwrap();
+    let peer_encrypted_auth = read(&mut stream, AUTH_LEN).await;
+
+    let handshake_hash = transcript_hash(&[&hello, &encrypted_auth], &[&peer_hello, &peer_encrypted_auth]);
+    let finished = crypto::compute_handshake_finished(handshake_keys.local_handshake_traffic_secret, handshake_hash);
+    stream.write_all(&finished).await.unwrap();
+    let peer_finished = read(&mut stream, 48).await;
+    assert!(crypto::verify_handshake_finished(peer_finished[..].try_into().unwrap(), handshake_keys.peer_handshake_traffic_secret, handshake_hash));
+
+    let application_keys = ApplicationKeys::derive_keys(handshake_keys.handshake_secret, handshake_hash, i_am_bob);
+    let padded_record = read_record(&mut stream).await;
+    let record = read_record(&mut stream).await;
+    let mut peer_cipher = RecordCipher::new(&application_keys.peer_key, application_keys.peer_iv);
+    for record in [&padded_record, &record] {
+        assert_eq!(net::read_record(&mut &record[..], &mut peer_cipher).await.unwrap().unwrap(), ASYNC_PSEC_MESSAGE);
+    }
+    let local_record = RecordCipher::new(&application_keys.local_key, application_keys.local_iv).encrypt(&PaddingPolicy::None.pad(AIRA_CORE_MESSAGE));
+    stream.write_all(&local_record).await.unwrap();
+    async_psec.await.unwrap();
+
+    let mut block = String::new();
+    for (name, value) in [
+        ("ephemeral_secret", &[ephemeral_seed; 32][..]),
+        ("public_key", identity.public.as_bytes()),
+        ("hello", &hello),
+        ("auth", &encrypted_auth),
+        ("finished", &finished),
+        ("message", AIRA_CORE_MESSAGE),
+        ("record", &local_record),
+        ("async_psec_public_key", keypair(2).public.as_bytes()),
+        ("async_psec_hello", &peer_hello),
+        ("async_psec_auth", &peer_encrypted_auth),
+        ("async_psec_finished", &peer_finished),
+        ("async_psec_message", ASYNC_PSEC_MESSAGE),
+        ("async_psec_padded_record", &padded_record),
+        ("async_psec_record", &record),
+    ] {
+        writeln!(block, "{} = {}", name, hex::encode(value)).unwrap();
+    }
+    (i_am_bob, block)
+}
+
+//async-psec's hello is random so the roles vary between sessions: keep one of each
+#[tokio::test(flavor = "multi_thread")]
+#[ignore]
+async fn record_session_vectors() {
+    let mut blocks = [None, None];
+    let mut ephemeral_seed = 3;
+    while blocks.iter().any(Option::is_none) {
+        let (i_am_bob, block) = record_session(ephemeral_seed).await;
+        blocks[i_am_bob as usize].get_or_insert(block);
+        ephemeral_seed += 1;
+    }
+    let header = "# Sessions recorded against async-psec 0.4.0 by psec-interop/tests/record_vectors.rs. Do not edit.\n\
+        # Unprefixed entries are the recorder's side, it is alice in the first session and bob in the second.\n\
+        # async-psec sends its message padded then unpadded, the recorder answers with an unpadded record.\n";
+    let vectors = format!("{}\n{}", header, blocks.iter().map(|block| block.as_deref().unwrap()).collect::<Vec<_>>().join("\n"));
+    std::fs::write(concat!(env!("CARGO_MANIFEST_DIR"), "/../core/tests/vectors/async_psec_session.txt"), vectors).unwrap();
+}